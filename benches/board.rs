@@ -0,0 +1,133 @@
+// Benchmarks for the bitboard, the playout engine and the solver
+
+use connect4::board::{Board, MoveResult, HEIGHT, WIDTH};
+use connect4::mcts::{Mcts, Rollouts};
+use connect4::misere::Objective;
+use connect4::play::{self, Policy};
+use connect4::solver::Solver;
+use connect4::testset;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rand::{rngs::StdRng, SeedableRng};
+
+// Positions one move away from a win in each direction: (setup, winning column).
+const WINS: [(&str, &[usize], usize); 4] = [
+    ("vertical", &[0, 1, 0, 1, 0, 1], 0),
+    ("horizontal", &[0, 0, 1, 1, 2, 2], 3),
+    ("upper_diagonal", &[0, 1, 1, 2, 2, 3, 2, 3, 3, 6], 3),
+    ("lower_diagonal", &[6, 5, 5, 4, 4, 3, 4, 3, 3, 0], 3),
+];
+
+fn make_move(c: &mut Criterion) {
+    let mut group = c.benchmark_group("make_move");
+    group.throughput(Throughput::Elements((WIDTH * HEIGHT) as u64));
+    group.bench_function("fill_board", |b| {
+        b.iter(|| {
+            let mut board = Board::new();
+            for col in 0..WIDTH {
+                for _row in 0..HEIGHT {
+                    black_box(board.make_move(col));
+                }
+            }
+            board
+        })
+    });
+    group.finish();
+}
+
+fn win_detection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("win_detection");
+    for (name, setup, col) in WINS {
+        let mut board = Board::new();
+        assert_eq!(board.make_moves(setup), MoveResult::None);
+        let mut check = board;
+        assert_eq!(check.make_move(col), MoveResult::WinX, "{name}");
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut board = black_box(board);
+                board.make_move(black_box(col))
+            })
+        });
+    }
+    group.finish();
+}
+
+fn random_playouts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("random_playout");
+    group.throughput(Throughput::Elements(1));
+    let mut rng = StdRng::seed_from_u64(0);
+    group.bench_function("empty_board", |b| {
+        b.iter(|| {
+            let mut board = Board::new();
+            play::playout(&mut board, Policy::Uniform, Objective::Standard, &mut rng)
+        })
+    });
+    group.finish();
+}
+
+// Positions searched by the tree search: the start, the middle game and close
+// to the end.
+const MCTS_POSITIONS: [(&str, &str); 3] = [
+    ("empty", ""),
+    ("middle", "3243342"),
+    ("late", "242512646300052352144344"),
+];
+
+// Simulations in each search, each evaluated with one random playout.
+const MCTS_SIMULATIONS: usize = 1000;
+
+fn mcts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mcts");
+    group.throughput(Throughput::Elements(MCTS_SIMULATIONS as u64));
+    for (name, moves) in MCTS_POSITIONS {
+        let board = Board::from_move_string(moves).unwrap();
+        let rollouts = Rollouts::with_rng(1, StdRng::seed_from_u64(0));
+        let mut engine = Mcts::with_simulations(rollouts, MCTS_SIMULATIONS);
+        group.bench_function(name, |b| b.iter(|| engine.search(black_box(&board))));
+    }
+    group.finish();
+}
+
+fn solver(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solver");
+    for name in ["end", "middle"] {
+        let path = format!("{}/testsets/{name}.txt", env!("CARGO_MANIFEST_DIR"));
+        let text = std::fs::read_to_string(path).unwrap();
+        let boards: Vec<Board> = testset::parse(&text)
+            .unwrap()
+            .iter()
+            .take(20)
+            .map(|case| Board::from_move_string(&case.moves).unwrap())
+            .collect();
+
+        // The search is deterministic once the move ordering is reset for each
+        // board, so count the nodes once to report nodes per second.
+        let mut solver = Solver::new();
+        let mut nodes = 0;
+        for board in &boards {
+            solver.reset();
+            solver.solve(board);
+            nodes += solver.get_node_count();
+        }
+        group.throughput(Throughput::Elements(nodes));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for board in &boards {
+                    solver.reset();
+                    black_box(solver.solve(board));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    make_move,
+    win_detection,
+    random_playouts,
+    mcts,
+    solver
+);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+// Serves games and engine analysis over HTTP on localhost
+
+use std::env;
+use std::process;
+
+use connect4::server::Server;
+
+const DEFAULT_PORT: u16 = 8080;
+
+fn main() {
+    let port = match env::args().nth(1).map(|port| port.parse::<u16>()) {
+        None => DEFAULT_PORT,
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            eprintln!("Usage: connect4-server [port]");
+            process::exit(1);
+        }
+    };
+    let server = match Server::bind(&format!("127.0.0.1:{port}")) {
+        Ok(server) => server,
+        Err(message) => {
+            eprintln!("{message}");
+            process::exit(1);
+        }
+    };
+    println!("Listening on http://{}", server.local_addr());
+    server.run();
+}
@@ -1,11 +1,44 @@
 // Defines the connect 4 board
 
 use std::convert::From;
+use std::hash::{Hash, Hasher};
 
 pub const WIDTH: usize = 7;
 pub const HEIGHT: usize = 6;
 const PADDED_HEIGHT: usize = 8; // align to a byte boundary
 
+// Cells of the board, leaving out the padding above each column.
+const BOARD_MASK: u64 = board_mask();
+
+const fn board_mask() -> u64 {
+    let mut mask = 0;
+    let mut col = 0;
+    while col < WIDTH {
+        mask |= ((1 << HEIGHT) - 1) << (col * PADDED_HEIGHT);
+        col += 1;
+    }
+    mask
+}
+
+// Random numbers for the Zobrist hash, one for each player and cell.
+const ZOBRIST: [[u64; WIDTH * PADDED_HEIGHT]; 2] = zobrist_table();
+
+// Fills the table with SplitMix64, so hashes are the same on every run.
+const fn zobrist_table() -> [[u64; WIDTH * PADDED_HEIGHT]; 2] {
+    let mut table = [[0; WIDTH * PADDED_HEIGHT]; 2];
+    let mut state: u64 = 0x0123_4567_89ab_cdef;
+    let mut i = 0;
+    while i < 2 * WIDTH * PADDED_HEIGHT {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i % 2][i / 2] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Cell {
     Empty,
@@ -14,7 +47,7 @@ pub enum Cell {
 }
 
 impl Cell {
-    pub fn to_char(&self) -> char {
+    pub fn to_char(self) -> char {
         match self {
             Cell::Empty => '.',
             Cell::X => 'X',
@@ -22,7 +55,7 @@ impl Cell {
         }
     }
 
-    pub fn switch(&self) -> Cell {
+    pub fn switch(self) -> Cell {
         match self {
             Cell::Empty => Cell::Empty,
             Cell::X => Cell::O,
@@ -50,7 +83,7 @@ impl From<MoveResult> for Cell {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Board {
     // Pieces are stored in columns. The player to move next is '1'
     bitmap: u64,
@@ -60,6 +93,26 @@ pub struct Board {
 
     // Total number of moves made on this board.
     moves: usize,
+
+    // Whether the turn has been passed an odd number of times, so that O is
+    // to move when the number of moves is even.
+    passed: bool,
+
+    // Zobrist hash of the pieces, updated as moves are made and taken back.
+    hash: u64,
+}
+
+// Boards are equal exactly when their keys are, so only the key is hashed.
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Board {
@@ -68,9 +121,29 @@ impl Board {
             bitmap: 0,
             mask: 0,
             moves: 0,
+            passed: false,
+            hash: 0,
         }
     }
 
+    /// Returns a key that identifies the position, with different positions
+    /// always having different keys. A column of height h adds up to between
+    /// 2^h - 1 and 2^(h+1) - 2, so columns of different heights never collide
+    /// and never carry into the next column. That leaves the top bit of each
+    /// column free, and the first holds whether the turn was passed, as a
+    /// passed position has the colours of another one with the same discs.
+    /// The key fits in 56 bits.
+    pub fn key(&self) -> u64 {
+        (self.bitmap + self.mask) | (self.passed as u64) << (PADDED_HEIGHT - 1)
+    }
+
+    /// Returns a 64-bit Zobrist hash of the position for hash tables. Unlike
+    /// the key, different positions can share a hash, but the bits are
+    /// evenly spread, so any subset of them can index a table.
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
     pub fn get_move_count(&self) -> usize {
         self.moves
     }
@@ -81,34 +154,44 @@ impl Board {
         if bit & self.mask == 0 {
             Cell::Empty
         } else if (self.bitmap & bit) == 0 {
-            if self.moves & 1 == 0 {
-                Cell::X
-            } else {
-                Cell::O
-            }
+            self.get_current_player()
         } else {
-            if self.moves & 1 != 0 {
-                Cell::X
-            } else {
-                Cell::O
-            }
+            self.get_current_player().switch()
         }
     }
 
     pub fn is_valid_move(&self, col: usize) -> bool {
-        col < WIDTH && self.mask & (1 << col * PADDED_HEIGHT + HEIGHT - 1) == 0
+        col < WIDTH && self.mask & (1 << (col * PADDED_HEIGHT + HEIGHT - 1)) == 0
+    }
+
+    /// Returns the number of pieces in the given column.
+    pub fn get_height(&self, col: usize) -> usize {
+        ((self.mask >> (col * PADDED_HEIGHT)) & ((1 << HEIGHT) - 1)).count_ones() as usize
     }
 
     pub fn get_current_player(&self) -> Cell {
-        match self.moves & 1 {
+        match self.turn() {
             0 => Cell::X,
             1 => Cell::O,
             _ => unreachable!(),
         }
     }
 
-    #[cfg(test)]
-    pub fn make_moves(&mut self, moves: &Vec<usize>) -> MoveResult {
+    /// Builds a board from a string of column digits, such as "3324".
+    /// Returns `None` if a move is illegal or ends the game.
+    pub fn from_move_string(moves: &str) -> Option<Board> {
+        let mut board = Board::new();
+        for c in moves.chars() {
+            let col = c.to_digit(10)? as usize;
+            if board.make_move(col) != MoveResult::None {
+                return None;
+            }
+        }
+        Some(board)
+    }
+
+    /// Plays a sequence of columns, returning the result of the last move.
+    pub fn make_moves(&mut self, moves: &[usize]) -> MoveResult {
         let mut last_result = MoveResult::None;
         for m in moves {
             last_result = self.make_move(*m);
@@ -123,7 +206,8 @@ impl Board {
             return MoveResult::Illegal;
         }
 
-        let bit = ((self.mask + (1 << col * PADDED_HEIGHT)) | self.mask) ^ self.mask;
+        let bit = ((self.mask + (1 << (col * PADDED_HEIGHT))) | self.mask) ^ self.mask;
+        self.hash ^= ZOBRIST[self.turn()][bit.trailing_zeros() as usize];
         self.mask |= bit;
         self.bitmap ^= self.mask; // Flip all Cells
         self.moves += 1;
@@ -141,6 +225,35 @@ impl Board {
         }
     }
 
+    /// Takes back the last move, which must have been played in the given column.
+    pub fn undo(&mut self, col: usize) {
+        let column = self.mask & (((1 << HEIGHT) - 1) << (col * PADDED_HEIGHT));
+        debug_assert!(column != 0, "undo in empty column {col}");
+        let bit = 1 << (63 - column.leading_zeros());
+        self.bitmap ^= self.mask; // Flip all Cells back
+        self.mask ^= bit;
+        self.moves -= 1;
+        self.hash ^= ZOBRIST[self.turn()][bit.trailing_zeros() as usize];
+    }
+
+    /// Gives the turn to the other player without a move, as when a player
+    /// with a handicap moves twice. Passing again takes it back.
+    pub fn pass(&mut self) {
+        self.bitmap ^= self.mask;
+        self.passed = !self.passed;
+    }
+
+    // 0 when X is to move and 1 when O is.
+    fn turn(&self) -> usize {
+        (self.moves & 1) ^ self.passed as usize
+    }
+
+    /// Returns the number of empty cells that would complete four in a row for
+    /// the player who made the last move.
+    pub fn count_threats(&self) -> u32 {
+        winning_cells(self.bitmap, self.mask).count_ones()
+    }
+
     fn check_win(&self, bit: u64, col: usize) -> bool {
         self.check_vertical_win(bit, col)
             || self.check_horizontal_win(bit, col)
@@ -162,6 +275,7 @@ impl Board {
         false
     }
 
+    #[allow(clippy::identity_op, clippy::erasing_op)]
     fn check_horizontal_win(&self, bit: u64, col: usize) -> bool {
         const LEFT_MASK: u64 = 1 << (0 * PADDED_HEIGHT)
             | 1 << (1 * PADDED_HEIGHT)
@@ -183,6 +297,7 @@ impl Board {
         false
     }
 
+    #[allow(clippy::identity_op, clippy::erasing_op)]
     fn check_upper_diagonal_win(&self, bit: u64, col: usize) -> bool {
         const MASK: u64 = 1 << (0 * PADDED_HEIGHT + 0)
             | 1 << (1 * PADDED_HEIGHT + 1)
@@ -209,6 +324,7 @@ impl Board {
         false
     }
 
+    #[allow(clippy::identity_op, clippy::erasing_op)]
     fn check_lower_diagonal_win(&self, bit: u64, col: usize) -> bool {
         const MASK: u64 = 1 << (0 * PADDED_HEIGHT - 0)
             | 1 << (1 * PADDED_HEIGHT - 1)
@@ -255,9 +371,28 @@ impl Board {
     }
 }
 
+// Returns the empty cells that would complete four in a row with the pieces
+// in `position`. Shifting by 1 moves along a column, by PADDED_HEIGHT along a
+// row, and by PADDED_HEIGHT - 1 and PADDED_HEIGHT + 1 along the diagonals.
+fn winning_cells(position: u64, mask: u64) -> u64 {
+    // Vertical: three pieces below the cell.
+    let mut cells = (position << 1) & (position << 2) & (position << 3);
+
+    for shift in [PADDED_HEIGHT, PADDED_HEIGHT - 1, PADDED_HEIGHT + 1] {
+        // Pairs of pieces one and two steps away in each direction.
+        let pairs = (position << shift) & (position << (2 * shift));
+        cells |= pairs & (position << (3 * shift));
+        cells |= pairs & (position >> shift);
+        let pairs = (position >> shift) & (position >> (2 * shift));
+        cells |= pairs & (position << shift);
+        cells |= pairs & (position >> (3 * shift));
+    }
+    cells & (BOARD_MASK ^ mask)
+}
+
 #[cfg(test)]
 mod board_tests {
-    use std::vec;
+    use std::collections::HashMap;
 
     use crate::board::MoveResult;
 
@@ -268,14 +403,14 @@ mod board_tests {
         let mut board = Board::new();
 
         // fill first two columns up to 3 rows high, each with the same Cell.
-        assert_eq!(board.make_moves(&vec![0, 1, 0, 1, 0, 1]), MoveResult::None);
+        assert_eq!(board.make_moves(&[0, 1, 0, 1, 0, 1]), MoveResult::None);
         assert_eq!(board.make_move(0), MoveResult::WinX);
 
         // Fill last two columns, but make O win in the upper-right corner.
         board = Board::new();
 
         assert_eq!(
-            board.make_moves(&vec![6, 5, 6, 5, 5, 6, 5, 6, 5, 6, 4]),
+            board.make_moves(&[6, 5, 6, 5, 5, 6, 5, 6, 5, 6, 4]),
             MoveResult::None
         );
         assert_eq!(board.make_move(6), MoveResult::WinO);
@@ -290,7 +425,7 @@ mod board_tests {
                 let result = board.make_move(col);
                 if col < 3 {
                     assert_eq!(result, MoveResult::None);
-                } else if (board.get_move_count() % 2) == 0 {
+                } else if board.get_move_count().is_multiple_of(2) {
                     assert_eq!(result, MoveResult::WinO);
                 } else {
                     assert_eq!(result, MoveResult::WinX);
@@ -309,7 +444,7 @@ mod board_tests {
                     let result = board.make_move(col);
                     if row < 3 {
                         assert_eq!(result, MoveResult::None);
-                    } else if (board.get_move_count() % 2) == 0 {
+                    } else if board.get_move_count().is_multiple_of(2) {
                         assert_eq!(result, MoveResult::WinO);
                     } else {
                         assert_eq!(result, MoveResult::WinX);
@@ -320,7 +455,7 @@ mod board_tests {
                     let result = board.make_move(col);
                     if row < 3 {
                         assert_eq!(result, MoveResult::None);
-                    } else if (board.get_move_count() % 2) == 0 {
+                    } else if board.get_move_count().is_multiple_of(2) {
                         assert_eq!(result, MoveResult::WinO);
                     } else {
                         assert_eq!(result, MoveResult::WinX);
@@ -350,8 +485,104 @@ mod board_tests {
         for row in 0..HEIGHT {
             assert!(board.make_move(0) == MoveResult::None);
             assert!(board.get(0, row) == cell);
+            assert_eq!(board.get_height(0), row + 1);
             cell = cell.switch();
         }
         assert!(board.make_move(0) == MoveResult::Illegal);
     }
+
+    #[test]
+    fn from_move_string() {
+        let board = Board::from_move_string("3324").unwrap();
+        assert_eq!(board.get_move_count(), 4);
+        assert_eq!(board.get(3, 0), Cell::X);
+        assert_eq!(board.get(3, 1), Cell::O);
+        assert_eq!(board.get(2, 0), Cell::X);
+        assert_eq!(board.get(4, 0), Cell::O);
+
+        assert!(Board::from_move_string("").is_some());
+        assert!(Board::from_move_string("37").is_none());
+        assert!(Board::from_move_string("000000").is_some());
+        assert!(Board::from_move_string("0000000").is_none());
+        assert!(Board::from_move_string("0101010").is_none());
+    }
+
+    fn cells(board: &Board) -> Vec<Cell> {
+        (0..WIDTH)
+            .flat_map(|col| (0..HEIGHT).map(move |row| board.get(col, row)))
+            .collect()
+    }
+
+    // Visits every position reachable in the given number of moves.
+    fn visit(board: &Board, depth: usize, keys: &mut HashMap<u64, Board>) {
+        for col in 0..WIDTH {
+            let mut next = *board;
+            if next.make_move(col) == MoveResult::Illegal {
+                continue;
+            }
+            // Positions reached by different move orders have the same key
+            // and hash, and different positions have different keys.
+            let other = keys.entry(next.key()).or_insert(next);
+            assert_eq!(cells(other), cells(&next));
+            assert_eq!(*other, next);
+
+            let mut undone = next;
+            undone.undo(col);
+            assert_eq!(undone, *board);
+            if depth > 1 {
+                visit(&next, depth - 1, keys);
+            }
+        }
+    }
+
+    #[test]
+    fn keys_and_hashes() {
+        let mut keys = HashMap::new();
+        visit(&Board::new(), 6, &mut keys);
+        let boards: HashMap<Board, u64> = keys.values().map(|b| (*b, b.get_hash())).collect();
+        assert_eq!(boards.len(), keys.len());
+
+        let board = Board::from_move_string("0123").unwrap();
+        assert_eq!(board, Board::from_move_string("2103").unwrap());
+        assert_ne!(board, Board::from_move_string("1032").unwrap());
+        assert_eq!(
+            board.get_hash(),
+            Board::from_move_string("2103").unwrap().get_hash()
+        );
+        assert_ne!(board.get_hash(), Board::new().get_hash());
+
+        // After passing, the discs of the player to move are X's disc in
+        // column 0, as before "01" with X to move.
+        let mut passed = Board::from_move_string("10").unwrap();
+        passed.pass();
+        let board = Board::from_move_string("01").unwrap();
+        assert_ne!(passed.key(), board.key());
+        assert_ne!(passed, board);
+    }
+
+    #[test]
+    fn count_threats() {
+        // X's three in a row along the bottom can be completed at either end.
+        let board = Board::from_move_string("16263").unwrap();
+        assert_eq!(board.count_threats(), 2);
+        // Three on top of each other, and O with two in column 1.
+        assert_eq!(Board::from_move_string("01010").unwrap().count_threats(), 1);
+        assert_eq!(Board::from_move_string("0101").unwrap().count_threats(), 0);
+        // X's diagonal from (0, 0) to (2, 2) is completed at (3, 3).
+        let board = Board::from_move_string("0112232").unwrap();
+        assert_eq!(board.get(2, 2), Cell::X);
+        assert_eq!(board.count_threats(), 1);
+        // Threats across the edge of the board do not count.
+        assert_eq!(Board::from_move_string("5060").unwrap().count_threats(), 0);
+    }
+
+    #[test]
+    fn undo_winning_move() {
+        let mut board = Board::from_move_string("010101").unwrap();
+        let before = board;
+        assert_eq!(board.make_move(0), MoveResult::WinX);
+        board.undo(0);
+        assert_eq!(board, before);
+        assert_eq!(board.make_move(0), MoveResult::WinX);
+    }
 }
@@ -1,25 +1,45 @@
-// ChatGPT:
+// Baseline engine written by ChatGPT, kept as close as possible to its original
+// output so that the real engines can be measured against it.
+//
+// Prompt:
 // Write a program in rust to play connect 4 using a bitmap for storing the game
 // board and using monte carlo simulation for finding the next best move.
-
-// Here's a possible implementation of a Connect Four game in Rust that uses a bitmap
-// to store the game board and a Monte Carlo simulation to find the next best move:
+//
+// Only visibility and what clippy warns about were changed, and its game loop in
+// `main` was replaced by the `ChatGpt` engine at the end of this file. The code does not follow the rules
+// of connect 4. The divergences from `board::Board`, all checked by the tests
+// below, are:
+// - Red is stored at bit `y * WIDTH + x` and Yellow at that bit shifted by
+//   HEIGHT, which is also the Red bit of another cell. Every piece therefore
+//   shows up a second time elsewhere on the board, and setting a cell can
+//   erase a piece from an unrelated cell.
+// - `is_valid_move` only checks the Red bit of the top cell, so a column topped
+//   by Yellow still looks playable and `make_move` then fails.
+// - `is_win` compares every cell against a fixed mask instead of the player's
+//   pieces, so it reports a win for nearly every position, including the empty
+//   board.
+// - `monte_carlo` evaluates the position before the move, so every column gets
+//   the same score, and playouts stop as soon as `is_win` fires.
+// Its `print` also shows the bottom row at the top.
 
 use rand::{thread_rng, Rng};
 
-const WIDTH: usize = 7;
-const HEIGHT: usize = 6;
-const NUM_MOVES: usize = 1000;
+use crate::board::{self, MoveResult};
+use crate::engine::Engine;
+
+pub const WIDTH: usize = 7;
+pub const HEIGHT: usize = 6;
+pub const NUM_MOVES: usize = 1000;
 
 #[derive(Clone, Copy, PartialEq)]
-enum Cell {
+pub enum Cell {
     Empty,
     Red,
     Yellow,
 }
 
 impl Cell {
-    fn from_char(c: char) -> Option<Cell> {
+    pub fn from_char(c: char) -> Option<Cell> {
         match c {
             ' ' => Some(Cell::Empty),
             'R' => Some(Cell::Red),
@@ -28,7 +48,7 @@ impl Cell {
         }
     }
 
-    fn to_char(&self) -> char {
+    pub fn to_char(&self) -> char {
         match self {
             Cell::Empty => ' ',
             Cell::Red => 'R',
@@ -38,16 +58,22 @@ impl Cell {
 }
 
 #[derive(Clone)]
-struct Board {
+pub struct Board {
     bitmap: u64,
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self { bitmap: 0 }
     }
 
-    fn get(&self, x: usize, y: usize) -> Cell {
+    pub fn get(&self, x: usize, y: usize) -> Cell {
         let pos = y * WIDTH + x;
         let bit = 1 << pos;
         if (self.bitmap & bit) != 0 {
@@ -59,7 +85,7 @@ impl Board {
         }
     }
 
-    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
         let pos = y * WIDTH + x;
         let bit = 1 << pos;
         match cell {
@@ -77,12 +103,12 @@ impl Board {
         }
     }
 
-    fn is_valid_move(&self, x: usize) -> bool {
-        let pos = 1 << (HEIGHT - 1) * WIDTH + x;
+    pub fn is_valid_move(&self, x: usize) -> bool {
+        let pos = 1 << ((HEIGHT - 1) * WIDTH + x);
         (self.bitmap & pos) == 0
     }
 
-    fn make_move(&mut self, x: usize, cell: Cell) -> bool {
+    pub fn make_move(&mut self, x: usize, cell: Cell) -> bool {
         for y in 0..HEIGHT {
             if self.get(x, y) == Cell::Empty {
                 self.set(x, y, cell);
@@ -92,14 +118,14 @@ impl Board {
         false
     }
 
-    fn is_full(&self) -> bool {
-        (self.bitmap & ((1 << HEIGHT * WIDTH) - 1)) == ((1 << HEIGHT * WIDTH) - 1)
+    pub fn is_full(&self) -> bool {
+        (self.bitmap & ((1 << (HEIGHT * WIDTH)) - 1)) == ((1 << (HEIGHT * WIDTH)) - 1)
     }
 
-    fn is_win(&self, cell: Cell) -> bool {
+    pub fn is_win(&self, _cell: Cell) -> bool {
         let mut mask = 0;
         for i in 0..WIDTH {
-            mask |= 1 << i * HEIGHT;
+            mask |= 1 << (i * HEIGHT);
         }
         for y in 0..HEIGHT {
             for x in 0..WIDTH {
@@ -108,64 +134,63 @@ impl Board {
                 if (self.bitmap & bit) == (mask & bit) {
                     return true;
                 }
-                if x + 3 < WIDTH {
-                    if (self.bitmap & (bit | (bit << 1) | (bit << 2) | (bit << 3)))
+                if x + 3 < WIDTH
+                    && (self.bitmap & (bit | (bit << 1) | (bit << 2) | (bit << 3)))
                         == (mask & (bit | (bit << 1) | (bit << 2) | (bit << 3)))
-                    {
-                        return true;
-                    }
+                {
+                    return true;
                 }
-                if y + 3 < HEIGHT {
-                    if (self.bitmap
-                        & (bit | (bit << WIDTH) | (bit << 2 * WIDTH) | (bit << 3 * WIDTH)))
-                        == (mask & (bit | (bit << WIDTH) | (bit << 2 * WIDTH) | (bit << 3 * WIDTH)))
-                    {
-                        return true;
-                    }
+                if y + 3 < HEIGHT
+                    && (self.bitmap
+                        & (bit | (bit << WIDTH) | (bit << (2 * WIDTH)) | (bit << (3 * WIDTH))))
+                        == (mask
+                            & (bit | (bit << WIDTH) | (bit << (2 * WIDTH)) | (bit << (3 * WIDTH))))
+                {
+                    return true;
                 }
-                if x + 3 < WIDTH && y + 3 < HEIGHT {
-                    if (self.bitmap
+                if x + 3 < WIDTH
+                    && y + 3 < HEIGHT
+                    && (self.bitmap
                         & (bit
                             | (bit << (HEIGHT + 1))
-                            | (bit << 2 * (HEIGHT + 1))
-                            | (bit << 3 * (HEIGHT + 1))))
+                            | (bit << (2 * (HEIGHT + 1)))
+                            | (bit << (3 * (HEIGHT + 1)))))
                         == (mask
                             & (bit
                                 | (bit << (HEIGHT + 1))
-                                | (bit << 2 * (HEIGHT + 1))
-                                | (bit << 3 * (HEIGHT + 1))))
-                    {
-                        return true;
-                    }
+                                | (bit << (2 * (HEIGHT + 1)))
+                                | (bit << (3 * (HEIGHT + 1)))))
+                {
+                    return true;
                 }
-                if x >= 3 && y + 3 < HEIGHT {
-                    if (self.bitmap
+                if x >= 3
+                    && y + 3 < HEIGHT
+                    && (self.bitmap
                         & (bit
                             | (bit << (HEIGHT - 1))
-                            | (bit << 2 * (HEIGHT - 1))
-                            | (bit << 3 * (HEIGHT - 1))))
+                            | (bit << (2 * (HEIGHT - 1)))
+                            | (bit << (3 * (HEIGHT - 1)))))
                         == (mask
                             & (bit
                                 | (bit << (HEIGHT - 1))
-                                | (bit << 2 * (HEIGHT - 1))
-                                | (bit << 3 * (HEIGHT - 1))))
-                    {
-                        return true;
-                    }
+                                | (bit << (2 * (HEIGHT - 1)))
+                                | (bit << (3 * (HEIGHT - 1)))))
+                {
+                    return true;
                 }
             }
         }
         false
     }
 
-    fn print(&self) {
+    pub fn print(&self) {
         for y in 0..HEIGHT {
             for x in 0..WIDTH {
                 print!("|{} ", self.get(x, y).to_char());
             }
             println!("|");
         }
-        for x in 0..WIDTH {
+        for _x in 0..WIDTH {
             print!("+--");
         }
         println!("+");
@@ -176,7 +201,7 @@ impl Board {
     }
 }
 
-fn monte_carlo(board: &Board, cell: Cell) -> usize {
+pub fn monte_carlo(board: &Board, cell: Cell) -> usize {
     let mut rng = thread_rng();
     let mut wins = 0;
     let mut moves = 0;
@@ -211,51 +236,225 @@ fn monte_carlo(board: &Board, cell: Cell) -> usize {
             }
         }
     }
-    if moves == 0 {
-        0
-    } else {
-        wins * WIDTH / moves
+    (wins * WIDTH).checked_div(moves).unwrap_or(0)
+}
+
+/// Runs the baseline behind the common engine interface.
+pub struct ChatGpt;
+
+impl ChatGpt {
+    /// Copies a position cell by cell, row by row from the bottom. Because of the
+    /// storage collisions, later cells can overwrite earlier ones.
+    pub fn convert(board: &board::Board) -> Board {
+        let mut converted = Board::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                match board.get(x, y) {
+                    board::Cell::X => converted.set(x, y, Cell::Red),
+                    board::Cell::O => converted.set(x, y, Cell::Yellow),
+                    board::Cell::Empty => {}
+                }
+            }
+        }
+        converted
+    }
+}
+
+impl Engine for ChatGpt {
+    fn name(&self) -> &'static str {
+        "chatgpt"
     }
+
+    // Like the original game loop, every valid column is scored from the current
+    // position without making the move.
+    fn score_moves(&mut self, board: &board::Board) -> Vec<(usize, f64)> {
+        let converted = Self::convert(board);
+        let cell = match board.get_current_player() {
+            board::Cell::X => Cell::Red,
+            _ => Cell::Yellow,
+        };
+        (0..WIDTH)
+            .filter(|&x| board.is_valid_move(x))
+            .map(|x| (x, monte_carlo(&converted, cell) as f64))
+            .collect()
+    }
+}
+
+/// A disagreement between the baseline and `board::Board` after a move.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Divergence {
+    // `get` returns a different piece for the cell.
+    Piece { x: usize, y: usize },
+    // `is_valid_move` differs for the column.
+    ValidMove { x: usize },
+    // `make_move` failed to play a legal move.
+    MoveRejected { x: usize },
+    // `is_win` reports a win for the player who moved, but the move did not win.
+    FalseWin,
+    // `is_win` does not report a win for the player whose move won.
+    MissedWin,
+    // `is_full` differs.
+    Full,
 }
 
-fn main() {
-    let mut board = Board::new();
+/// Plays a game on both boards, each using its own `make_move`, and returns
+/// the divergences found after every move. Fails if a move is not legal.
+pub fn replay(moves: &[usize]) -> Result<Vec<Vec<Divergence>>, String> {
+    let mut expected = board::Board::new();
+    let mut actual = Board::new();
     let mut cell = Cell::Red;
-    loop {
-        board.print();
-        if board.is_win(cell) {
-            println!("{} wins!", cell.to_char());
-            break;
+    let mut divergences = Vec::new();
+    let mut over = false;
+    for &x in moves {
+        if over {
+            return Err(format!("Move {x} after the end of the game"));
         }
-        if board.is_full() {
-            println!("It's a draw!");
-            break;
+        let result = expected.make_move(x);
+        if result == MoveResult::Illegal {
+            return Err(format!("Illegal move {x}"));
+        }
+        over = result != MoveResult::None;
+        let mut found = Vec::new();
+        if !actual.make_move(x, cell) {
+            found.push(Divergence::MoveRejected { x });
         }
-        let mut best_score = 0;
-        let mut best_move = None;
-        for x in 0..WIDTH {
-            if board.is_valid_move(x) {
-                let score = monte_carlo(&board, cell);
 
-                if score > best_score {
-                    best_score = score;
-                    best_move = Some(x);
+        let won = matches!(result, MoveResult::WinX | MoveResult::WinO);
+        match (won, actual.is_win(cell)) {
+            (false, true) => found.push(Divergence::FalseWin),
+            (true, false) => found.push(Divergence::MissedWin),
+            _ => {}
+        }
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let same = match expected.get(x, y) {
+                    board::Cell::X => actual.get(x, y) == Cell::Red,
+                    board::Cell::O => actual.get(x, y) == Cell::Yellow,
+                    board::Cell::Empty => actual.get(x, y) == Cell::Empty,
+                };
+                if !same {
+                    found.push(Divergence::Piece { x, y });
                 }
             }
         }
-        match best_move {
-            Some(x) => {
-                board.make_move(x, cell);
-                cell = match cell {
-                    Cell::Red => Cell::Yellow,
-                    Cell::Yellow => Cell::Red,
-                    Cell::Empty => unreachable!(),
-                };
+        for x in 0..WIDTH {
+            if expected.is_valid_move(x) != actual.is_valid_move(x) {
+                found.push(Divergence::ValidMove { x });
             }
-            None => {
-                println!("No valid moves left!");
-                break;
+        }
+        if expected.is_full() != actual.is_full() {
+            found.push(Divergence::Full);
+        }
+
+        divergences.push(found);
+        cell = match cell {
+            Cell::Red => Cell::Yellow,
+            _ => Cell::Red,
+        };
+    }
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod chatgpt_tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{monte_carlo, replay, Board, Cell, ChatGpt, Divergence, HEIGHT, WIDTH};
+    use crate::board::{self, MoveResult};
+    use crate::engine::Engine;
+
+    #[test]
+    fn pieces_alias_other_cells() {
+        let mut board = Board::new();
+        board.set(0, 0, Cell::Yellow);
+        assert!(board.get(0, 0) == Cell::Yellow);
+        assert!(board.get(6, 0) == Cell::Red);
+
+        let mut board = Board::new();
+        board.set(6, 0, Cell::Red);
+        assert!(board.get(0, 0) == Cell::Yellow);
+
+        // Setting (1, 0) clears the Yellow bit of (1, 0), which is the Red bit of (0, 1).
+        let mut board = Board::new();
+        board.set(0, 1, Cell::Red);
+        board.set(1, 0, Cell::Red);
+        assert!(board.get(0, 1) == Cell::Empty);
+    }
+
+    #[test]
+    fn empty_board_is_a_win() {
+        let board = Board::new();
+        assert!(board.is_win(Cell::Red));
+        assert!(board.is_win(Cell::Yellow));
+    }
+
+    #[test]
+    fn column_topped_by_yellow_looks_playable() {
+        let mut board = Board::new();
+        for y in 0..HEIGHT {
+            let cell = if y % 2 == 0 { Cell::Red } else { Cell::Yellow };
+            board.set(0, y, cell);
+        }
+        assert!(board.is_valid_move(0));
+        assert!(!board.make_move(0, Cell::Red));
+    }
+
+    #[test]
+    fn monte_carlo_never_counts_moves() {
+        // Every playout ends at its first move, before the move is counted.
+        assert_eq!(monte_carlo(&Board::new(), Cell::Red), 0);
+
+        let board = board::Board::from_move_string("3324").unwrap();
+        let scores = ChatGpt.score_moves(&board);
+        assert_eq!(scores.len(), WIDTH);
+        assert!(scores.iter().all(|&(_, score)| score == 0.0));
+        assert_eq!(ChatGpt.choose_move(&board), Some(0));
+    }
+
+    #[test]
+    fn differential_random_games() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut seen = Vec::new();
+        for _ in 0..200 {
+            let mut board = board::Board::new();
+            let mut moves = Vec::new();
+            let mut result = MoveResult::None;
+            while result == MoveResult::None {
+                let valid: Vec<usize> = (0..WIDTH).filter(|&x| board.is_valid_move(x)).collect();
+                let x = valid[rng.gen_range(0..valid.len())];
+                moves.push(x);
+                result = board.make_move(x);
+            }
+
+            let divergences = replay(&moves).unwrap();
+            for (i, found) in divergences.iter().enumerate() {
+                let last = i == moves.len() - 1;
+                assert_eq!(found.contains(&Divergence::FalseWin), !last);
+                assert!(!found.contains(&Divergence::MissedWin));
+                for divergence in found {
+                    let kind = std::mem::discriminant(divergence);
+                    if !seen.contains(&kind) {
+                        seen.push(kind);
+                    }
+                }
             }
         }
+
+        for divergence in [
+            Divergence::Piece { x: 0, y: 0 },
+            Divergence::ValidMove { x: 0 },
+            Divergence::MoveRejected { x: 0 },
+        ] {
+            assert!(seen.contains(&std::mem::discriminant(&divergence)));
+        }
+    }
+
+    #[test]
+    fn replay_rejects_illegal_moves() {
+        assert!(replay(&[3, 3, 4]).is_ok());
+        assert!(replay(&[7]).is_err());
+        assert!(replay(&[0; 7]).is_err());
+        assert!(replay(&[3, 3, 4, 4, 5, 5, 6, 0]).is_err());
     }
 }
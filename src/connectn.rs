@@ -0,0 +1,263 @@
+// Connect N: drop discs to make N in a row on a board of any size
+//
+// Besides Connect 5 on a 9x6 board this covers Five-in-a-Row, where the
+// outer columns of the 9x6 board start filled with alternating discs, and
+// toroidal boards, where lines carry on past one edge from the opposite edge,
+// both across the board and up it.
+
+use crate::board::{Cell, MoveResult};
+use crate::rules::{Move, Rules};
+
+// Moves are written as one digit per column.
+const MAX_WIDTH: usize = 10;
+
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConnectN {
+    width: usize,
+    height: usize,
+    n: usize,
+    wrap: bool,
+    name: &'static str,
+
+    // Cell of column col, row y is at col * height + y.
+    cells: Vec<Cell>,
+    heights: Vec<usize>,
+    moves: Vec<Move>,
+    result: MoveResult,
+}
+
+impl ConnectN {
+    pub fn new(width: usize, height: usize, n: usize) -> Self {
+        assert!((1..=MAX_WIDTH).contains(&width), "width {width}");
+        assert!(height > 0 && n > 1, "height {height}, n {n}");
+        Self {
+            width,
+            height,
+            n,
+            wrap: false,
+            name: "connect-n",
+            cells: vec![Cell::Empty; width * height],
+            heights: vec![0; width],
+            moves: Vec::new(),
+            result: MoveResult::None,
+        }
+    }
+
+    /// A board whose opposite edges join, as on a torus.
+    pub fn torus(width: usize, height: usize, n: usize) -> Self {
+        Self {
+            wrap: true,
+            name: "torus",
+            ..Self::new(width, height, n)
+        }
+    }
+
+    /// Five in a row on a 9x6 board whose outer columns start full, with the
+    /// colours alternating up each column and X at the bottom on the left.
+    pub fn five_in_a_row() -> Self {
+        let mut game = Self {
+            name: "five-in-a-row",
+            ..Self::new(9, 6, 5)
+        };
+        for y in 0..game.height {
+            let (left, right) = if y % 2 == 0 {
+                (Cell::X, Cell::O)
+            } else {
+                (Cell::O, Cell::X)
+            };
+            game.cells[y] = left;
+            game.cells[(game.width - 1) * game.height + y] = right;
+        }
+        game.heights[0] = game.height;
+        game.heights[game.width - 1] = game.height;
+        game
+    }
+
+    pub fn get_n(&self) -> usize {
+        self.n
+    }
+
+    fn is_full(&self) -> bool {
+        self.heights.iter().all(|&height| height == self.height)
+    }
+
+    // The cell a number of steps along a direction, if it is on the board.
+    fn step(&self, col: usize, y: usize, (dx, dy): (isize, isize), steps: isize) -> Option<Cell> {
+        let mut col = col as isize + dx * steps;
+        let mut y = y as isize + dy * steps;
+        if self.wrap {
+            col = col.rem_euclid(self.width as isize);
+            y = y.rem_euclid(self.height as isize);
+        } else if col < 0 || col >= self.width as isize || y < 0 || y >= self.height as isize {
+            return None;
+        }
+        Some(self.cells[col as usize * self.height + y as usize])
+    }
+
+    // How many cells a line in the direction passes before coming back to
+    // where it started on a wrapping board.
+    fn cycle(&self, (dx, dy): (isize, isize)) -> usize {
+        match (dx, dy) {
+            (_, 0) => self.width,
+            (0, _) => self.height,
+            _ => lcm(self.width, self.height),
+        }
+    }
+
+    // Whether the disc at col, y is part of N in a row.
+    fn makes_line(&self, col: usize, y: usize) -> bool {
+        let player = self.cells[col * self.height + y];
+        DIRECTIONS.into_iter().any(|direction| {
+            // On a wrapping board, stop before counting any cell twice.
+            let cells = if self.wrap {
+                self.cycle(direction) - 1
+            } else {
+                usize::MAX
+            };
+            let run = |sign: isize, limit: usize| {
+                (1..=limit)
+                    .take_while(|&steps| {
+                        self.step(col, y, direction, sign * steps as isize) == Some(player)
+                    })
+                    .count()
+            };
+            let forward = run(1, (self.n - 1).min(cells));
+            let backward = run(-1, (self.n - 1).min(cells - forward));
+            forward + backward + 1 >= self.n
+        })
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+impl Rules for ConnectN {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn get_width(&self) -> usize {
+        self.width
+    }
+
+    fn get_height(&self) -> usize {
+        self.height
+    }
+
+    fn get(&self, col: usize, y: usize) -> Cell {
+        self.cells[col * self.height + y]
+    }
+
+    fn get_current_player(&self) -> Cell {
+        match self.moves.len() & 1 {
+            0 => Cell::X,
+            1 => Cell::O,
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        (0..self.width)
+            .filter(|&col| self.heights[col] < self.height)
+            .map(Move::Drop)
+            .collect()
+    }
+
+    fn apply(&mut self, mv: Move) -> MoveResult {
+        if !self.is_legal(mv) {
+            return MoveResult::Illegal;
+        }
+        let col = mv.get_column();
+        let y = self.heights[col];
+        let player = self.get_current_player();
+        self.cells[col * self.height + y] = player;
+        self.heights[col] += 1;
+        self.moves.push(mv);
+        self.result = if self.makes_line(col, y) {
+            match player {
+                Cell::X => MoveResult::WinX,
+                _ => MoveResult::WinO,
+            }
+        } else if self.is_full() {
+            MoveResult::Draw
+        } else {
+            MoveResult::None
+        };
+        self.result
+    }
+
+    fn outcome(&self) -> MoveResult {
+        self.result
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod connectn_tests {
+    use super::ConnectN;
+    use crate::board::{Cell, MoveResult};
+    use crate::rules::{play_notation, Move, Rules};
+
+    #[test]
+    fn connect_five() {
+        let mut game = ConnectN::new(9, 6, 5);
+        // Four in a row is not enough.
+        play_notation(&mut game, "4455667").unwrap();
+        assert_eq!(game.outcome(), MoveResult::None);
+        assert_eq!(game.apply(Move::Drop(8)), MoveResult::None);
+        assert_eq!(game.apply(Move::Drop(3)), MoveResult::WinX);
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::Illegal);
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn five_in_a_row_starts_with_full_outer_columns() {
+        let mut game = ConnectN::five_in_a_row();
+        assert_eq!((game.get(0, 0), game.get(0, 1)), (Cell::X, Cell::O));
+        assert_eq!((game.get(8, 0), game.get(8, 1)), (Cell::O, Cell::X));
+        assert_eq!(game.get_current_player(), Cell::X);
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::Illegal);
+        // Four of X's discs join the prefilled X at the bottom left.
+        play_notation(&mut game, "1122334").unwrap();
+        assert_eq!(game.outcome(), MoveResult::WinX);
+    }
+
+    #[test]
+    fn torus_lines_wrap() {
+        // Across the edge: X plays 5, 6, 0 and 1 on the bottom row.
+        let mut game = ConnectN::torus(7, 6, 4);
+        play_notation(&mut game, "5566001").unwrap();
+        assert_eq!(game.outcome(), MoveResult::WinX);
+        let mut game = ConnectN::new(7, 6, 4);
+        play_notation(&mut game, "5566001").unwrap();
+        assert_eq!(game.outcome(), MoveResult::None);
+
+        // Up the edge: O's discs at the top of column 0 join the one at the bottom.
+        let mut game = ConnectN::torus(7, 6, 4);
+        play_notation(&mut game, "100600204").unwrap();
+        assert_eq!(game.outcome(), MoveResult::None);
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::WinO);
+
+        // A column shorter than the line never counts itself twice.
+        let mut game = ConnectN::torus(4, 3, 4);
+        play_notation(&mut game, "01010").unwrap();
+        assert_eq!(game.outcome(), MoveResult::None);
+    }
+}
@@ -0,0 +1,249 @@
+// Connect 4 on a cylinder: the 7x6 board wraps around from column 6 back to
+// column 0
+//
+// Horizontal and diagonal lines may run off the right edge and carry on from
+// the left, while columns still have a bottom and a top. The edges of
+// `Board`'s win checks are built into its masks, so lines are found here by
+// laying a copy of the board next to itself.
+
+use crate::board::{Cell, MoveResult, HEIGHT, WIDTH};
+use crate::rules::{Move, Rules};
+
+// Same bit layout as `Board`: column col, row y is bit col * 8 + y. The
+// empty rows above each column keep diagonals from running into the next one.
+const PADDED_HEIGHT: usize = 8;
+const COLUMN_MASK: u64 = (1 << HEIGHT) - 1;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cylinder {
+    // Discs of X and of O.
+    pieces: [u64; 2],
+    moves: Vec<Move>,
+    result: MoveResult,
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cylinder {
+    pub fn new() -> Self {
+        Self {
+            pieces: [0; 2],
+            moves: Vec::new(),
+            result: MoveResult::None,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        self.pieces[0] | self.pieces[1]
+    }
+
+    fn get_column_height(&self, col: usize) -> usize {
+        ((self.mask() >> (col * PADDED_HEIGHT)) & COLUMN_MASK).count_ones() as usize
+    }
+}
+
+// Whether the discs contain four in a row, wrapping around the sides.
+fn has_four(pieces: u64) -> bool {
+    // Columns don't wrap.
+    let pairs = pieces & (pieces >> 1);
+    if pairs & (pairs >> 2) != 0 {
+        return true;
+    }
+
+    // Lines running off the last column carry on into the copy of the first.
+    let wide = pieces as u128 | (pieces as u128) << (WIDTH * PADDED_HEIGHT);
+    [PADDED_HEIGHT, PADDED_HEIGHT - 1, PADDED_HEIGHT + 1]
+        .into_iter()
+        .any(|shift| {
+            let pairs = wide & (wide >> shift);
+            pairs & (pairs >> (2 * shift)) != 0
+        })
+}
+
+impl Rules for Cylinder {
+    fn name(&self) -> &'static str {
+        "cylinder"
+    }
+
+    fn get_width(&self) -> usize {
+        WIDTH
+    }
+
+    fn get_height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn get(&self, col: usize, y: usize) -> Cell {
+        let bit = 1 << (col * PADDED_HEIGHT + y);
+        if self.pieces[0] & bit != 0 {
+            Cell::X
+        } else if self.pieces[1] & bit != 0 {
+            Cell::O
+        } else {
+            Cell::Empty
+        }
+    }
+
+    fn get_current_player(&self) -> Cell {
+        match self.moves.len() & 1 {
+            0 => Cell::X,
+            1 => Cell::O,
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        (0..WIDTH)
+            .filter(|&col| self.get_column_height(col) < HEIGHT)
+            .map(Move::Drop)
+            .collect()
+    }
+
+    fn apply(&mut self, mv: Move) -> MoveResult {
+        if !self.is_legal(mv) {
+            return MoveResult::Illegal;
+        }
+        let col = mv.get_column();
+        let player = self.moves.len() & 1;
+        self.pieces[player] |= 1 << (col * PADDED_HEIGHT + self.get_column_height(col));
+        self.moves.push(mv);
+        self.result = if has_four(self.pieces[player]) {
+            match player {
+                0 => MoveResult::WinX,
+                _ => MoveResult::WinO,
+            }
+        } else if self.moves.len() == WIDTH * HEIGHT {
+            MoveResult::Draw
+        } else {
+            MoveResult::None
+        };
+        self.result
+    }
+
+    fn outcome(&self) -> MoveResult {
+        self.result
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod cylinder_tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::Cylinder;
+    use crate::board::{Cell, MoveResult, HEIGHT, WIDTH};
+    use crate::engine::MonteCarlo;
+    use crate::rules::{notation, play_notation, Move, Rules};
+
+    fn play(moves: &str) -> Cylinder {
+        let mut game = Cylinder::new();
+        play_notation(&mut game, moves).unwrap();
+        game
+    }
+
+    // Looks for four in a row through each cell, one cell at a time.
+    fn has_four_slowly(game: &Cylinder, player: Cell) -> bool {
+        (0..WIDTH).any(|col| {
+            (0..HEIGHT).any(|y| {
+                [(1, 0), (0, 1), (1, 1), (1, -1)].iter().any(|&(dx, dy)| {
+                    (0..4).all(|step| {
+                        let y = y as isize + dy * step;
+                        let col = (col as isize + dx * step).rem_euclid(WIDTH as isize);
+                        (0..HEIGHT as isize).contains(&y)
+                            && game.get(col as usize, y as usize) == player
+                    })
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn rows_wrap() {
+        // X plays 5, 6, 0 and 1 on the bottom row.
+        assert_eq!(play("556600").apply(Move::Drop(1)), MoveResult::WinX);
+        assert_eq!(play("556600").apply(Move::Drop(4)), MoveResult::WinX);
+        assert_eq!(play("55660").apply(Move::Drop(2)), MoveResult::None);
+    }
+
+    #[test]
+    fn diagonals_wrap() {
+        // X rises from column 5 at the bottom through 6 and 0 to 1.
+        let mut game = play("000661505110");
+        assert_eq!(game.outcome(), MoveResult::None);
+        assert_eq!(game.apply(Move::Drop(1)), MoveResult::WinX);
+        assert_eq!((game.get(5, 0), game.get(1, 3)), (Cell::X, Cell::X));
+
+        // O falls from column 5 through 6 and 0 to 1 at the bottom.
+        let mut game = play("6151115500661");
+        assert_eq!(game.outcome(), MoveResult::None);
+        assert_eq!(game.apply(Move::Drop(5)), MoveResult::WinO);
+        assert!(has_four_slowly(&game, Cell::O));
+    }
+
+    #[test]
+    fn columns_do_not_wrap() {
+        // X has the top three of column 0 and the bottom one.
+        let game = play("0101001010");
+        assert_eq!(game.get(0, 0), Cell::X);
+        assert_eq!(game.outcome(), MoveResult::None);
+        assert!(!has_four_slowly(&game, Cell::X));
+    }
+
+    #[test]
+    fn matches_a_slow_check() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut results = [0; 3];
+        for _ in 0..500 {
+            let mut game = Cylinder::new();
+            while !game.is_terminal() {
+                let moves = game.legal_moves();
+                let player = game.get_current_player();
+                let result = game.apply(moves[rng.gen_range(0..moves.len())]);
+                let expected = if has_four_slowly(&game, player) {
+                    match player {
+                        Cell::X => MoveResult::WinX,
+                        _ => MoveResult::WinO,
+                    }
+                } else if game.get_moves().len() == WIDTH * HEIGHT {
+                    MoveResult::Draw
+                } else {
+                    MoveResult::None
+                };
+                assert_eq!(result, expected, "{}", notation(game.get_moves()));
+            }
+            match game.outcome() {
+                MoveResult::WinX => results[0] += 1,
+                MoveResult::WinO => results[1] += 1,
+                _ => results[2] += 1,
+            }
+        }
+        assert!(results[0] > 0 && results[1] > 0, "{results:?}");
+    }
+
+    #[test]
+    fn engine_takes_a_wrapped_win() {
+        let mut engine = MonteCarlo::with_rng(10, StdRng::seed_from_u64(2));
+        let scores = engine.score_variant_moves(&play("556600"));
+        assert!(scores.contains(&(Move::Drop(1), f64::INFINITY)));
+        assert!(scores.contains(&(Move::Drop(4), f64::INFINITY)));
+        assert_eq!(
+            engine.choose_variant_move(&play("556600")),
+            Some(Move::Drop(1))
+        );
+    }
+}
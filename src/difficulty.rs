@@ -0,0 +1,240 @@
+// Difficulty levels for the computer opponent
+//
+// The expert level plays perfectly from the first move, with the full solver.
+// Solving early positions takes time, so it can think for minutes in the
+// opening.
+
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::{Board, HEIGHT, WIDTH};
+use crate::engine::{Engine, Exact, Info, MonteCarlo, Perfect};
+use crate::hint;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Level {
+    pub name: &'static str,
+
+    // Random games played per column by the Monte Carlo engine.
+    pub num_games: usize,
+
+    // Positions with at most this many empty cells are played with the solver.
+    pub solve_limit: usize,
+
+    // Number of best scoring moves to choose between.
+    pub top_k: usize,
+
+    // How likely worse ranked moves among the top k are to be chosen. The move
+    // ranked r (starting at 0) is weighted by exp(-r / temperature), so zero
+    // always picks the best move.
+    pub temperature: f64,
+
+    // Probability of ignoring the engine and playing a random valid move.
+    pub blunder: f64,
+}
+
+pub const LEVELS: [Level; 4] = [
+    Level {
+        name: "beginner",
+        num_games: 5,
+        solve_limit: 0,
+        top_k: 3,
+        temperature: 1.0,
+        blunder: 0.2,
+    },
+    Level {
+        name: "casual",
+        num_games: 20,
+        solve_limit: 0,
+        top_k: 2,
+        temperature: 0.5,
+        blunder: 0.05,
+    },
+    Level {
+        name: "strong",
+        num_games: 200,
+        solve_limit: 10,
+        top_k: 1,
+        temperature: 0.0,
+        blunder: 0.0,
+    },
+    // Solves every position, so it never plays a move that throws away a win
+    // or a draw.
+    Level {
+        name: "expert",
+        num_games: 1000,
+        solve_limit: WIDTH * HEIGHT,
+        top_k: 1,
+        temperature: 0.0,
+        blunder: 0.0,
+    },
+];
+
+pub fn from_name(name: &str) -> Option<Level> {
+    LEVELS.into_iter().find(|level| level.name == name)
+}
+
+/// Engine that plays at a given difficulty level.
+pub struct Leveled {
+    level: Level,
+    monte_carlo: MonteCarlo,
+
+    // Engine for the positions within the solve limit. Only the full solver
+    // can solve positions beyond `hint::SOLVE_LIMIT` empty cells.
+    solver: Box<dyn Engine>,
+
+    rng: StdRng,
+}
+
+impl Leveled {
+    pub fn new(level: Level) -> Self {
+        Self::with_rng(level, StdRng::from_entropy())
+    }
+
+    pub fn with_rng(level: Level, rng: StdRng) -> Self {
+        Self {
+            level,
+            monte_carlo: MonteCarlo::new(level.num_games),
+            solver: if level.solve_limit > hint::SOLVE_LIMIT {
+                Box::new(Perfect::new())
+            } else {
+                Box::new(Exact::new())
+            },
+            rng,
+        }
+    }
+}
+
+impl Engine for Leveled {
+    fn name(&self) -> &'static str {
+        self.level.name
+    }
+
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        if WIDTH * HEIGHT - board.get_move_count() <= self.level.solve_limit {
+            self.solver.score_moves(board)
+        } else {
+            self.monte_carlo.score_moves(board)
+        }
+    }
+
+    fn choose_move(&mut self, board: &Board) -> Option<usize> {
+        self.analyze(board).map(|info| info.pv[0])
+    }
+
+    // Reports the move actually chosen, which is not always the best scoring one.
+    fn analyze(&mut self, board: &Board) -> Option<Info> {
+        let blunder = self.rng.gen_bool(self.level.blunder);
+        let empty_cells = WIDTH * HEIGHT - board.get_move_count();
+        if !blunder && self.level.top_k <= 1 && empty_cells <= self.level.solve_limit {
+            // Only the best move is needed, which the solver can find without
+            // scoring every column.
+            return self.solver.analyze(board);
+        }
+        let mut scores = self.score_moves(board);
+        if scores.is_empty() {
+            return None;
+        }
+
+        let (col, score) = if blunder {
+            scores[self.rng.gen_range(0..scores.len())]
+        } else {
+            // The sort is stable, so ties are still broken by the lowest column.
+            scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+            scores.truncate(self.level.top_k.max(1));
+            if self.level.temperature <= 0.0 {
+                scores[0]
+            } else {
+                let weights =
+                    (0..scores.len()).map(|rank| (-(rank as f64) / self.level.temperature).exp());
+                scores[self.rng.sample(WeightedIndex::new(weights).unwrap())]
+            }
+        };
+        Some(Info {
+            depth: if empty_cells <= self.level.solve_limit {
+                empty_cells
+            } else {
+                1
+            },
+            score,
+            pv: vec![col],
+        })
+    }
+}
+
+#[cfg(test)]
+mod difficulty_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{from_name, Leveled, LEVELS};
+    use crate::board::{Board, HEIGHT, WIDTH};
+    use crate::engine::Engine;
+
+    // X can win in columns 1, 3 and 5, and loses after 0 or 6.
+    const POSITION: &str = "242512646300052352144344621036";
+
+    fn solved_level(top_k: usize, temperature: f64, blunder: f64) -> Leveled {
+        let level = super::Level {
+            name: "test",
+            num_games: 1,
+            solve_limit: WIDTH * HEIGHT,
+            top_k,
+            temperature,
+            blunder,
+        };
+        Leveled::with_rng(level, StdRng::seed_from_u64(7))
+    }
+
+    #[test]
+    fn level_names() {
+        for level in LEVELS {
+            assert_eq!(from_name(level.name), Some(level));
+        }
+        assert_eq!(from_name("impossible"), None);
+    }
+
+    #[test]
+    fn expert_plays_perfectly_from_the_start() {
+        let mut engine = Leveled::new(from_name("expert").unwrap());
+        assert_eq!(engine.choose_move(&Board::new()), Some(3));
+        // Columns 2 to 5 keep the draw after an opening move next to the
+        // center, and ties go to the lowest column.
+        let board = Board::from_move_string("2").unwrap();
+        assert_eq!(engine.choose_move(&board), Some(2));
+    }
+
+    #[test]
+    fn zero_temperature_plays_best_move() {
+        let board = Board::from_move_string(POSITION).unwrap();
+        let mut engine = solved_level(3, 0.0, 0.0);
+        for _ in 0..10 {
+            assert_eq!(engine.choose_move(&board), Some(1));
+        }
+    }
+
+    #[test]
+    fn top_k_limits_choices() {
+        let board = Board::from_move_string(POSITION).unwrap();
+        let mut engine = solved_level(3, 10.0, 0.0);
+        let mut seen = [false; WIDTH];
+        for _ in 0..50 {
+            seen[engine.choose_move(&board).unwrap()] = true;
+        }
+        assert_eq!(seen, [false, true, false, true, false, true, false]);
+    }
+
+    #[test]
+    fn blunders_play_any_valid_move() {
+        let board = Board::from_move_string(POSITION).unwrap();
+        let mut engine = solved_level(1, 0.0, 1.0);
+        let mut seen = [false; WIDTH];
+        for _ in 0..100 {
+            seen[engine.choose_move(&board).unwrap()] = true;
+        }
+        for (col, seen) in seen.iter().enumerate() {
+            assert_eq!(*seen, board.is_valid_move(col));
+        }
+    }
+}
@@ -0,0 +1,543 @@
+// Common interface for the engines that choose moves
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::{Board, Cell, MoveResult, HEIGHT, WIDTH};
+use crate::chatgpt_connect4::ChatGpt;
+use crate::eval::{Heuristic, Weights};
+use crate::fullsolve::{self, Geometry, Position, Search};
+use crate::hint;
+use crate::mcts::{self, Mcts, Rollouts};
+use crate::misere::Objective;
+use crate::play::{self, Policy};
+use crate::rules::{Move, Rules};
+use crate::solver::Solver;
+use crate::tablebase;
+
+/// What an engine found while choosing a move.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Info {
+    // Number of moves searched ahead, counting the move itself.
+    pub depth: usize,
+
+    // Score of the chosen move, in the units of `Engine::score_moves`.
+    pub score: f64,
+
+    // Principal variation: the chosen move followed by the replies the engine expects.
+    pub pv: Vec<usize>,
+}
+
+pub trait Engine {
+    fn name(&self) -> &'static str;
+
+    /// Scores every valid column for the player to move, higher being better.
+    /// Scores are only comparable between columns of the same call.
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)>;
+
+    /// Returns the best scoring column, preferring the lowest column on ties.
+    fn choose_move(&mut self, board: &Board) -> Option<usize> {
+        best_move(self.score_moves(board)).map(|(col, _)| col)
+    }
+
+    /// Chooses a move like `choose_move` and reports how it was found. The
+    /// default only looks at the moves themselves.
+    fn analyze(&mut self, board: &Board) -> Option<Info> {
+        let (col, score) = best_move(self.score_moves(board))?;
+        Some(Info {
+            depth: 1,
+            score,
+            pv: vec![col],
+        })
+    }
+
+    /// Analyzes like `analyze`, passing what the engine has found so far to
+    /// `progress` while it searches. The default only returns the result.
+    fn analyze_with_progress(
+        &mut self,
+        board: &Board,
+        _progress: &mut dyn FnMut(&Info),
+    ) -> Option<Info> {
+        self.analyze(board)
+    }
+
+    /// Starts thinking about a position where the opponent is to move, until
+    /// the engine is next asked about a position. Engines that only think when
+    /// asked ignore this.
+    fn ponder(&mut self, _board: &Board) {}
+}
+
+pub(crate) fn best_move(scores: Vec<(usize, f64)>) -> Option<(usize, f64)> {
+    let mut best: Option<(usize, f64)> = None;
+    for (col, score) in scores {
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((col, score));
+        }
+    }
+    best
+}
+
+pub const ENGINE_NAMES: [&str; 5] = ["montecarlo", "solver", "mcts", "heuristic", "chatgpt"];
+
+pub fn from_name(name: &str) -> Option<Box<dyn Engine>> {
+    from_name_for(name, Objective::Standard)
+}
+
+/// Returns the named engine playing for the objective, if it can.
+pub fn from_name_for(name: &str, objective: Objective) -> Option<Box<dyn Engine>> {
+    match (name, objective) {
+        ("montecarlo", _) => Some(Box::new(MonteCarlo::with_objective(
+            play::NUM_GAMES,
+            objective,
+        ))),
+        ("solver", _) => Some(Box::new(Exact::with_objective(objective))),
+        ("mcts", _) => Some(Box::new(Mcts::with_objective(
+            Rollouts::with_objective(play::NUM_GAMES, objective),
+            mcts::NUM_SIMULATIONS,
+            objective,
+        ))),
+        ("heuristic", _) => Some(Box::new(Heuristic::with_objective(
+            Weights::default(),
+            objective,
+        ))),
+        ("chatgpt", Objective::Standard) => Some(Box::new(ChatGpt)),
+        _ => None,
+    }
+}
+
+// Random games of a variant longer than this are counted as draws, as variants
+// with pops can go on for a long time.
+const MAX_VARIANT_PLAYOUT_MOVES: usize = 200;
+
+/// Scores each column with playouts from the resulting position: the share of
+/// the games won, counting draws as half. It also plays every variant through
+/// `Rules`.
+pub struct MonteCarlo {
+    // Number of games played for each column.
+    num_games: usize,
+
+    objective: Objective,
+    policy: Policy,
+    rng: StdRng,
+}
+
+impl Default for MonteCarlo {
+    fn default() -> Self {
+        Self::new(play::NUM_GAMES)
+    }
+}
+
+impl MonteCarlo {
+    pub fn new(num_games: usize) -> Self {
+        Self::with_objective(num_games, Objective::Standard)
+    }
+
+    pub fn with_objective(num_games: usize, objective: Objective) -> Self {
+        Self::with_policy(num_games, objective, Policy::Uniform)
+    }
+
+    pub fn with_policy(num_games: usize, objective: Objective, policy: Policy) -> Self {
+        Self {
+            num_games,
+            objective,
+            policy,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn with_rng(num_games: usize, rng: StdRng) -> Self {
+        Self {
+            rng,
+            ..Self::new(num_games)
+        }
+    }
+
+    /// Scores every legal move of a game of any variant, like `score_moves`.
+    /// The random games pick moves uniformly and the rules decide who wins,
+    /// so the policy and objective are not used.
+    pub fn score_variant_moves(&mut self, rules: &dyn Rules) -> Vec<(Move, f64)> {
+        let player = rules.get_current_player();
+        let mut scores = Vec::new();
+        for mv in rules.legal_moves() {
+            let mut next = rules.box_clone();
+            let score = match next.apply(mv) {
+                MoveResult::None => {
+                    let mut total = 0.0;
+                    for _ in 0..self.num_games {
+                        total += self.variant_playout(next.clone(), player);
+                    }
+                    total / self.num_games.max(1) as f64
+                }
+                MoveResult::Draw => 0.5,
+                result if Cell::from(result) == player => f64::INFINITY,
+                _ => f64::NEG_INFINITY,
+            };
+            scores.push((mv, score));
+        }
+        scores
+    }
+
+    /// Returns the best scoring move of a game of any variant, preferring the
+    /// first on ties.
+    pub fn choose_variant_move(&mut self, rules: &dyn Rules) -> Option<Move> {
+        let mut best: Option<(Move, f64)> = None;
+        for (mv, score) in self.score_variant_moves(rules) {
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((mv, score));
+            }
+        }
+        best.map(|(mv, _)| mv)
+    }
+
+    // Plays random moves to the end and returns the share of the game won by
+    // the player.
+    fn variant_playout(&mut self, mut rules: Box<dyn Rules>, player: Cell) -> f64 {
+        for _ in 0..MAX_VARIANT_PLAYOUT_MOVES {
+            let moves = rules.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            rules.apply(moves[self.rng.gen_range(0..moves.len())]);
+        }
+        match rules.outcome() {
+            MoveResult::WinX | MoveResult::WinO if Cell::from(rules.outcome()) == player => 1.0,
+            MoveResult::WinX | MoveResult::WinO => 0.0,
+            _ => 0.5,
+        }
+    }
+}
+
+impl Engine for MonteCarlo {
+    fn name(&self) -> &'static str {
+        "montecarlo"
+    }
+
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        let player = board.get_current_player();
+        let mut scores = Vec::new();
+        for col in 0..WIDTH {
+            let mut next = *board;
+            let score = match self.objective.interpret(next.make_move(col)) {
+                MoveResult::Illegal => continue,
+                MoveResult::Draw => 0.5,
+                MoveResult::None => match tablebase::probe(&next) {
+                    // A known result is as good or bad as the playouts can get.
+                    Some(score) if self.objective == Objective::Standard => {
+                        (1 - score.signum()) as f64 / 2.0
+                    }
+                    _ => play::playouts(
+                        &next,
+                        player,
+                        self.num_games,
+                        self.policy,
+                        self.objective,
+                        &mut self.rng,
+                    )
+                    .score(),
+                },
+                result if Cell::from(result) == player => f64::INFINITY,
+                _ => f64::NEG_INFINITY,
+            };
+            scores.push((col, score));
+        }
+        scores
+    }
+}
+
+/// Scores each column with its exact solver score once the position is within
+/// `hint::SOLVE_LIMIT` empty cells, and with Monte Carlo before that, where the
+/// solver could take hours.
+pub struct Exact {
+    solver: Solver,
+    monte_carlo: MonteCarlo,
+}
+
+impl Default for Exact {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Exact {
+    pub fn new() -> Self {
+        Self::with_objective(Objective::Standard)
+    }
+
+    pub fn with_objective(objective: Objective) -> Self {
+        Self {
+            solver: Solver::with_objective(objective),
+            monte_carlo: MonteCarlo::with_objective(play::NUM_GAMES, objective),
+        }
+    }
+
+    // Whether the position is close enough to the end to solve.
+    fn can_solve(board: &Board) -> bool {
+        WIDTH * HEIGHT - board.get_move_count() <= hint::SOLVE_LIMIT
+    }
+}
+
+impl Engine for Exact {
+    fn name(&self) -> &'static str {
+        "solver"
+    }
+
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        if !Exact::can_solve(board) {
+            return self.monte_carlo.score_moves(board);
+        }
+        let moves = board.get_move_count() as i32;
+        let win = (WIDTH as i32 * HEIGHT as i32 + 1 - moves) / 2;
+        let mut scores = Vec::new();
+        for col in 0..WIDTH {
+            let mut next = *board;
+            let score = match next.make_move(col) {
+                MoveResult::Illegal => continue,
+                MoveResult::WinX | MoveResult::WinO => match self.solver.get_objective() {
+                    Objective::Standard => win,
+                    Objective::Misere => -win,
+                },
+                MoveResult::Draw => 0,
+                MoveResult::None => -self.solver.solve(&next),
+            };
+            scores.push((col, score as f64));
+        }
+        scores
+    }
+
+    // Searches to the end of the game, so the principal variation is played out
+    // until the game is over. Positions too early to solve are only looked at
+    // one move deep.
+    fn analyze(&mut self, board: &Board) -> Option<Info> {
+        if !Exact::can_solve(board) {
+            return self.monte_carlo.analyze(board);
+        }
+        let (col, score) = best_move(self.score_moves(board))?;
+        let mut pv = vec![col];
+        let mut next = *board;
+        while next.make_move(*pv.last().unwrap()) == MoveResult::None {
+            let (col, _) = best_move(self.score_moves(&next))?;
+            pv.push(col);
+        }
+        Some(Info {
+            depth: WIDTH * HEIGHT - board.get_move_count(),
+            score,
+            pv,
+        })
+    }
+}
+
+/// Scores each column exactly however early the position, with the full solver
+/// and a transposition table that it keeps from move to move. Positions more
+/// than `hint::SOLVE_LIMIT` empty cells from the end are only solved weakly, as
+/// a win (1), draw (0) or loss (-1), and can still take minutes.
+pub struct Perfect {
+    search: Search,
+}
+
+// Weak scores of each column on the empty board, then of each reply to each
+// first move, which would take far too long to solve during a game.
+const OPENING: [[i32; WIDTH]; WIDTH + 1] = [
+    [-1, -1, 0, 1, 0, -1, -1],
+    [-1, 1, 1, 1, -1, 1, -1],
+    [-1, 0, 1, 0, -1, -1, -1],
+    [-1, -1, 0, 0, 0, 0, -1],
+    [-1, -1, -1, -1, -1, -1, -1],
+    [-1, 0, 0, 0, 0, -1, -1],
+    [-1, -1, -1, 0, 1, 0, -1],
+    [-1, 1, -1, 1, 1, 1, -1],
+];
+
+impl Default for Perfect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Columns from the center outwards, the order in which winning moves are
+// most likely to be found.
+const CENTER_ORDER: [usize; WIDTH] = [3, 2, 4, 1, 5, 0, 6];
+
+impl Perfect {
+    pub fn new() -> Self {
+        Self {
+            search: Search::new(Geometry::STANDARD, fullsolve::DEFAULT_TABLE_SIZE),
+        }
+    }
+
+    // Scores of every column from the opening book, for the first two moves.
+    fn opening(board: &Board) -> Option<[i32; WIDTH]> {
+        match board.get_move_count() {
+            0 => Some(OPENING[0]),
+            1 => (0..WIDTH)
+                .find(|&col| board.get_height(col) == 1)
+                .map(|col| OPENING[col + 1]),
+            _ => None,
+        }
+    }
+
+    // Whether positions with this many moves played are only solved weakly.
+    fn is_weak(moves: usize) -> bool {
+        WIDTH * HEIGHT - moves > hint::SOLVE_LIMIT
+    }
+
+    // Scores playing a valid column.
+    fn score(&mut self, position: &Position, col: usize) -> i32 {
+        let moves = position.get_move_count();
+        let weak = Perfect::is_weak(moves);
+        if position.is_winning_move(col) {
+            if weak {
+                1
+            } else {
+                (WIDTH * HEIGHT + 1 - moves) as i32 / 2
+            }
+        } else if moves + 1 == WIDTH * HEIGHT {
+            0
+        } else {
+            let mut next = *position;
+            next.play(col);
+            -self.search.solve(&next, weak)
+        }
+    }
+}
+
+impl Engine for Perfect {
+    fn name(&self) -> &'static str {
+        "perfect"
+    }
+
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        if let Some(scores) = Perfect::opening(board) {
+            return scores
+                .iter()
+                .enumerate()
+                .map(|(col, &score)| (col, score as f64))
+                .collect();
+        }
+        let position = Position::from_board(board);
+        (0..WIDTH)
+            .filter(|&col| position.can_play(col))
+            .map(|col| (col, self.score(&position, col) as f64))
+            .collect()
+    }
+
+    // Tries the columns from the center outwards and stops at the first win
+    // when solving weakly, as no column can do better, so won positions rarely
+    // need every column solved.
+    fn analyze(&mut self, board: &Board) -> Option<Info> {
+        let depth = WIDTH * HEIGHT - board.get_move_count();
+        let (col, score) = match Perfect::opening(board) {
+            Some(_) => best_move(self.score_moves(board))?,
+            None => {
+                let position = Position::from_board(board);
+                let weak = Perfect::is_weak(board.get_move_count());
+                let mut best: Option<(usize, f64)> = None;
+                for col in CENTER_ORDER
+                    .into_iter()
+                    .filter(|&col| position.can_play(col))
+                {
+                    let score = self.score(&position, col) as f64;
+                    if best.is_none_or(|(_, best_score)| score > best_score) {
+                        best = Some((col, score));
+                    }
+                    if weak && score > 0.0 {
+                        break;
+                    }
+                }
+                best?
+            }
+        };
+        Some(Info {
+            depth,
+            score,
+            pv: vec![col],
+        })
+    }
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use std::time::{Duration, Instant};
+
+    use super::{from_name, from_name_for, Engine, Exact, MonteCarlo, Perfect, ENGINE_NAMES};
+    use crate::board::{Board, MoveResult};
+    use crate::misere::Objective;
+    use crate::testset;
+
+    #[test]
+    fn engines_take_immediate_win() {
+        // X can win in columns 1, 3 and 5.
+        let board = Board::from_move_string("242512646300052352144344621036").unwrap();
+        for name in ENGINE_NAMES {
+            let mut engine = from_name(name).unwrap();
+            assert_eq!(engine.name(), name);
+            if name == "chatgpt" {
+                // The baseline scores every column the same.
+                continue;
+            }
+            assert_eq!(engine.choose_move(&board), Some(1), "{name}");
+        }
+    }
+
+    #[test]
+    fn misere_engines_avoid_four() {
+        let board = Board::from_move_string("242512646300052352144344621036").unwrap();
+        for name in ["montecarlo", "solver", "mcts", "heuristic"] {
+            let mut engine = from_name_for(name, Objective::Misere).unwrap();
+            let scores = engine.score_moves(&board);
+            let best = engine.choose_move(&board).unwrap();
+            assert!(![1, 3, 5].contains(&best), "{name} {scores:?}");
+        }
+        assert!(from_name_for("chatgpt", Objective::Misere).is_none());
+    }
+
+    #[test]
+    fn solver_returns_early_in_the_game() {
+        // Solving this exactly would take far too long, so the engine falls
+        // back to Monte Carlo.
+        let board = Board::from_move_string("3333").unwrap();
+        let start = Instant::now();
+        let mut engine = Exact::new();
+        assert!(engine.choose_move(&board).is_some());
+        let info = engine.analyze(&board).unwrap();
+        assert_eq!(info.depth, 1);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn perfect_play() {
+        let mut engine = Perfect::new();
+        assert_eq!(engine.choose_move(&Board::new()), Some(3));
+
+        // The best move keeps the result of the position.
+        let cases = testset::parse(include_str!("../testsets/begin.txt")).unwrap();
+        for case in &cases[..3] {
+            let board = Board::from_move_string(&case.moves).unwrap();
+            let (_, score) = super::best_move(engine.score_moves(&board)).unwrap();
+            assert_eq!(score as i32, case.score.signum(), "{}", case.moves);
+            let info = engine.analyze(&board).unwrap();
+            assert_eq!(info.score as i32, case.score.signum(), "{}", case.moves);
+        }
+        let board = Board::from_move_string("2425126463000523521443440002").unwrap();
+        assert_eq!(engine.score_moves(&board), Exact::new().score_moves(&board));
+    }
+
+    #[test]
+    fn analysis() {
+        let board = Board::from_move_string("242512646300052352144344621036").unwrap();
+        let info = MonteCarlo::default().analyze(&board).unwrap();
+        assert_eq!((info.depth, info.pv), (1, vec![1]));
+
+        // The solver plays out the whole line, here O's forced win after any move by X.
+        let board = Board::from_move_string("2425126463000523521443440002").unwrap();
+        let info = Exact::new().analyze(&board).unwrap();
+        assert_eq!(info.depth, 14);
+        assert_eq!(info.score, -7.0);
+        assert_eq!(info.pv, vec![1, 1]);
+        let mut next = board;
+        let results: Vec<MoveResult> = info.pv.iter().map(|&col| next.make_move(col)).collect();
+        assert_eq!(results.last(), Some(&MoveResult::WinO));
+        assert!(results[..results.len() - 1]
+            .iter()
+            .all(|&r| r == MoveResult::None));
+    }
+}
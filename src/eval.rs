@@ -0,0 +1,409 @@
+// Static evaluation with tunable weights, and an engine searching with it
+//
+// A position is described by a few features, each counted for the player to
+// move minus the same count for the opponent, and scored as their weighted
+// sum. The features look at every line of four cells on the board:
+//
+//   twos         lines holding two of the player's discs and two empty cells
+//   threes       lines holding three of the player's discs and an empty cell
+//   good_threats empty cells completing four on rows that favour the player:
+//                odd rows (counting from 1 at the bottom) for X, even for O
+//   bad_threats  empty cells completing four on the other rows
+//   center       discs in the center column
+//
+// Weights files are text with one "<feature> <weight>" per line. Blank lines
+// and lines starting with '#' are ignored, and features left out keep their
+// default weights.
+//
+// `tune` fits the weights Texel style: the evaluation is turned into a
+// winning chance with the logistic function and the weights descend the
+// squared error against known results, such as a solver-labeled test set.
+
+use std::fmt::Write;
+use std::fs;
+
+use crate::board::{Board, Cell, MoveResult, HEIGHT, WIDTH};
+use crate::engine::{self, Engine, Info};
+use crate::misere::Objective;
+
+pub const FEATURES: [&str; 5] = ["twos", "threes", "good_threats", "bad_threats", "center"];
+
+/// Default depth of the heuristic engine's search, in moves.
+pub const DEPTH: usize = 4;
+
+// Score of a won position, far above any evaluation.
+const WIN: f64 = 1e6;
+
+// Directions of the lines of four.
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Weights {
+    pub values: [f64; FEATURES.len()],
+}
+
+impl Default for Weights {
+    // Picked by hand before tuning.
+    fn default() -> Self {
+        Self {
+            values: [0.05, 0.2, 0.5, 0.2, 0.05],
+        }
+    }
+}
+
+impl Weights {
+    /// Scores the position for the player to move.
+    pub fn evaluate(&self, board: &Board) -> f64 {
+        dot(&self.values, &features(board))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in FEATURES.iter().zip(self.values) {
+            writeln!(text, "{name} {value}").unwrap();
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Weights, String> {
+        let mut weights = Weights::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(value), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(format!("line {}: expected '<feature> <weight>'", i + 1));
+            };
+            let index = FEATURES
+                .iter()
+                .position(|feature| *feature == name)
+                .ok_or(format!("line {}: unknown feature '{name}'", i + 1))?;
+            weights.values[index] = value
+                .parse()
+                .map_err(|_| format!("line {}: invalid weight '{value}'", i + 1))?;
+        }
+        Ok(weights)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn load(path: &str) -> Result<Weights, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Weights::parse(&text).map_err(|e| format!("{path}: {e}"))
+    }
+}
+
+fn dot(weights: &[f64], features: &[f64]) -> f64 {
+    weights.iter().zip(features).map(|(w, f)| w * f).sum()
+}
+
+/// Counts the features of the position for the player to move.
+pub fn features(board: &Board) -> [f64; FEATURES.len()] {
+    let player = board.get_current_player();
+    let mut counts = [0.0; FEATURES.len()];
+    // Cells completing four for X and for O, bit col * HEIGHT + y.
+    let mut threats = [0u64; 2];
+
+    for col in 0..WIDTH {
+        for y in 0..HEIGHT {
+            for (dx, dy) in DIRECTIONS {
+                let end = (col as isize + 3 * dx, y as isize + 3 * dy);
+                if !(0..WIDTH as isize).contains(&end.0) || !(0..HEIGHT as isize).contains(&end.1) {
+                    continue;
+                }
+                let cells: Vec<(usize, usize)> = (0..4)
+                    .map(|step| {
+                        (
+                            (col as isize + step * dx) as usize,
+                            (y as isize + step * dy) as usize,
+                        )
+                    })
+                    .collect();
+                let count = |cell| {
+                    cells
+                        .iter()
+                        .filter(|&&(c, y)| board.get(c, y) == cell)
+                        .count()
+                };
+                let (x, o) = (count(Cell::X), count(Cell::O));
+                let (owner, discs) = match (x, o) {
+                    (n, 0) if n >= 2 => (Cell::X, n),
+                    (0, n) if n >= 2 => (Cell::O, n),
+                    _ => continue,
+                };
+                let sign = if owner == player { 1.0 } else { -1.0 };
+                counts[discs - 2] += sign;
+                if discs == 3 {
+                    let &(c, y) = cells
+                        .iter()
+                        .find(|&&(c, y)| board.get(c, y) == Cell::Empty)
+                        .unwrap();
+                    threats[(owner == Cell::O) as usize] |= 1 << (c * HEIGHT + y);
+                }
+            }
+        }
+    }
+
+    for (index, owner) in [Cell::X, Cell::O].into_iter().enumerate() {
+        let sign = if owner == player { 1.0 } else { -1.0 };
+        for cell in 0..WIDTH * HEIGHT {
+            if threats[index] >> cell & 1 == 0 {
+                continue;
+            }
+            // Row y counts from 0, so X's odd rows have even y.
+            let good = (cell % HEIGHT).is_multiple_of(2) == (owner == Cell::X);
+            counts[if good { 2 } else { 3 }] += sign;
+        }
+        let center = (0..HEIGHT)
+            .filter(|&y| board.get(WIDTH / 2, y) == owner)
+            .count();
+        counts[4] += sign * center as f64;
+    }
+    counts
+}
+
+/// A position with a known result, from 0 for a loss to 1 for a win for the
+/// player to move.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Labeled {
+    pub features: [f64; FEATURES.len()],
+    pub result: f64,
+}
+
+impl Labeled {
+    /// Labels a position by the sign of its solver score.
+    pub fn from_score(board: &Board, score: i32) -> Self {
+        Self {
+            features: features(board),
+            result: (score.signum() as f64 + 1.0) / 2.0,
+        }
+    }
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Mean squared error of the winning chances the weights predict.
+pub fn error(weights: &Weights, positions: &[Labeled]) -> f64 {
+    let total: f64 = positions
+        .iter()
+        .map(|position| {
+            let predicted = sigmoid(dot(&weights.values, &position.features));
+            (position.result - predicted).powi(2)
+        })
+        .sum();
+    total / positions.len().max(1) as f64
+}
+
+/// Fits the weights to the positions by gradient descent and returns them.
+pub fn tune(start: &Weights, positions: &[Labeled], iterations: usize, rate: f64) -> Weights {
+    let mut weights = *start;
+    for _ in 0..iterations {
+        let mut gradient = [0.0; FEATURES.len()];
+        for position in positions {
+            let predicted = sigmoid(dot(&weights.values, &position.features));
+            let slope = -2.0 * (position.result - predicted) * predicted * (1.0 - predicted);
+            for (g, f) in gradient.iter_mut().zip(position.features) {
+                *g += slope * f;
+            }
+        }
+        for (w, g) in weights.values.iter_mut().zip(gradient) {
+            *w -= rate * g / positions.len().max(1) as f64;
+        }
+    }
+    weights
+}
+
+/// Searches a few moves ahead with alpha-beta and scores the positions
+/// reached with the static evaluation. Under misère rules completing four
+/// loses and the evaluation changes sign, as threats become cells to avoid.
+pub struct Heuristic {
+    weights: Weights,
+    depth: usize,
+    objective: Objective,
+}
+
+impl Default for Heuristic {
+    fn default() -> Self {
+        Self::new(Weights::default())
+    }
+}
+
+impl Heuristic {
+    pub fn new(weights: Weights) -> Self {
+        Self::with_objective(weights, Objective::Standard)
+    }
+
+    pub fn with_objective(weights: Weights, objective: Objective) -> Self {
+        Self {
+            weights,
+            depth: DEPTH,
+            objective,
+        }
+    }
+
+    // Scores the position for the player to move without searching.
+    fn evaluate(&self, board: &Board) -> f64 {
+        match self.objective {
+            Objective::Standard => self.weights.evaluate(board),
+            Objective::Misere => -self.weights.evaluate(board),
+        }
+    }
+
+    // Score of completing four with depth moves left to search, so sooner
+    // wins score higher and sooner losses lower.
+    fn four(&self, depth: usize) -> f64 {
+        match self.objective {
+            Objective::Standard => WIN + depth as f64,
+            Objective::Misere => -(WIN + depth as f64),
+        }
+    }
+
+    pub fn get_weights(&self) -> &Weights {
+        &self.weights
+    }
+
+    // Scores the position for the player to move, looking depth moves ahead.
+    fn search(&self, board: &Board, depth: usize, mut alpha: f64, beta: f64) -> f64 {
+        if depth == 0 {
+            return self.evaluate(board);
+        }
+        let mut best = f64::NEG_INFINITY;
+        for col in 0..WIDTH {
+            let mut next = *board;
+            let score = match next.make_move(col) {
+                MoveResult::Illegal => continue,
+                MoveResult::Draw => 0.0,
+                MoveResult::WinX | MoveResult::WinO => self.four(depth),
+                MoveResult::None => -self.search(&next, depth - 1, -beta, -alpha),
+            };
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    // Scores each valid column, searching depth moves ahead including it.
+    fn scores(&self, board: &Board, depth: usize) -> Vec<(usize, f64)> {
+        let mut scores = Vec::new();
+        for col in 0..WIDTH {
+            let mut next = *board;
+            let score = match next.make_move(col) {
+                MoveResult::Illegal => continue,
+                MoveResult::Draw => 0.0,
+                MoveResult::WinX | MoveResult::WinO => self.four(depth),
+                MoveResult::None => {
+                    -self.search(&next, depth - 1, f64::NEG_INFINITY, f64::INFINITY)
+                }
+            };
+            scores.push((col, score));
+        }
+        scores
+    }
+}
+
+impl Engine for Heuristic {
+    fn name(&self) -> &'static str {
+        "heuristic"
+    }
+
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        self.scores(board, self.depth)
+    }
+
+    fn analyze(&mut self, board: &Board) -> Option<Info> {
+        self.analyze_with_progress(board, &mut |_| {})
+    }
+
+    // Searches one move deeper at a time, reporting each depth.
+    fn analyze_with_progress(
+        &mut self,
+        board: &Board,
+        progress: &mut dyn FnMut(&Info),
+    ) -> Option<Info> {
+        let mut info = None;
+        for depth in 1..=self.depth {
+            let (col, score) = engine::best_move(self.scores(board, depth))?;
+            if let Some(info) = &info {
+                progress(info);
+            }
+            info = Some(Info {
+                depth,
+                score,
+                pv: vec![col],
+            });
+        }
+        info
+    }
+}
+
+#[cfg(test)]
+mod eval_tests {
+    use std::fs;
+
+    use super::{error, features, tune, Heuristic, Labeled, Weights};
+    use crate::board::Board;
+    use crate::engine::Engine;
+    use crate::testset;
+
+    #[test]
+    fn counts_features() {
+        // X has three in a row on the bottom, threatening column 3, and O two
+        // stacked in column 6.
+        let board = Board::from_move_string("06162").unwrap();
+        // O to move: twos, threes, good threats, bad threats, center.
+        assert_eq!(features(&board), [0.0, -1.0, -1.0, 0.0, 0.0]);
+        assert_eq!(features(&Board::new()), [0.0; 5]);
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let weights = Weights {
+            values: [0.5, -1.0, 2.25, 0.0, 3.0],
+        };
+        assert_eq!(Weights::parse(&weights.to_text()).unwrap(), weights);
+        let partial = Weights::parse("# tuned\ncenter 1\n").unwrap();
+        assert_eq!(partial.values[4], 1.0);
+        assert_eq!(partial.values[0], Weights::default().values[0]);
+        assert!(Weights::parse("corners 1").is_err());
+        assert!(Weights::parse("center").is_err());
+    }
+
+    #[test]
+    fn tuning_lowers_the_error() {
+        let text = fs::read_to_string("testsets/middle.txt").unwrap();
+        let positions: Vec<Labeled> = testset::parse(&text)
+            .unwrap()
+            .iter()
+            .map(|case| {
+                Labeled::from_score(&Board::from_move_string(&case.moves).unwrap(), case.score)
+            })
+            .collect();
+        let start = Weights::default();
+        let tuned = tune(&start, &positions, 200, 1.0);
+        assert!(error(&tuned, &positions) < error(&start, &positions));
+    }
+
+    #[test]
+    fn engine_blocks_a_threat() {
+        let board = Board::from_move_string("06162").unwrap();
+        assert_eq!(Heuristic::default().choose_move(&board), Some(3));
+
+        // Each shallower depth is reported on the way.
+        let mut depths = Vec::new();
+        let info = Heuristic::default()
+            .analyze_with_progress(&board, &mut |info| depths.push(info.depth))
+            .unwrap();
+        assert_eq!(depths, [1, 2, 3]);
+        assert_eq!((info.depth, info.pv), (4, vec![3]));
+    }
+}
@@ -0,0 +1,704 @@
+// Solves the starting position of boards of any size
+//
+// The solver in solver.rs only knows the standard board and keeps nothing
+// between positions. Solving from the empty board needs more: this solver
+// takes the board size at run time, remembers upper bounds in a transposition
+// table, never searches moves that hand the opponent a win, and finds the
+// score with a series of null window searches that narrow its bounds.
+//
+// A full solve scores every first move. Bounds are reported after each null
+// window search and can be saved to a checkpoint, so a long run that is
+// stopped can be resumed without repeating the finished searches.
+//
+// Scores have the same meaning as in solver.rs. A weak solve only tells a win
+// (1), a draw (0) and a loss (-1) apart, which is much faster.
+
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::board::{Board, HEIGHT, WIDTH};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Geometry {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Geometry {
+    pub const STANDARD: Geometry = Geometry {
+        width: 7,
+        height: 6,
+    };
+
+    /// Parses a size such as "7x6", for 7 columns of 6 cells.
+    pub fn parse(size: &str) -> Result<Geometry, String> {
+        let invalid = || format!("Invalid board size: {size} (expected <width>x<height>)");
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let geometry = Geometry {
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+        };
+        // Each column takes one bit more than its height, and lines are found
+        // by shifting up to three columns over.
+        let Geometry { width, height } = geometry;
+        if width == 0 || height == 0 || height > 16 || width * (height + 1) > 64 {
+            return Err(format!("Unsupported board size: {size}"));
+        }
+        Ok(geometry)
+    }
+
+    pub fn cells(self) -> usize {
+        self.width * self.height
+    }
+
+    // Bit of the bottom cell of every column.
+    fn bottom_mask(self) -> u64 {
+        (0..self.width).fold(0, |mask, col| mask | 1 << (col * (self.height + 1)))
+    }
+
+    fn column_mask(self, col: usize) -> u64 {
+        ((1 << self.height) - 1) << (col * (self.height + 1))
+    }
+
+    // Columns from the center outwards, the usual best order to try moves.
+    fn center_order(self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.width).collect();
+        order.sort_by_key(|&col| (2 * col).abs_diff(self.width - 1));
+        order
+    }
+}
+
+impl std::fmt::Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// A position on a board of any size, stored as bitboards with a spare bit
+/// above each column.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Position {
+    geometry: Geometry,
+
+    // Pieces of the player to move.
+    current: u64,
+
+    // Every piece on the board.
+    mask: u64,
+
+    moves: usize,
+}
+
+impl Position {
+    pub fn new(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            current: 0,
+            mask: 0,
+            moves: 0,
+        }
+    }
+
+    /// Plays a string of column digits, returning None if a move is invalid
+    /// or the game ends.
+    pub fn from_moves(geometry: Geometry, moves: &str) -> Option<Position> {
+        let mut position = Position::new(geometry);
+        for c in moves.chars() {
+            let col = c.to_digit(10)? as usize;
+            if col >= geometry.width || !position.can_play(col) || position.is_winning_move(col) {
+                return None;
+            }
+            position.play(col);
+        }
+        (position.moves < geometry.cells()).then_some(position)
+    }
+
+    /// Converts a board of the standard size.
+    pub fn from_board(board: &Board) -> Position {
+        let mut position = Position::new(Geometry::STANDARD);
+        for col in 0..WIDTH {
+            for row in 0..board.get_height(col) {
+                let bit = 1 << (row + col * (HEIGHT + 1));
+                position.mask |= bit;
+                if board.get(col, row) == board.get_current_player() {
+                    position.current |= bit;
+                }
+            }
+        }
+        position.moves = board.get_move_count();
+        position
+    }
+
+    pub fn get_geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn get_move_count(&self) -> usize {
+        self.moves
+    }
+
+    pub fn can_play(&self, col: usize) -> bool {
+        self.mask & self.top_cell(col) == 0
+    }
+
+    pub fn play(&mut self, col: usize) {
+        self.play_bit((self.mask + self.bottom_cell(col)) & self.geometry.column_mask(col));
+    }
+
+    pub fn is_winning_move(&self, col: usize) -> bool {
+        self.winning_cells() & self.possible() & self.geometry.column_mask(col) != 0
+    }
+
+    /// Returns a key that identifies the position, like `Board::key`.
+    pub fn key(&self) -> u64 {
+        self.current + self.mask
+    }
+
+    fn play_bit(&mut self, bit: u64) {
+        self.current ^= self.mask;
+        self.mask |= bit;
+        self.moves += 1;
+    }
+
+    fn bottom_cell(&self, col: usize) -> u64 {
+        1 << (col * (self.geometry.height + 1))
+    }
+
+    fn top_cell(&self, col: usize) -> u64 {
+        1 << (self.geometry.height - 1 + col * (self.geometry.height + 1))
+    }
+
+    // Empty cells that can be played now.
+    fn possible(&self) -> u64 {
+        let board = self.geometry.bottom_mask() * ((1 << self.geometry.height) - 1);
+        (self.mask + self.geometry.bottom_mask()) & board
+    }
+
+    // Empty cells that would complete four in a row for the player to move.
+    fn winning_cells(&self) -> u64 {
+        winning_cells(self.geometry, self.current, self.mask)
+    }
+
+    fn opponent_winning_cells(&self) -> u64 {
+        winning_cells(self.geometry, self.current ^ self.mask, self.mask)
+    }
+
+    fn can_win_next(&self) -> bool {
+        self.winning_cells() & self.possible() != 0
+    }
+
+    // Moves that do not let the opponent win straight away, assuming the
+    // player to move cannot win now. Returns no moves if every move loses.
+    fn non_losing_moves(&self) -> u64 {
+        let mut possible = self.possible();
+        let opponent_wins = self.opponent_winning_cells();
+        let forced = possible & opponent_wins;
+        if forced != 0 {
+            if forced & (forced - 1) != 0 {
+                // The opponent has two threats and we can only block one.
+                return 0;
+            }
+            possible = forced;
+        }
+        // Never play below a cell where the opponent would win.
+        possible & !(opponent_wins >> 1)
+    }
+
+    // Number of threats a move creates, to search the most forcing moves first.
+    fn move_score(&self, bit: u64) -> u32 {
+        winning_cells(self.geometry, self.current | bit, self.mask).count_ones()
+    }
+}
+
+// Empty cells that would complete four in a row for the given pieces.
+fn winning_cells(geometry: Geometry, position: u64, mask: u64) -> u64 {
+    let board = geometry.bottom_mask() * ((1 << geometry.height) - 1);
+    let mut cells = 0;
+    // Vertical: only the cell above three pieces.
+    cells |= (position << 1) & (position << 2) & (position << 3);
+    for shift in [geometry.height + 1, geometry.height, geometry.height + 2] {
+        let pairs = (position << shift) & (position << (2 * shift));
+        cells |= pairs & (position << (3 * shift));
+        cells |= pairs & (position >> shift);
+        let pairs = (position >> shift) & (position >> (2 * shift));
+        cells |= pairs & (position << shift);
+        cells |= pairs & (position >> (3 * shift));
+    }
+    cells & board & !mask
+}
+
+// Transposition table of upper bounds, indexed by key modulo its size. Each
+// slot keeps the last position stored in it.
+struct Table {
+    keys: Vec<u64>,
+    values: Vec<i8>,
+}
+
+impl Table {
+    fn new(size: usize) -> Self {
+        Self {
+            keys: vec![u64::MAX; size],
+            values: vec![0; size],
+        }
+    }
+
+    fn get(&self, key: u64) -> Option<i32> {
+        let index = (key % self.keys.len() as u64) as usize;
+        (self.keys[index] == key).then(|| self.values[index] as i32)
+    }
+
+    fn put(&mut self, key: u64, value: i32) {
+        let index = (key % self.keys.len() as u64) as usize;
+        self.keys[index] = key;
+        self.values[index] = value as i8;
+    }
+}
+
+/// Default number of transposition table entries, a prime near 8 million
+/// taking about 72 MB.
+pub const DEFAULT_TABLE_SIZE: usize = 8_388_617;
+
+// Nodes between calls to the heartbeat.
+const HEARTBEAT_NODES: u64 = 1 << 26;
+
+pub struct Search {
+    geometry: Geometry,
+    table: Table,
+    order: Vec<usize>,
+    node_count: u64,
+
+    // Called with the node count while a long search runs.
+    heartbeat: Option<Box<dyn FnMut(u64)>>,
+}
+
+impl Search {
+    pub fn new(geometry: Geometry, table_size: usize) -> Self {
+        Self {
+            geometry,
+            table: Table::new(table_size),
+            order: geometry.center_order(),
+            node_count: 0,
+            heartbeat: None,
+        }
+    }
+
+    pub fn get_node_count(&self) -> u64 {
+        self.node_count
+    }
+
+    /// Sets a function to call every few million nodes, to show that a long
+    /// search is still making progress.
+    pub fn set_heartbeat(&mut self, heartbeat: Box<dyn FnMut(u64)>) {
+        self.heartbeat = Some(heartbeat);
+    }
+
+    /// Returns the exact score of the position for the player to move, or only
+    /// its sign if `weak` is set.
+    pub fn solve(&mut self, position: &Position, weak: bool) -> i32 {
+        let (min, max) = self.initial_bounds(position, weak);
+        self.narrow(position, min, max, &mut |_, _, _| {})
+    }
+
+    // Bounds on the score before searching.
+    fn initial_bounds(&self, position: &Position, weak: bool) -> (i32, i32) {
+        let cells = self.geometry.cells() as i32;
+        let moves = position.moves as i32;
+        if position.can_win_next() {
+            let score = (cells + 1 - moves) / 2;
+            return if weak { (1, 1) } else { (score, score) };
+        }
+        if weak {
+            (-1, 1)
+        } else {
+            (-(cells - moves) / 2, (cells + 1 - moves) / 2)
+        }
+    }
+
+    // Narrows bounds on the score with null window searches until they meet,
+    // reporting the bounds and the node count after each search.
+    fn narrow(
+        &mut self,
+        position: &Position,
+        mut min: i32,
+        mut max: i32,
+        report: &mut dyn FnMut(i32, i32, u64),
+    ) -> i32 {
+        if position.can_win_next() {
+            return max;
+        }
+        while min < max {
+            // Search closer to zero first, where most scores are.
+            let mut middle = min + (max - min) / 2;
+            if middle <= 0 && min / 2 < middle {
+                middle = min / 2;
+            } else if middle >= 0 && max / 2 > middle {
+                middle = max / 2;
+            }
+            let score = self.negamax(position, middle, middle + 1);
+            if score <= middle {
+                max = score.max(min);
+            } else {
+                min = score.min(max);
+            }
+            report(min, max, self.node_count);
+        }
+        min
+    }
+
+    // Like `Solver::negamax`, for a position where the player to move cannot
+    // win straight away.
+    fn negamax(&mut self, position: &Position, mut alpha: i32, mut beta: i32) -> i32 {
+        self.node_count += 1;
+        if self.node_count.is_multiple_of(HEARTBEAT_NODES) {
+            if let Some(heartbeat) = &mut self.heartbeat {
+                heartbeat(self.node_count);
+            }
+        }
+        let cells = self.geometry.cells() as i32;
+        let moves = position.moves as i32;
+        let next = position.non_losing_moves();
+        if next == 0 {
+            return -(cells - moves) / 2;
+        }
+        if moves >= cells - 2 {
+            return 0;
+        }
+
+        // The opponent cannot win with their next move.
+        let min = -(cells - 2 - moves) / 2;
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        // We cannot win with this move.
+        let max = match self.table.get(position.key()) {
+            Some(bound) => bound,
+            None => (cells - 1 - moves) / 2,
+        };
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        let mut candidates: Vec<(u64, u32)> = Vec::with_capacity(self.geometry.width);
+        for &col in &self.order {
+            let bit = next & self.geometry.column_mask(col);
+            if bit != 0 {
+                candidates.push((bit, position.move_score(bit)));
+            }
+        }
+        // Stable, so equal scores stay in center order.
+        candidates.sort_by_key(|&(_, score)| Reverse(score));
+        for (bit, _) in candidates {
+            let mut child = *position;
+            child.play_bit(bit);
+            let score = -self.negamax(&child, -beta, -alpha);
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        self.table.put(position.key(), alpha);
+        alpha
+    }
+}
+
+/// What is known so far about the first moves of a full solve.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Checkpoint {
+    pub geometry: Geometry,
+    pub weak: bool,
+
+    // Score of each first move, for the first player, once it is solved.
+    pub scores: Vec<Option<i32>>,
+
+    // Bounds on the score of the first move being solved, as (column, min, max).
+    pub bounds: Option<(usize, i32, i32)>,
+}
+
+const CHECKPOINT_HEADER: &str = "# connect4 solve checkpoint";
+
+impl Checkpoint {
+    pub fn new(geometry: Geometry, weak: bool) -> Self {
+        Self {
+            geometry,
+            weak,
+            scores: vec![None; geometry.width],
+            bounds: None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.scores.iter().all(Option::is_some)
+    }
+
+    /// Returns the score of the empty board once every first move is solved.
+    pub fn get_value(&self) -> Option<i32> {
+        self.scores
+            .iter()
+            .copied()
+            .collect::<Option<Vec<i32>>>()?
+            .into_iter()
+            .max()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{CHECKPOINT_HEADER}\nsize {}\n", self.geometry);
+        writeln!(text, "mode {}", if self.weak { "weak" } else { "strong" }).unwrap();
+        for (col, score) in self.scores.iter().enumerate() {
+            if let Some(score) = score {
+                writeln!(text, "column {col} score {score}").unwrap();
+            }
+        }
+        if let Some((col, min, max)) = self.bounds {
+            writeln!(text, "column {col} bounds {min} {max}").unwrap();
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Checkpoint, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(CHECKPOINT_HEADER) {
+            return Err("not a solve checkpoint".to_string());
+        }
+        let mut checkpoint: Option<Checkpoint> = None;
+        let mut geometry = None;
+        for (i, line) in lines.enumerate() {
+            let invalid = || format!("line {}: invalid line '{line}'", i + 2);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["size", size] => geometry = Some(Geometry::parse(size)?),
+                ["mode", mode @ ("weak" | "strong")] => {
+                    let geometry = geometry.ok_or_else(invalid)?;
+                    checkpoint = Some(Checkpoint::new(geometry, *mode == "weak"));
+                }
+                ["column", col, kind, numbers @ ..] => {
+                    let checkpoint = checkpoint.as_mut().ok_or_else(invalid)?;
+                    let col: usize = col.parse().map_err(|_| invalid())?;
+                    if col >= checkpoint.geometry.width {
+                        return Err(invalid());
+                    }
+                    let numbers: Vec<i32> = numbers
+                        .iter()
+                        .map(|n| n.parse().map_err(|_| invalid()))
+                        .collect::<Result<_, _>>()?;
+                    match (*kind, numbers.as_slice()) {
+                        ("score", &[score]) => checkpoint.scores[col] = Some(score),
+                        ("bounds", &[min, max]) => checkpoint.bounds = Some((col, min, max)),
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        checkpoint.ok_or("checkpoint is missing its size or mode".to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        // Write a new file first so a crash never leaves half a checkpoint.
+        let temp = format!("{path}.tmp");
+        fs::write(&temp, self.to_text()).map_err(|e| format!("{temp}: {e}"))?;
+        fs::rename(&temp, path).map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn load(path: &str) -> Result<Checkpoint, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Checkpoint::parse(&text).map_err(|e| format!("{path}: {e}"))
+    }
+}
+
+/// Progress of a full solve, reported along with the updated checkpoint.
+pub struct Progress {
+    pub column: usize,
+
+    // Bounds on the score of the first move in the column, equal once it is solved.
+    pub min: i32,
+    pub max: i32,
+
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+/// Solves every first move that the checkpoint does not already score,
+/// center columns first, and returns the score of the empty board.
+pub fn solve_start(
+    search: &mut Search,
+    checkpoint: &mut Checkpoint,
+    report: &mut dyn FnMut(&Progress, &Checkpoint),
+) -> i32 {
+    let geometry = checkpoint.geometry;
+    let start = Instant::now();
+    for col in geometry.center_order() {
+        if checkpoint.scores[col].is_some() {
+            continue;
+        }
+        let mut position = Position::new(geometry);
+        position.play(col);
+
+        // Search the position after the move, from the opponent's side.
+        let (mut min, mut max) = search.initial_bounds(&position, checkpoint.weak);
+        if let Some((bounds_col, low, high)) = checkpoint.bounds {
+            if bounds_col == col {
+                (min, max) = (-high, -low);
+            }
+        }
+        let score = -search.narrow(&position, min, max, &mut |min, max, nodes| {
+            if min == max {
+                // Reported below as solved.
+                return;
+            }
+            checkpoint.bounds = Some((col, -max, -min));
+            let progress = Progress {
+                column: col,
+                min: -max,
+                max: -min,
+                nodes,
+                elapsed: start.elapsed(),
+            };
+            report(&progress, checkpoint);
+        });
+        checkpoint.scores[col] = Some(score);
+        checkpoint.bounds = None;
+        let progress = Progress {
+            column: col,
+            min: score,
+            max: score,
+            nodes: search.get_node_count(),
+            elapsed: start.elapsed(),
+        };
+        report(&progress, checkpoint);
+    }
+    checkpoint.get_value().unwrap()
+}
+
+#[cfg(test)]
+mod fullsolve_tests {
+    use std::collections::HashMap;
+
+    use super::{solve_start, Checkpoint, Geometry, Position, Search};
+    use crate::board::Board;
+    use crate::testset;
+
+    // Scores a position by trying every line, for checking the search.
+    fn minimax(position: &Position, scores: &mut HashMap<u64, i32>) -> i32 {
+        if let Some(&score) = scores.get(&position.key()) {
+            return score;
+        }
+        let geometry = position.get_geometry();
+        let moves = position.get_move_count() as i32;
+        let mut best = None;
+        for col in 0..geometry.width {
+            if !position.can_play(col) {
+                continue;
+            }
+            let score = if position.is_winning_move(col) {
+                (geometry.cells() as i32 + 1 - moves) / 2
+            } else if position.get_move_count() + 1 == geometry.cells() {
+                0
+            } else {
+                let mut next = *position;
+                next.play(col);
+                -minimax(&next, scores)
+            };
+            best = best.max(Some(score));
+        }
+        let best = best.unwrap_or(0);
+        scores.insert(position.key(), best);
+        best
+    }
+
+    #[test]
+    fn parse_geometry() {
+        assert_eq!(Geometry::parse("7x6"), Ok(Geometry::STANDARD));
+        assert_eq!(Geometry::STANDARD.to_string(), "7x6");
+        assert!(Geometry::parse("7").is_err());
+        assert!(Geometry::parse("0x6").is_err());
+        assert!(Geometry::parse("9x7").is_err());
+    }
+
+    #[test]
+    fn matches_the_solver() {
+        let cases = testset::parse(include_str!("../testsets/end.txt")).unwrap();
+        let mut search = Search::new(Geometry::STANDARD, 1 << 16);
+        for case in &cases[..20] {
+            let position = Position::from_moves(Geometry::STANDARD, &case.moves).unwrap();
+            let board = Board::from_move_string(&case.moves).unwrap();
+            assert_eq!(Position::from_board(&board), position);
+            assert_eq!(search.solve(&position, false), case.score, "{}", case.moves);
+            assert_eq!(search.solve(&position, true), case.score.signum());
+        }
+    }
+
+    #[test]
+    fn small_boards() {
+        let geometry = Geometry::parse("4x4").unwrap();
+        let mut scores = HashMap::new();
+        let expected = minimax(&Position::new(geometry), &mut scores);
+        let mut search = Search::new(geometry, 1 << 12);
+        let mut checkpoint = Checkpoint::new(geometry, false);
+        assert_eq!(
+            solve_start(&mut search, &mut checkpoint, &mut |_, _| {}),
+            expected
+        );
+        for col in 0..geometry.width {
+            let mut next = Position::new(geometry);
+            next.play(col);
+            assert_eq!(checkpoint.scores[col], Some(-minimax(&next, &mut scores)));
+        }
+        let mut weak = Checkpoint::new(geometry, true);
+        let value = solve_start(&mut search, &mut weak, &mut |_, _| {});
+        assert_eq!(value, expected.signum());
+
+        // Trying every line from the start of larger boards takes too long.
+        for (size, moves) in [("5x4", "220143"), ("4x5", "112230")] {
+            let geometry = Geometry::parse(size).unwrap();
+            let position = Position::from_moves(geometry, moves).unwrap();
+            let expected = minimax(&position, &mut HashMap::new());
+            let mut search = Search::new(geometry, 1 << 12);
+            assert_eq!(search.solve(&position, false), expected, "{size}");
+        }
+    }
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let geometry = Geometry::parse("5x4").unwrap();
+        let mut saved = Vec::new();
+        let mut search = Search::new(geometry, 1 << 12);
+        let mut checkpoint = Checkpoint::new(geometry, false);
+        let value = solve_start(&mut search, &mut checkpoint, &mut |_, checkpoint| {
+            saved.push(checkpoint.clone());
+        });
+        assert!(checkpoint.is_complete());
+        assert_eq!(checkpoint.get_value(), Some(value));
+
+        // Stop part way through a first move, after another is solved.
+        let stopped = saved
+            .iter()
+            .find(|c| c.bounds.is_some() && c.scores.iter().any(Option::is_some))
+            .unwrap();
+        let mut resumed = Checkpoint::parse(&stopped.to_text()).unwrap();
+        assert_eq!(&resumed, stopped);
+        assert!(!resumed.is_complete());
+        let mut search = Search::new(geometry, 1 << 12);
+        assert_eq!(
+            solve_start(&mut search, &mut resumed, &mut |_, _| {}),
+            value
+        );
+        assert_eq!(resumed.scores, checkpoint.scores);
+
+        assert!(Checkpoint::parse("size 5x4").is_err());
+        let text = "# connect4 solve checkpoint\nsize 5x4\nmode weak\ncolumn 9 score 1\n";
+        assert!(Checkpoint::parse(text).is_err());
+        let text = "# connect4 solve checkpoint\nsize 5x4\nmode weak\ncolumn 3\n";
+        assert!(Checkpoint::parse(text).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+// Tracks a game in progress along with its move history
+
+use crate::board::{Board, MoveResult};
+use crate::setup::Setup;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Game {
+    setup: Setup,
+    board: Board,
+
+    // Columns played so far, in order.
+    moves: Vec<usize>,
+
+    // Result of the last move, which tells whether the game is over.
+    result: MoveResult,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self::from_setup(Setup::standard())
+    }
+
+    pub fn from_setup(setup: Setup) -> Self {
+        Self {
+            setup,
+            board: *setup.get_board(),
+            moves: Vec::new(),
+            result: MoveResult::None,
+        }
+    }
+
+    pub fn get_setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    pub fn get_board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn get_moves(&self) -> &[usize] {
+        &self.moves
+    }
+
+    /// Returns the result of the last move, or `MoveResult::None` if no move was made.
+    pub fn get_result(&self) -> MoveResult {
+        self.result
+    }
+
+    pub fn is_over(&self) -> bool {
+        matches!(
+            self.result,
+            MoveResult::WinX | MoveResult::WinO | MoveResult::Draw
+        )
+    }
+
+    /// Plays the given column for the current player, who moves again while
+    /// any handicap lasts.
+    /// Returns `MoveResult::Illegal`, leaving the game unchanged, if the game is
+    /// already over or the column is full.
+    pub fn make_move(&mut self, col: usize) -> MoveResult {
+        if self.is_over() || !self.board.is_valid_move(col) {
+            return MoveResult::Illegal;
+        }
+        self.result = self.board.make_move(col);
+        if self.moves.len() < self.setup.get_handicap() && self.result == MoveResult::None {
+            self.board.pass();
+        }
+        self.moves.push(col);
+        self.result
+    }
+
+    /// Takes back the last move, returning its column.
+    pub fn undo(&mut self) -> Option<usize> {
+        let col = self.moves.pop()?;
+        if self.moves.len() < self.setup.get_handicap() && !self.is_over() {
+            self.board.pass();
+        }
+        self.board.undo(col);
+        // No move before the last one could have ended the game.
+        self.result = MoveResult::None;
+        Some(col)
+    }
+
+    /// Returns the moves played so far as a string of column digits.
+    pub fn get_move_string(&self) -> String {
+        self.moves.iter().map(|col| col.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod game_tests {
+    use super::Game;
+    use crate::board::{Board, Cell, MoveResult};
+    use crate::setup::Setup;
+
+    #[test]
+    fn undo_restores_position() {
+        let mut game = Game::new();
+        for col in [3, 3, 2, 4] {
+            assert_eq!(game.make_move(col), MoveResult::None);
+        }
+        assert_eq!(game.get_move_string(), "3324");
+        assert_eq!(game.undo(), Some(4));
+        assert_eq!(game.get_move_string(), "332");
+        assert_eq!(game.get_board().get(4, 0), Cell::Empty);
+        assert_eq!(game.get_board().get_current_player(), Cell::O);
+
+        assert_eq!(game.undo(), Some(2));
+        assert_eq!(game.undo(), Some(3));
+        assert_eq!(game.undo(), Some(3));
+        assert_eq!(game.undo(), None);
+    }
+
+    #[test]
+    fn no_moves_after_win() {
+        let mut game = Game::new();
+        for col in [0, 1, 0, 1, 0, 1] {
+            game.make_move(col);
+        }
+        assert_eq!(game.make_move(0), MoveResult::WinX);
+        assert!(game.is_over());
+        assert_eq!(game.make_move(2), MoveResult::Illegal);
+        assert_eq!(game.get_moves().len(), 7);
+
+        assert_eq!(game.undo(), Some(0));
+        assert!(!game.is_over());
+        assert_eq!(game.make_move(2), MoveResult::None);
+    }
+
+    #[test]
+    fn handicap() {
+        // X drops two extra discs, then the players take turns.
+        let mut game = Game::from_setup(Setup::new(Board::new(), 2));
+        for col in [3, 2, 4] {
+            assert_eq!(game.get_board().get_current_player(), Cell::X);
+            assert_eq!(game.make_move(col), MoveResult::None);
+        }
+        assert_eq!(game.get_board().get_current_player(), Cell::O);
+        game.make_move(3);
+        assert_eq!(game.get_board().get_current_player(), Cell::X);
+        assert_eq!(game.make_move(1), MoveResult::WinX);
+
+        while game.undo().is_some() {}
+        assert_eq!(game, Game::from_setup(Setup::new(Board::new(), 2)));
+    }
+}
@@ -0,0 +1,166 @@
+// Recommends a move and labels the columns whose outcome can be proven
+
+use crate::board::{Board, MoveResult, HEIGHT, WIDTH};
+use crate::engine::Engine;
+use crate::misere::Objective;
+use crate::solver::Solver;
+
+/// Positions with at most this many empty cells are quick enough to solve exactly.
+pub const SOLVE_LIMIT: usize = 14;
+
+/// Outcome of a move for the player making it, assuming perfect play afterwards.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    pub fn from_score(score: i32) -> Outcome {
+        match score {
+            s if s > 0 => Outcome::Win,
+            0 => Outcome::Draw,
+            _ => Outcome::Loss,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Win => "winning",
+            Outcome::Draw => "drawing",
+            Outcome::Loss => "losing",
+        }
+    }
+}
+
+pub struct Hint {
+    // Column recommended by the engine.
+    pub best_move: Option<usize>,
+
+    // Proven outcome of playing each column, or `None` if the column is full
+    // or its outcome is unknown.
+    pub outcomes: [Option<Outcome>; WIDTH],
+}
+
+pub fn hint(engine: &mut dyn Engine, board: &Board) -> Hint {
+    hint_for(engine, board, Objective::Standard)
+}
+
+/// Like `hint`, for games with the given objective. The engine should be
+/// playing for the same objective.
+pub fn hint_for(engine: &mut dyn Engine, board: &Board, objective: Objective) -> Hint {
+    Hint {
+        best_move: engine.choose_move(board),
+        outcomes: prove_outcomes_for(board, objective),
+    }
+}
+
+/// Proves the outcome of every column that either ends the game, lets the
+/// opponent win immediately, or leaves at most `SOLVE_LIMIT` empty cells.
+pub fn prove_outcomes(board: &Board) -> [Option<Outcome>; WIDTH] {
+    prove_outcomes_for(board, Objective::Standard)
+}
+
+/// Like `prove_outcomes`, for games with the given objective. In misère games
+/// a column is also proven when it leaves the opponent only moves that
+/// complete four.
+pub fn prove_outcomes_for(board: &Board, objective: Objective) -> [Option<Outcome>; WIDTH] {
+    let mut solver = Solver::with_objective(objective);
+    let mut outcomes = [None; WIDTH];
+    for (col, outcome) in outcomes.iter_mut().enumerate() {
+        let mut next = *board;
+        *outcome = match (objective, next.make_move(col)) {
+            (_, MoveResult::Illegal) => None,
+            (Objective::Standard, MoveResult::WinX | MoveResult::WinO) => Some(Outcome::Win),
+            (Objective::Misere, MoveResult::WinX | MoveResult::WinO) => Some(Outcome::Loss),
+            (_, MoveResult::Draw) => Some(Outcome::Draw),
+            (Objective::Standard, MoveResult::None) if can_win_now(&next) => Some(Outcome::Loss),
+            (Objective::Misere, MoveResult::None) if must_lose_now(&next) => Some(Outcome::Win),
+            (_, MoveResult::None) if WIDTH * HEIGHT - next.get_move_count() <= SOLVE_LIMIT => {
+                Some(Outcome::from_score(-solver.solve(&next)))
+            }
+            (_, MoveResult::None) => None,
+        };
+    }
+    outcomes
+}
+
+fn can_win_now(board: &Board) -> bool {
+    (0..WIDTH).any(|col| {
+        let mut next = *board;
+        matches!(next.make_move(col), MoveResult::WinX | MoveResult::WinO)
+    })
+}
+
+// Whether every move completes four, which loses a misère game.
+fn must_lose_now(board: &Board) -> bool {
+    (0..WIDTH).all(|col| {
+        let mut next = *board;
+        matches!(
+            next.make_move(col),
+            MoveResult::WinX | MoveResult::WinO | MoveResult::Illegal
+        )
+    })
+}
+
+#[cfg(test)]
+mod hint_tests {
+    use super::{hint, hint_for, prove_outcomes, prove_outcomes_for, Outcome};
+    use crate::board::Board;
+    use crate::engine::{Exact, MonteCarlo};
+    use crate::misere::Objective;
+
+    #[test]
+    fn immediate_outcomes() {
+        // X can win in column 0. Any column but 1 lets O win in column 1.
+        let board = Board::from_move_string("010101").unwrap();
+        let outcomes = prove_outcomes(&board);
+        assert_eq!(outcomes[0], Some(Outcome::Win));
+        assert_eq!(outcomes[1], None);
+        for outcome in &outcomes[2..] {
+            assert_eq!(*outcome, Some(Outcome::Loss));
+        }
+
+        let hint = hint(&mut MonteCarlo::default(), &board);
+        assert_eq!(hint.best_move, Some(0));
+    }
+
+    #[test]
+    fn solved_outcomes() {
+        let board = Board::from_move_string("242512646300052352144344621036").unwrap();
+        let outcomes = prove_outcomes(&board);
+        for col in [1, 3, 5] {
+            assert_eq!(outcomes[col], Some(Outcome::Win));
+        }
+        for (col, outcome) in outcomes.iter().enumerate() {
+            assert_eq!(outcome.is_some(), board.is_valid_move(col));
+        }
+    }
+
+    #[test]
+    fn misere_outcomes() {
+        // Completing four in column 0 now loses, and nothing else is proven yet.
+        let board = Board::from_move_string("010101").unwrap();
+        let outcomes = prove_outcomes_for(&board, Objective::Misere);
+        assert_eq!(outcomes[0], Some(Outcome::Loss));
+        assert!(outcomes[1..].iter().all(Option::is_none));
+
+        let board = Board::from_move_string("242512646300052352144344621036").unwrap();
+        let outcomes = prove_outcomes_for(&board, Objective::Misere);
+        for col in [1, 3, 5] {
+            assert_eq!(outcomes[col], Some(Outcome::Loss));
+        }
+        let mut engine = Exact::with_objective(Objective::Misere);
+        let hint = hint_for(&mut engine, &board, Objective::Misere);
+        let best = hint.best_move.unwrap();
+        assert!(![1, 3, 5].contains(&best));
+        if outcomes
+            .iter()
+            .flatten()
+            .any(|&outcome| outcome != Outcome::Loss)
+        {
+            assert_ne!(outcomes[best], Some(Outcome::Loss));
+        }
+    }
+}
@@ -0,0 +1,388 @@
+// Line based terminal front end for two player games over the network
+
+use std::io::{self, BufRead};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use connect4::board::{Cell, MoveResult};
+use connect4::net::{self, color_name, Connection, Ending, Message, Session};
+
+// The host waits as long as the client keeps trying.
+const RECONNECT_ATTEMPTS: usize = 30;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// How often the host checks for the player coming back.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const COMMANDS: &str = "\
+Commands: a column number to move, draw (offer or accept a draw), decline,
+resign, say <message>, quit";
+
+enum Event {
+    Input(String),
+
+    // A message or a lost connection, tagged with the connection it came from.
+    Remote(usize, Message),
+    Disconnected(usize),
+}
+
+// How to get the connection back when it drops.
+enum Link {
+    Host(TcpListener),
+    Client(String),
+}
+
+struct Lan {
+    session: Session,
+    name: String,
+    peer: String,
+    link: Link,
+
+    // Handle used for sending. Another thread receives on its own handle.
+    connection: Connection,
+
+    // Number of the current connection, to ignore events from older ones.
+    generation: usize,
+    events: Sender<Event>,
+}
+
+/// Waits for another player to join on the given port, then plays Red.
+pub fn host(port: u16, name: &str) -> Result<(), String> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|e| format!("port {port}: {e}"))?;
+    println!("Waiting for a player to join on port {port}...");
+    let session = Session::new(Cell::X);
+    let (connection, peer) = net::accept(&listener, name, &session).map_err(|e| e.to_string())?;
+    println!("{peer} joined the game.");
+    play(session, name, peer, Link::Host(listener), connection)
+}
+
+/// Joins a game hosted at the given address, such as "192.168.1.5:4444".
+pub fn join(addr: &str, name: &str) -> Result<(), String> {
+    let (connection, welcome) = net::connect(addr, name).map_err(|e| format!("{addr}: {e}"))?;
+    println!("Joined {}'s game.", welcome.name);
+    let mut session = Session::new(welcome.color);
+    session.sync(&welcome)?;
+    play(
+        session,
+        name,
+        welcome.name,
+        Link::Client(addr.to_string()),
+        connection,
+    )
+}
+
+fn play(
+    session: Session,
+    name: &str,
+    peer: String,
+    link: Link,
+    connection: Connection,
+) -> Result<(), String> {
+    let (events, receiver) = mpsc::channel();
+    let input = events.clone();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if input.send(Event::Input(line)).is_err() {
+                return;
+            }
+        }
+        let _ = input.send(Event::Input("quit".to_string()));
+    });
+
+    let mut lan = Lan {
+        session,
+        name: name.to_string(),
+        peer,
+        link,
+        connection: connection.try_clone().map_err(|e| e.to_string())?,
+        generation: 0,
+        events,
+    };
+    lan.listen(connection);
+    println!(
+        "You are playing {}. {COMMANDS}",
+        color_name(lan.session.get_color())
+    );
+    lan.run(receiver)
+}
+
+impl Lan {
+    fn run(&mut self, receiver: Receiver<Event>) -> Result<(), String> {
+        self.show();
+        while let Ok(event) = receiver.recv() {
+            match event {
+                Event::Input(line) if line.trim() == "quit" => break,
+                Event::Input(line) => self.command(line.trim()),
+                Event::Remote(generation, message) if generation == self.generation => {
+                    self.receive(message);
+                }
+                Event::Disconnected(generation) if generation == self.generation => {
+                    if self.session.is_over() {
+                        break;
+                    }
+                    if !self.reconnect(&receiver)? {
+                        break;
+                    }
+                    self.show();
+                }
+                _ => {}
+            }
+            if self.session.is_over() {
+                break;
+            }
+        }
+        self.connection.close();
+        Ok(())
+    }
+
+    // Receives messages on a new connection in the background.
+    fn listen(&mut self, mut connection: Connection) {
+        self.generation += 1;
+        let generation = self.generation;
+        let events = self.events.clone();
+        thread::spawn(move || loop {
+            let event = match connection.receive() {
+                Ok(message) => Event::Remote(generation, message),
+                Err(_) => Event::Disconnected(generation),
+            };
+            let disconnected = matches!(event, Event::Disconnected(_));
+            if events.send(event).is_err() || disconnected {
+                return;
+            }
+        });
+    }
+
+    // Handles a line typed by the local player.
+    fn command(&mut self, line: &str) {
+        let result = match line.split_once(' ').unwrap_or((line, "")) {
+            ("", _) => return,
+            ("resign", _) => self.session.resign(),
+            ("draw", _) if self.session.get_draw_offer() == Some(self.opponent()) => {
+                self.session.answer_draw(true)
+            }
+            ("draw", _) => self.session.offer_draw(),
+            ("decline", _) => self.session.answer_draw(false),
+            ("say", text) => Ok(Message::Chat(text.to_string())),
+            (col, _) => match col.parse() {
+                Ok(col) => self.session.play(col),
+                Err(_) => Err(COMMANDS.to_string()),
+            },
+        };
+        match result {
+            Ok(message) => {
+                // If this fails the connection is lost, and reconnecting
+                // brings the game back in sync with the host, playing a lost
+                // move again.
+                let _ = self.connection.send(&message);
+                if matches!(message, Message::Move(_)) || self.session.is_over() {
+                    self.show();
+                }
+            }
+            Err(error) => println!("{error}"),
+        }
+    }
+
+    fn receive(&mut self, message: Message) {
+        if let Err(error) = self.session.receive(&message) {
+            println!("Refused {}'s message: {error}", self.peer);
+            let _ = self.connection.send(&Message::Error(error));
+            return;
+        }
+        match message {
+            Message::Move(_) | Message::Resign | Message::AcceptDraw => self.show(),
+            Message::OfferDraw => println!(
+                "{} offers a draw. Type draw to accept or decline.",
+                self.peer
+            ),
+            Message::DeclineDraw => println!("{} declined the draw.", self.peer),
+            Message::Chat(text) => println!("{}: {text}", self.peer),
+            Message::Error(error) => println!("{} refused a message: {error}", self.peer),
+            Message::Hello { .. } | Message::Welcome(_) => {}
+        }
+    }
+
+    // Gets the connection back, returning false if the player quits first.
+    fn reconnect(&mut self, receiver: &Receiver<Event>) -> Result<bool, String> {
+        println!(
+            "Lost the connection to {}. Reconnecting... (type quit to stop)",
+            self.peer
+        );
+        let moves = self.session.get_game().get_moves().to_vec();
+        let mut attempts = 0;
+        let connection = loop {
+            if self.quit_requested(receiver) {
+                return Ok(false);
+            }
+            let result = match &self.link {
+                Link::Host(listener) => self.readmit(listener),
+                Link::Client(addr) => {
+                    thread::sleep(RECONNECT_DELAY);
+                    rejoin(addr, &self.name, &mut self.session)
+                }
+            };
+            attempts += 1;
+            match result {
+                Ok(connection) => break connection,
+                Err(e) if attempts == RECONNECT_ATTEMPTS => {
+                    return Err(format!("Could not reconnect to {}: {e}", self.peer));
+                }
+                Err(_) => {}
+            }
+        };
+        println!("Reconnected to {}.", self.peer);
+        self.connection = connection.try_clone().map_err(|e| e.to_string())?;
+        self.listen(connection);
+
+        // The host keeps every move it made, but a move sent by the client
+        // just before the connection dropped may never have arrived.
+        if let Some(col) = lost_move(&moves, &self.session) {
+            if let Ok(message) = self.session.play(col) {
+                println!("Your move in column {col} was lost. Playing it again.");
+                let _ = self.connection.send(&message);
+            }
+        }
+        Ok(true)
+    }
+
+    // Waits up to `RECONNECT_DELAY` for the other player to join the host
+    // again, turning away anyone else.
+    fn readmit(&self, listener: &TcpListener) -> Result<Connection, String> {
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let mut waited = Duration::ZERO;
+        let result = loop {
+            match net::accept(listener, &self.name, &self.session) {
+                Ok((connection, peer)) if peer == self.peer => break Ok(connection),
+                Ok((mut connection, peer)) => {
+                    let error = format!("{} is already playing this game", self.peer);
+                    let _ = connection.send(&Message::Error(error));
+                    println!("Turned away {peer}.");
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if waited >= RECONNECT_DELAY {
+                        break Err("timed out".to_string());
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                    waited += POLL_INTERVAL;
+                }
+                Err(e) => break Err(e.to_string()),
+            }
+        };
+        listener.set_nonblocking(false).map_err(|e| e.to_string())?;
+        result
+    }
+
+    // Handles the lines typed while there is no connection, returning true if
+    // the player wants to quit.
+    fn quit_requested(&self, receiver: &Receiver<Event>) -> bool {
+        loop {
+            match receiver.try_recv() {
+                Ok(Event::Input(line)) if line.trim() == "quit" => return true,
+                Ok(Event::Input(_)) => println!("Waiting for {} to reconnect.", self.peer),
+                Ok(_) => {}
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+    }
+
+    fn show(&self) {
+        let game = self.session.get_game();
+        println!();
+        game.get_board().print();
+        let status = match (self.session.get_ending(), game.get_result()) {
+            (Some(Ending::Resigned(cell)), _) => format!("{} resigned.", self.player(cell)),
+            (Some(Ending::DrawAgreed), _) => "Draw agreed.".to_string(),
+            (None, MoveResult::WinX) => format!("{} wins!", self.player(Cell::X)),
+            (None, MoveResult::WinO) => format!("{} wins!", self.player(Cell::O)),
+            (None, MoveResult::Draw) => "It's a draw!".to_string(),
+            _ if self.session.is_local_turn() => "Your move.".to_string(),
+            _ => format!("Waiting for {}...", self.peer),
+        };
+        println!("{status}");
+    }
+
+    fn opponent(&self) -> Cell {
+        self.session.get_color().switch()
+    }
+
+    fn player(&self, cell: Cell) -> String {
+        if cell == self.session.get_color() {
+            format!("You ({})", color_name(cell))
+        } else {
+            format!("{} ({})", self.peer, color_name(cell))
+        }
+    }
+}
+
+// Returns the last move the player made before reconnecting if the game is
+// otherwise back where they left it, so the host never received the move.
+fn lost_move(moves: &[usize], session: &Session) -> Option<usize> {
+    let (&col, played) = moves.split_last()?;
+    (session.get_game().get_moves() == played && session.is_local_turn()).then_some(col)
+}
+
+// Connects to the host again and brings the session back in sync with the
+// game there, including how it ended if it is over.
+fn rejoin(addr: &str, name: &str, session: &mut Session) -> Result<Connection, String> {
+    let (connection, welcome) = net::connect(addr, name).map_err(|e| e.to_string())?;
+    session.sync(&welcome)?;
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod lan_tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use connect4::board::Cell;
+    use connect4::net::{self, Ending, Message, Session};
+
+    use super::{lost_move, rejoin};
+
+    #[test]
+    fn reconnect_after_resignation() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Red resigns after the first moves, while Yellow is away.
+        let mut red = Session::new(Cell::X);
+        let mut yellow = Session::new(Cell::O);
+        yellow.receive(&red.play(3).unwrap()).unwrap();
+        red.receive(&yellow.play(3).unwrap()).unwrap();
+        assert_eq!(red.resign(), Ok(Message::Resign));
+
+        let host = thread::spawn(move || {
+            net::accept(&listener, "red player", &red).unwrap();
+        });
+        rejoin(&addr, "yellow player", &mut yellow).unwrap();
+        host.join().unwrap();
+        assert_eq!(yellow.get_game().get_moves(), &[3, 3]);
+        assert_eq!(yellow.get_ending(), Some(Ending::Resigned(Cell::X)));
+        assert!(yellow.is_over());
+    }
+
+    #[test]
+    fn replay_a_lost_move() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Red never receives Yellow's reply.
+        let mut red = Session::new(Cell::X);
+        let mut yellow = Session::new(Cell::O);
+        yellow.receive(&red.play(3).unwrap()).unwrap();
+        yellow.play(2).unwrap();
+        let moves = yellow.get_game().get_moves().to_vec();
+
+        let host = thread::spawn(move || {
+            net::accept(&listener, "red player", &red).unwrap();
+        });
+        rejoin(&addr, "yellow player", &mut yellow).unwrap();
+        host.join().unwrap();
+        assert_eq!(lost_move(&moves, &yellow), Some(2));
+        assert_eq!(lost_move(&[3], &yellow), None);
+        assert_eq!(lost_move(&[], &yellow), None);
+    }
+}
@@ -0,0 +1,30 @@
+// Connect 4 engine library shared by the binary and the benchmarks
+
+pub mod board;
+pub mod chatgpt_connect4;
+pub mod connectn;
+pub mod cylinder;
+pub mod difficulty;
+pub mod engine;
+pub mod eval;
+pub mod fullsolve;
+pub mod game;
+pub mod hint;
+pub mod mcts;
+pub mod misere;
+pub mod net;
+pub mod nn;
+pub mod ordering;
+pub mod play;
+pub mod ponder;
+pub mod pop10;
+pub mod popout;
+pub mod record;
+pub mod rules;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod setup;
+pub mod solver;
+pub mod tablebase;
+pub mod testset;
+pub mod train;
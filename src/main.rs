@@ -1,45 +1,689 @@
 // Play Connect 4 using monte carlo simulation
 
-mod board;
-mod play;
+mod lan;
+mod tui;
 
-use board::{Board, Cell};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use connect4::board::{self, Board, Cell, MoveResult};
+use connect4::difficulty::{self, Leveled, LEVELS};
+use connect4::engine::{self, Engine, MonteCarlo, ENGINE_NAMES};
+use connect4::eval::{self, Heuristic, Labeled, Weights};
+use connect4::fullsolve::{self, Checkpoint, Geometry, Search};
+use connect4::game::Game;
+use connect4::hint::{self, Outcome};
+use connect4::mcts::Mcts;
+use connect4::misere::Objective;
+use connect4::net;
+use connect4::nn::{self, Network};
+use connect4::ordering::Heuristics;
+use connect4::play::{self, Policy};
+use connect4::ponder::Ponderer;
+use connect4::popout::{PopOut, SimultaneousFour};
+use connect4::rules::{self, Move, Rules, VARIANT_NAMES};
+use connect4::setup::{self, Setup};
+use connect4::tablebase::{self, Tablebase};
+use connect4::testset::{self, Difficulty};
+use connect4::train::{self, Training};
+use rand::{rngs::StdRng, SeedableRng};
+
+const USAGE: &str = "\
+Usage:
+  connect4                                         Watch the engine play itself
+  connect4 tui [--engine <name>] [--level <level>] Play in a full screen terminal UI,
+                                                   against the computer if a level is given
+  connect4 tui --think <seconds>                   Play against a Monte Carlo engine that
+                                                   thinks for a fixed time and ponders
+  connect4 hint <moves> [--engine <name>]          Recommend a move for a position
+  connect4 play <variant>                          Play a variant against the computer
+  connect4 popout [--double-four <mover|draw>]     Play Pop Out against the computer, where
+                                                   a pop making four for both players wins
+                                                   for the mover (default) or draws
+  connect4 host [port] [--name <name>]             Host a game on the network and play Red
+  connect4 join <host[:port]> [--name <name>]      Join a hosted game and play Yellow
+  connect4 solve [--size <width>x<height>] [--weak] [--checkpoint <file>]
+                                                   Solve the empty board and every first move,
+                                                   resuming from the checkpoint if it exists
+  connect4 train <weights> [--iterations <n>] [--games <n>] [--simulations <n>] [--shards <dir>]
+                                                   Improve a network by self-play, keeping
+                                                   only candidates that beat the best so far
+  connect4 tune <weights> <testset>... [--iterations <n>]
+                                                   Fit the heuristic engine's evaluation
+                                                   weights to solver-labeled test sets
+  connect4 tablebase gen <file> <min-moves> [opening]
+                                                   Solve every position after the opening
+                                                   with at least min-moves moves played.
+                                                   Every line up to min-moves is played
+                                                   through, so this takes seconds for an
+                                                   opening with up to 22 empty cells and
+                                                   min-moves up to 10 moves after it, but
+                                                   grows quickly beyond that
+  connect4 tablebase verify <file>                 Check a tablebase file for damage
+  connect4 testset gen <begin|middle|end> <count> [seed]
+                                                   Print a solver-labeled test set
+  connect4 testset run <file>... [--ordering <list>]
+                                                   Check the solver against test sets
+
+Engines: montecarlo (default), solver, mcts (tree search with random playouts, or
+guided by a network with --weights <file>), heuristic (short search with a static
+evaluation, whose weights --weights <file> loads), chatgpt (broken baseline)
+Rollouts: uniform (default), tactical (wins, then blocks, then random) or softmax
+(weighted by the static evaluation), chosen for montecarlo with --rollout <policy>
+Variants: standard, misere, connect5 (five in a row on 9x6), five-in-a-row (9x6 with
+full outer columns), cylinder (7x6 wrapping from column 6 to column 0), torus
+(7x6 wrapping at every edge), popout, pop10
+Levels: beginner, casual, strong, expert (plays perfectly, but can think for minutes
+in the opening)
+Orderings: all (default), none or a comma separated list of center, threats,
+killers and history
+The tui and hint commands also take --tablebase <file> to look up late positions,
+--rules misere for games where completing four in a row loses, --position <position>
+to start from a position such as \"......./......./......./......./...O.../..XX... O\"
+(rows from the top, then the player to move) and --handicap <discs> to let the
+player to move at the start drop that many extra discs.";
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let Err(message) = run(&args) {
+        eprintln!("{message}");
+        process::exit(1);
+    }
+}
+
+fn run(args: &[&str]) -> Result<(), String> {
+    match args {
+        [] => {
+            self_play();
+            Ok(())
+        }
+        ["tui", options @ ..] => {
+            let options = Options::parse(options)?;
+            options.install_tablebase()?;
+            let opponent: Option<Box<dyn Engine>> = match (options.level, options.think) {
+                (Some(_), Some(_)) => return Err("Use either --level or --think".to_string()),
+                (Some(name), None) => Some(parse_level(name)?),
+                (None, Some(seconds)) => {
+                    let seconds: f64 = seconds
+                        .parse()
+                        .map_err(|_| format!("Invalid time: {seconds}"))?;
+                    let think_time = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("Invalid time: {seconds}"))?;
+                    Some(Box::new(Ponderer::new(think_time)))
+                }
+                (None, None) => None,
+            };
+            let objective = options.objective()?;
+            if objective == Objective::Misere && opponent.is_some() {
+                return Err(
+                    "Computer opponents play standard rules; try: connect4 play misere".to_string(),
+                );
+            }
+            tui::run(options.engine()?, opponent, objective, options.setup()?)
+                .map_err(|e| e.to_string())
+        }
+        ["hint", moves, options @ ..] => {
+            let options = Options::parse(options)?;
+            options.install_tablebase()?;
+            show_hint(
+                moves,
+                options.engine()?,
+                options.objective()?,
+                options.setup()?,
+            )
+        }
+        ["play", name] => match rules::from_name(name) {
+            Some(game) => play_variant(game),
+            None => Err(format!(
+                "Unknown variant: {name}. Variants: {}",
+                VARIANT_NAMES.join(", ")
+            )),
+        },
+        ["popout"] => play_variant(Box::new(PopOut::new(SimultaneousFour::MoverWins))),
+        ["popout", "--double-four", "draw"] => {
+            play_variant(Box::new(PopOut::new(SimultaneousFour::Draw)))
+        }
+        ["popout", "--double-four", "mover"] => {
+            play_variant(Box::new(PopOut::new(SimultaneousFour::MoverWins)))
+        }
+        ["host", port, options @ ..] if !port.starts_with("--") => {
+            let port = port.parse().map_err(|_| format!("Invalid port: {port}"))?;
+            lan::host(port, &Options::parse(options)?.name())
+        }
+        ["host", options @ ..] => lan::host(net::DEFAULT_PORT, &Options::parse(options)?.name()),
+        ["join", addr, options @ ..] => {
+            let addr = if addr.contains(':') {
+                addr.to_string()
+            } else {
+                format!("{addr}:{}", net::DEFAULT_PORT)
+            };
+            lan::join(&addr, &Options::parse(options)?.name())
+        }
+        ["solve", options @ ..] => solve(options),
+        ["train", weights, options @ ..] => train(weights, options),
+        ["tune", weights, files @ ..] if !files.is_empty() => tune(weights, files),
+        ["tablebase", "gen", file, min_moves] => generate_tablebase(file, min_moves, ""),
+        ["tablebase", "gen", file, min_moves, opening] => {
+            generate_tablebase(file, min_moves, opening)
+        }
+        ["tablebase", "verify", file] => verify_tablebase(file),
+        ["testset", "gen", difficulty, count] => generate_testset(difficulty, count, "0"),
+        ["testset", "gen", difficulty, count, seed] => generate_testset(difficulty, count, seed),
+        ["testset", "run", files @ .., "--ordering", ordering] if !files.is_empty() => {
+            run_testsets(files, Heuristics::parse(ordering)?)
+        }
+        ["testset", "run", files @ ..] if !files.is_empty() => run_testsets(files, Heuristics::ALL),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn self_play() {
     let mut board = Board::new();
-    let mut cell = Cell::X;
+    let mut rng = rand::thread_rng();
     loop {
         board.print();
-        if board.is_full() {
-            println!("It's a draw!");
+        let candidates = play::evaluate_moves(
+            &board,
+            play::NUM_GAMES,
+            Policy::Uniform,
+            Objective::Standard,
+            &mut rng,
+        );
+        for candidate in &candidates {
+            let (low, high) = candidate.stats.confidence_interval(play::Z_95);
+            println!(
+                "Col: {}, Score: {:.3}{}",
+                candidate.col,
+                candidate.score(),
+                match candidate.result {
+                    MoveResult::WinX | MoveResult::WinO => " (wins)".to_string(),
+                    MoveResult::Draw => " (draws)".to_string(),
+                    _ if candidate.allows_win => " (allows a win)".to_string(),
+                    _ => format!(
+                        " ({} games, 95% between {low:.3} and {high:.3})",
+                        candidate.stats.get_games()
+                    ),
+                }
+            );
+        }
+        let Some(best) = candidates.first() else {
+            println!("No valid moves left!");
             break;
+        };
+        match board.make_move(best.col) {
+            MoveResult::WinX | MoveResult::WinO => {
+                board.print();
+                println!("{} wins!", best.mover.to_char());
+                break;
+            }
+            MoveResult::Draw => {
+                board.print();
+                println!("It's a draw!");
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Options shared by the interactive commands.
+#[derive(Default)]
+struct Options<'a> {
+    engine: Option<&'a str>,
+    level: Option<&'a str>,
+    name: Option<&'a str>,
+    think: Option<&'a str>,
+    tablebase: Option<&'a str>,
+    rules: Option<&'a str>,
+    position: Option<&'a str>,
+    handicap: Option<&'a str>,
+    weights: Option<&'a str>,
+    rollout: Option<&'a str>,
+}
+
+impl<'a> Options<'a> {
+    fn parse(args: &[&'a str]) -> Result<Self, String> {
+        let mut options = Options::default();
+        for pair in args.chunks(2) {
+            match pair {
+                ["--engine", name] => options.engine = Some(name),
+                ["--level", name] => options.level = Some(name),
+                ["--name", name] => options.name = Some(name),
+                ["--think", seconds] => options.think = Some(seconds),
+                ["--tablebase", file] => options.tablebase = Some(file),
+                ["--rules", name] => options.rules = Some(name),
+                ["--position", position] => options.position = Some(position),
+                ["--handicap", discs] => options.handicap = Some(discs),
+                ["--weights", file] => options.weights = Some(file),
+                ["--rollout", policy] => options.rollout = Some(policy),
+                _ => return Err(USAGE.to_string()),
+            }
+        }
+        Ok(options)
+    }
+
+    // Returns the player name, defaulting to the user's login name.
+    fn name(&self) -> String {
+        match self.name {
+            Some(name) => name.to_string(),
+            None => env::var("USER").unwrap_or("player".to_string()),
+        }
+    }
+
+    // Loads the tablebase file, if one was given, for the engines to use.
+    fn install_tablebase(&self) -> Result<(), String> {
+        if let Some(file) = self.tablebase {
+            tablebase::install(Tablebase::load(file)?);
+        }
+        Ok(())
+    }
+
+    // Returns the selected rules, defaulting to standard.
+    fn objective(&self) -> Result<Objective, String> {
+        self.rules.map_or(Ok(Objective::Standard), Objective::parse)
+    }
+
+    // Returns where games start, defaulting to the empty board.
+    fn setup(&self) -> Result<Setup, String> {
+        let board = match self.position {
+            Some(position) => setup::parse_position(position)?,
+            None => Board::new(),
+        };
+        let handicap = match self.handicap {
+            Some(discs) => discs
+                .parse()
+                .map_err(|_| format!("Invalid handicap: {discs}"))?,
+            None => 0,
+        };
+        Ok(Setup::new(board, handicap))
+    }
+
+    // Returns the selected engine, defaulting to Monte Carlo, or tree search
+    // guided by the network when a weights file is given. The heuristic engine
+    // reads its evaluation weights from the file instead.
+    fn engine(&self) -> Result<Box<dyn Engine>, String> {
+        if let Some(file) = self.weights {
+            let name = self.engine.unwrap_or("mcts");
+            let objective = self.objective()?;
+            return match name {
+                "mcts" if objective != Objective::Standard => {
+                    Err("Networks only play standard rules".to_string())
+                }
+                "mcts" => Ok(Box::new(Mcts::new(Network::load(file)?))),
+                "heuristic" => Ok(Box::new(Heuristic::with_objective(
+                    Weights::load(file)?,
+                    objective,
+                ))),
+                _ => Err("--weights is only used by the mcts and heuristic engines".to_string()),
+            };
+        }
+        let name = self.engine.unwrap_or("montecarlo");
+        if let Some(policy) = self.rollout {
+            if name != "montecarlo" {
+                return Err("--rollout is only used by the montecarlo engine".to_string());
+            }
+            return Ok(Box::new(MonteCarlo::with_policy(
+                play::NUM_GAMES,
+                self.objective()?,
+                Policy::parse(policy)?,
+            )));
+        }
+        if !ENGINE_NAMES.contains(&name) {
+            return Err(format!(
+                "Unknown engine: {name} (expected one of {})",
+                ENGINE_NAMES.join(", ")
+            ));
+        }
+        engine::from_name_for(name, self.objective()?)
+            .ok_or(format!("The {name} engine only plays standard rules"))
+    }
+}
+
+fn parse_level(name: &str) -> Result<Box<dyn Engine>, String> {
+    let level = difficulty::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = LEVELS.iter().map(|level| level.name).collect();
+        format!(
+            "Unknown level: {name} (expected one of {})",
+            names.join(", ")
+        )
+    })?;
+    Ok(Box::new(Leveled::new(level)))
+}
+
+fn show_hint(
+    moves: &str,
+    mut engine: Box<dyn Engine>,
+    objective: Objective,
+    setup: Setup,
+) -> Result<(), String> {
+    let mut game = Game::from_setup(setup);
+    for c in moves.chars() {
+        let col = c.to_digit(10).map_or(board::WIDTH, |col| col as usize);
+        if game.make_move(col) != MoveResult::None {
+            return Err(format!("Invalid or finished position: {moves}"));
+        }
+    }
+    let board = *game.get_board();
+    board.print();
+    let hint = hint::hint_for(engine.as_mut(), &board, objective);
+    match hint.best_move {
+        Some(col) => println!("Recommended column ({}): {col}", engine.name()),
+        None => println!("No valid moves left!"),
+    }
+    for col in 0..board::WIDTH {
+        if board.is_valid_move(col) {
+            let label = hint.outcomes[col].map_or("unknown", |outcome| outcome.label());
+            println!("Col: {col}, {label}");
         }
-        let mut best_score = 0.0;
-        let mut best_move = None;
-        for x in 0..board::WIDTH {
-            if board.is_valid_move(x) {
-                let score = play::monte_carlo(&board, cell);
-                println!("Col: {x}, Score: {score}");
+    }
+    Ok(())
+}
+
+// Random games played after each move by the Monte Carlo engine in variants.
+const VARIANT_GAMES: usize = 100;
 
-                if score > best_score {
-                    best_score = score;
-                    best_move = Some(x);
+fn play_variant(mut game: Box<dyn Rules>) -> Result<(), String> {
+    let mut engine = MonteCarlo::new(VARIANT_GAMES);
+    let mut lines = io::stdin().lock().lines();
+    println!("You play X. Enter a column to drop a disc, p and a column to pop one, or quit.");
+    while !game.is_terminal() {
+        game.print();
+        let mv = if game.get_current_player() == Cell::X {
+            print!("Your move: ");
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            let line = line.map_err(|e| e.to_string())?;
+            match Move::parse_list(line.trim()).as_deref() {
+                _ if line.trim() == "quit" => return Ok(()),
+                Ok([mv]) if game.is_legal(*mv) => *mv,
+                _ => {
+                    println!("Invalid move: {}", line.trim());
+                    continue;
                 }
             }
+        } else {
+            let mv = engine.choose_variant_move(game.as_ref()).unwrap();
+            println!("Computer plays {mv}");
+            mv
+        };
+        game.apply(mv);
+    }
+    game.print();
+    match game.outcome() {
+        MoveResult::WinX => println!("You win!"),
+        MoveResult::WinO => println!("The computer wins!"),
+        _ => println!("It's a draw!"),
+    }
+    println!("Moves: {}", rules::notation(game.get_moves()));
+    Ok(())
+}
+
+fn solve(args: &[&str]) -> Result<(), String> {
+    let mut geometry = Geometry::STANDARD;
+    let mut weak = false;
+    let mut path = None;
+    let mut rest = args;
+    while !rest.is_empty() {
+        rest = match rest {
+            ["--size", size, rest @ ..] => {
+                geometry = Geometry::parse(size)?;
+                rest
+            }
+            ["--weak", rest @ ..] => {
+                weak = true;
+                rest
+            }
+            ["--checkpoint", file, rest @ ..] => {
+                path = Some(*file);
+                rest
+            }
+            _ => return Err(USAGE.to_string()),
+        };
+    }
+
+    let mut checkpoint = Checkpoint::new(geometry, weak);
+    if let Some(path) = path.filter(|path| Path::new(path).exists()) {
+        checkpoint = Checkpoint::load(path)?;
+        if (checkpoint.geometry, checkpoint.weak) != (geometry, weak) {
+            return Err(format!(
+                "{path} is a {} solve of a {} board",
+                if checkpoint.weak { "weak" } else { "strong" },
+                checkpoint.geometry
+            ));
         }
-        match best_move {
-            Some(x) => {
-                board.make_move(x);
-                cell = match cell {
-                    Cell::X => Cell::O,
-                    Cell::O => Cell::X,
-                    Cell::Empty => unreachable!(),
-                };
+        println!("Resuming from {path}");
+    }
+
+    let mut search = Search::new(geometry, fullsolve::DEFAULT_TABLE_SIZE);
+    let start = Instant::now();
+    search.set_heartbeat(Box::new(move |nodes| {
+        println!("  {nodes} nodes, {:.0?}", start.elapsed());
+    }));
+    let value =
+        fullsolve::solve_start(&mut search, &mut checkpoint, &mut |progress, checkpoint| {
+            let score = if progress.min == progress.max {
+                format!("score {}", progress.min)
+            } else {
+                format!("score between {} and {}", progress.min, progress.max)
+            };
+            println!(
+                "Column {}: {score} ({} nodes, {:.1?})",
+                progress.column, progress.nodes, progress.elapsed
+            );
+            if let Some(Err(error)) = path.map(|path| checkpoint.save(path)) {
+                eprintln!("Could not save the checkpoint: {error}");
             }
-            None => {
-                println!("No valid moves left!");
-                break;
+        });
+
+    println!("{geometry} board, first player to move:");
+    for (col, score) in checkpoint.scores.iter().enumerate() {
+        let score = score.unwrap();
+        println!(
+            "  Column {col}: {} ({score})",
+            Outcome::from_score(score).label()
+        );
+    }
+    println!(
+        "The first player {} with perfect play.",
+        match Outcome::from_score(value) {
+            Outcome::Win => "wins",
+            Outcome::Draw => "draws",
+            Outcome::Loss => "loses",
+        }
+    );
+    Ok(())
+}
+
+fn train(weights: &str, args: &[&str]) -> Result<(), String> {
+    let mut iterations = 10;
+    let mut games = 50;
+    let mut simulations = 200;
+    let mut shards = format!("{weights}.shards");
+    let parse = |value: &str| value.parse().map_err(|_| format!("Invalid count: {value}"));
+    let mut rest = args;
+    while !rest.is_empty() {
+        rest = match rest {
+            ["--iterations", n, rest @ ..] => {
+                iterations = parse(n)?;
+                rest
+            }
+            ["--games", n, rest @ ..] => {
+                games = parse(n)?;
+                rest
+            }
+            ["--simulations", n, rest @ ..] => {
+                simulations = parse(n)?;
+                rest
+            }
+            ["--shards", dir, rest @ ..] => {
+                shards = dir.to_string();
+                rest
             }
+            _ => return Err(USAGE.to_string()),
+        };
+    }
+    if simulations < train::MIN_SIMULATIONS {
+        return Err(format!(
+            "--simulations must be at least {}",
+            train::MIN_SIMULATIONS
+        ));
+    }
+
+    let mut best = if Path::new(weights).exists() {
+        Network::load(weights)?
+    } else {
+        let network = Network::new(&nn::DEFAULT_HIDDEN, 0);
+        network.save(weights)?;
+        println!("Created {weights}");
+        network
+    };
+    fs::create_dir_all(&shards).map_err(|e| format!("{shards}: {e}"))?;
+    let mut rng = StdRng::from_entropy();
+    for iteration in 1..=iterations {
+        let start = Instant::now();
+        let samples = train::augment(&train::self_play(&best, games, simulations, &mut rng));
+        let mut paths: Vec<String> = fs::read_dir(&shards)
+            .map_err(|e| format!("{shards}: {e}"))?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with("shard-") && name.ends_with(".bin"))
+            .map(|name| format!("{shards}/{name}"))
+            .collect();
+        paths.sort();
+        let path = format!("{shards}/shard-{:05}.bin", paths.len());
+        train::write_shard(&path, &samples)?;
+        paths.push(path);
+
+        let mut window = Vec::new();
+        for path in paths.iter().rev().take(train::SHARD_WINDOW) {
+            window.extend(train::read_shard(path)?);
+        }
+        let mut candidate = best.clone();
+        let losses = train::train(&mut candidate, &window, &Training::default(), &mut rng);
+        let score = train::play_match(&candidate, &best, train::MATCH_GAMES, simulations, &mut rng);
+        let accepted = score >= train::ACCEPT_SCORE;
+        if accepted {
+            candidate.save(weights)?;
+            best = candidate;
+        }
+        println!(
+            "Iteration {iteration}: {} samples, {} in window, loss {:.3}, candidate scored {:.0}%, {} ({:.1?})",
+            samples.len(),
+            window.len(),
+            losses.last().copied().unwrap_or(0.0),
+            100.0 * score,
+            if accepted { "accepted" } else { "rejected" },
+            start.elapsed()
+        );
+    }
+    Ok(())
+}
+
+fn tune(weights: &str, args: &[&str]) -> Result<(), String> {
+    let (files, iterations) = match args {
+        [files @ .., "--iterations", n] if !files.is_empty() => {
+            (files, n.parse().map_err(|_| format!("Invalid count: {n}"))?)
         }
+        _ => (args, 1000),
+    };
+    let mut positions = Vec::new();
+    for file in files {
+        let text = fs::read_to_string(file).map_err(|e| format!("{file}: {e}"))?;
+        for case in testset::parse(&text).map_err(|e| format!("{file}: {e}"))? {
+            let board = Board::from_move_string(&case.moves).unwrap();
+            positions.push(Labeled::from_score(&board, case.score));
+        }
+    }
+
+    // Start from the weights file if it exists.
+    let start = if Path::new(weights).exists() {
+        Weights::load(weights)?
+    } else {
+        Weights::default()
+    };
+    let tuned = eval::tune(&start, &positions, iterations, 1.0);
+    println!(
+        "{} positions, error {:.4} -> {:.4}",
+        positions.len(),
+        eval::error(&start, &positions),
+        eval::error(&tuned, &positions)
+    );
+    print!("{}", tuned.to_text());
+    tuned.save(weights)
+}
+
+fn generate_tablebase(file: &str, min_moves: &str, opening: &str) -> Result<(), String> {
+    let min_moves = min_moves
+        .parse()
+        .map_err(|_| format!("Invalid move count: {min_moves}"))?;
+    let opening = Board::from_move_string(opening)
+        .ok_or(format!("Invalid or finished position: {opening}"))?;
+    let start = Instant::now();
+    let tablebase = Tablebase::generate(&opening, min_moves);
+    tablebase.save(file)?;
+    println!(
+        "{file}: {} positions with at least {min_moves} moves in {:?}",
+        tablebase.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+fn verify_tablebase(file: &str) -> Result<(), String> {
+    let tablebase = Tablebase::load(file)?;
+    println!("{file}: {}", tablebase.get_header());
+    tablebase.verify().map_err(|e| format!("{file}: {e}"))?;
+    println!("{file}: OK");
+    Ok(())
+}
+
+fn generate_testset(difficulty: &str, count: &str, seed: &str) -> Result<(), String> {
+    let difficulty =
+        Difficulty::from_name(difficulty).ok_or(format!("Unknown difficulty: {difficulty}"))?;
+    let count = count
+        .parse()
+        .map_err(|_| format!("Invalid count: {count}"))?;
+    let seed = seed.parse().map_err(|_| format!("Invalid seed: {seed}"))?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let cases = testset::generate(difficulty, count, &mut rng);
+    print!("{}", testset::format(difficulty, &cases));
+    Ok(())
+}
+
+fn run_testsets(files: &[&str], heuristics: Heuristics) -> Result<(), String> {
+    let mut failed = false;
+    for file in files {
+        let text = fs::read_to_string(file).map_err(|e| format!("{file}: {e}"))?;
+        let cases = testset::parse(&text).map_err(|e| format!("{file}: {e}"))?;
+        let report = testset::run(&cases, heuristics);
+        println!(
+            "{file}: {} positions, mean time {:?}, mean nodes {:.0}, {:.0} nodes/s, {} failures",
+            report.positions,
+            report.mean_time(),
+            report.mean_nodes(),
+            report.nodes_per_second(),
+            report.failures.len()
+        );
+        println!(
+            "  cutoff rate {:.1}%, first move cutoffs {:.1}%, mean moves to cutoff {:.2}",
+            100.0 * report.cutoffs.cutoff_rate(),
+            100.0 * report.cutoffs.first_move_rate(),
+            report.cutoffs.mean_moves_to_cutoff()
+        );
+        for (case, score) in &report.failures {
+            println!("  {}: expected {}, got {score}", case.moves, case.score);
+        }
+        failed |= !report.failures.is_empty();
+    }
+    if failed {
+        Err("Some test positions failed".to_string())
+    } else {
+        Ok(())
     }
 }
@@ -0,0 +1,432 @@
+// Monte Carlo tree search guided by an evaluator's policy and value
+//
+// Each simulation walks down the tree choosing the child with the best PUCT
+// score, the child's mean value plus an exploration bonus that grows with the
+// evaluator's prior for the move and shrinks as the move is visited. The
+// first position reached that has not been seen before is evaluated and its
+// value backed up the path, switching sign at each move. Positions where the
+// game is over are valued by their result instead, read through the
+// objective, so that under misère rules completing four loses. The move
+// searched most is the one played.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::board::{Board, Cell, MoveResult, WIDTH};
+use crate::engine::{Engine, Info};
+use crate::misere::Objective;
+use crate::play::{self, Policy};
+
+/// Default number of simulations for each move.
+pub const NUM_SIMULATIONS: usize = 800;
+
+/// Default weight of the prior in the exploration bonus.
+pub const C_PUCT: f32 = 1.5;
+
+/// Simulations between the reports of `analyze_with_progress`.
+pub const REPORT_INTERVAL: usize = 100;
+
+/// What an evaluator thinks of a position.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Evaluation {
+    // Probability of playing each column, zero for full columns.
+    pub policy: [f32; WIDTH],
+
+    // Expected result for the player to move, from -1 for a loss to 1 for a win.
+    pub value: f32,
+}
+
+pub trait Evaluator {
+    fn evaluate(&mut self, board: &Board) -> Evaluation;
+}
+
+/// Evaluates a position by random playouts, with every move equally likely.
+pub struct Rollouts {
+    num_games: usize,
+    objective: Objective,
+    rng: StdRng,
+}
+
+impl Rollouts {
+    pub fn new(num_games: usize) -> Self {
+        Self::with_objective(num_games, Objective::Standard)
+    }
+
+    pub fn with_objective(num_games: usize, objective: Objective) -> Self {
+        Self {
+            num_games,
+            objective,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn with_rng(num_games: usize, rng: StdRng) -> Self {
+        Self {
+            num_games,
+            objective: Objective::Standard,
+            rng,
+        }
+    }
+}
+
+impl Evaluator for Rollouts {
+    fn evaluate(&mut self, board: &Board) -> Evaluation {
+        let player = board.get_current_player();
+        let valid: Vec<usize> = (0..WIDTH).filter(|&col| board.is_valid_move(col)).collect();
+        let mut policy = [0.0; WIDTH];
+        for &col in &valid {
+            policy[col] = 1.0 / valid.len() as f32;
+        }
+        let mut total = 0.0;
+        for _ in 0..self.num_games {
+            let result = play::playout(
+                &mut board.clone(),
+                Policy::Uniform,
+                self.objective,
+                &mut self.rng,
+            );
+            let result = self.objective.interpret(result);
+            if matches!(result, MoveResult::WinX | MoveResult::WinO) {
+                total += if Cell::from(result) == player {
+                    1.0
+                } else {
+                    -1.0
+                };
+            }
+        }
+        Evaluation {
+            policy,
+            value: total / self.num_games.max(1) as f32,
+        }
+    }
+}
+
+struct Node {
+    board: Board,
+
+    // Column played to reach the node and the evaluator's prior for it.
+    col: usize,
+    prior: f32,
+
+    visits: u32,
+
+    // Sum of the values backed up through the node, for the player who moved
+    // into it.
+    total: f32,
+
+    // Value for the player who moved into the node if the game is over.
+    terminal: Option<f32>,
+
+    // Indices of the children in the tree, empty until the node is expanded.
+    children: Vec<usize>,
+}
+
+impl Node {
+    fn new(board: Board, col: usize, prior: f32, terminal: Option<f32>) -> Self {
+        Self {
+            board,
+            col,
+            prior,
+            visits: 0,
+            total: 0.0,
+            terminal,
+            children: Vec::new(),
+        }
+    }
+
+    // Mean value for the player who moved into the node, counting unvisited
+    // nodes as even.
+    fn mean(&self) -> f32 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.total / self.visits as f32
+        }
+    }
+}
+
+/// The tree built by one search.
+pub struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    /// Visits of each column from the root, zero for full columns.
+    pub fn get_visits(&self) -> [u32; WIDTH] {
+        let mut visits = [0; WIDTH];
+        for &child in &self.nodes[0].children {
+            visits[self.nodes[child].col] = self.nodes[child].visits;
+        }
+        visits
+    }
+
+    /// The most visited line and the mean value of its first move, or None
+    /// if the root has no children.
+    pub fn get_info(&self) -> Option<Info> {
+        let mut pv = self.principal_variation();
+        let children = self.nodes[0]
+            .children
+            .iter()
+            .map(|&child| &self.nodes[child]);
+        let best = match pv.first() {
+            Some(&col) => children.clone().find(|node| node.col == col)?,
+            // A single simulation only expands the root, so fall back to the
+            // move with the highest prior, the first on ties.
+            None => {
+                let best = children.reduce(
+                    |best, node| {
+                        if node.prior > best.prior {
+                            node
+                        } else {
+                            best
+                        }
+                    },
+                )?;
+                pv.push(best.col);
+                best
+            }
+        };
+        Some(Info {
+            depth: pv.len(),
+            score: best.mean() as f64,
+            pv,
+        })
+    }
+
+    // Follows the most visited children from the root.
+    fn principal_variation(&self) -> Vec<usize> {
+        let mut pv = Vec::new();
+        let mut node = &self.nodes[0];
+        while let Some(&best) = node
+            .children
+            .iter()
+            .filter(|&&child| self.nodes[child].visits > 0)
+            .max_by_key(|&&child| self.nodes[child].visits)
+        {
+            node = &self.nodes[best];
+            pv.push(node.col);
+        }
+        pv
+    }
+}
+
+/// Tree search engine using an evaluator such as a `Network` or `Rollouts`.
+pub struct Mcts<E: Evaluator> {
+    evaluator: E,
+    simulations: usize,
+    c_puct: f32,
+    objective: Objective,
+}
+
+impl<E: Evaluator> Mcts<E> {
+    pub fn new(evaluator: E) -> Self {
+        Self::with_simulations(evaluator, NUM_SIMULATIONS)
+    }
+
+    pub fn with_simulations(evaluator: E, simulations: usize) -> Self {
+        Self::with_objective(evaluator, simulations, Objective::Standard)
+    }
+
+    /// The evaluator should value positions for the same objective.
+    pub fn with_objective(evaluator: E, simulations: usize, objective: Objective) -> Self {
+        Self {
+            evaluator,
+            simulations,
+            c_puct: C_PUCT,
+            objective,
+        }
+    }
+
+    pub fn get_evaluator(&self) -> &E {
+        &self.evaluator
+    }
+
+    /// Searches the position, which must not be over.
+    pub fn search(&mut self, board: &Board) -> Tree {
+        self.search_with_progress(board, &mut |_| {})
+    }
+
+    // Searches like `search`, passing the tree to `progress` every
+    // `REPORT_INTERVAL` simulations.
+    fn search_with_progress(&mut self, board: &Board, progress: &mut dyn FnMut(&Tree)) -> Tree {
+        let mut tree = Tree {
+            nodes: vec![Node::new(*board, WIDTH, 1.0, None)],
+        };
+        for simulation in 1..=self.simulations.max(1) {
+            self.simulate(&mut tree, 0);
+            if simulation % REPORT_INTERVAL == 0 && simulation < self.simulations {
+                progress(&tree);
+            }
+        }
+        tree
+    }
+
+    // Runs one simulation through the node and returns the value found for
+    // the player who moved into it.
+    fn simulate(&mut self, tree: &mut Tree, index: usize) -> f32 {
+        let value = if let Some(value) = tree.nodes[index].terminal {
+            value
+        } else if tree.nodes[index].children.is_empty() {
+            -self.expand(tree, index)
+        } else {
+            let child = self.select(tree, index);
+            -self.simulate(tree, child)
+        };
+        let node = &mut tree.nodes[index];
+        node.visits += 1;
+        node.total += value;
+        value
+    }
+
+    // Adds the children of the node and returns its value for the player to move.
+    fn expand(&mut self, tree: &mut Tree, index: usize) -> f32 {
+        let board = tree.nodes[index].board;
+        let evaluation = self.evaluator.evaluate(&board);
+        for col in 0..WIDTH {
+            let mut next = board;
+            let terminal = match next.make_move(col) {
+                MoveResult::Illegal => continue,
+                MoveResult::None => None,
+                MoveResult::Draw => Some(0.0),
+                // Completing four wins, or loses under misère rules.
+                MoveResult::WinX | MoveResult::WinO => match self.objective {
+                    Objective::Standard => Some(1.0),
+                    Objective::Misere => Some(-1.0),
+                },
+            };
+            let child = tree.nodes.len();
+            tree.nodes
+                .push(Node::new(next, col, evaluation.policy[col], terminal));
+            tree.nodes[index].children.push(child);
+        }
+        evaluation.value
+    }
+
+    // Picks the child with the highest PUCT score, the first on ties.
+    fn select(&self, tree: &Tree, index: usize) -> usize {
+        let node = &tree.nodes[index];
+        let scale = self.c_puct * (node.visits as f32).sqrt();
+        let score = |child: usize| {
+            let child = &tree.nodes[child];
+            child.mean() + scale * child.prior / (1.0 + child.visits as f32)
+        };
+        let mut best = node.children[0];
+        for &child in &node.children[1..] {
+            if score(child) > score(best) {
+                best = child;
+            }
+        }
+        best
+    }
+}
+
+impl<E: Evaluator> Engine for Mcts<E> {
+    fn name(&self) -> &'static str {
+        "mcts"
+    }
+
+    // Scores each column by the number of times the search visited it.
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        if board.is_full() {
+            return Vec::new();
+        }
+        let visits = self.search(board).get_visits();
+        (0..WIDTH)
+            .filter(|&col| board.is_valid_move(col))
+            .map(|col| (col, visits[col] as f64))
+            .collect()
+    }
+
+    fn analyze(&mut self, board: &Board) -> Option<Info> {
+        self.analyze_with_progress(board, &mut |_| {})
+    }
+
+    fn analyze_with_progress(
+        &mut self,
+        board: &Board,
+        progress: &mut dyn FnMut(&Info),
+    ) -> Option<Info> {
+        if board.is_full() {
+            return None;
+        }
+        let tree = self.search_with_progress(board, &mut |tree| {
+            if let Some(info) = tree.get_info() {
+                progress(&info);
+            }
+        });
+        tree.get_info()
+    }
+}
+
+#[cfg(test)]
+mod mcts_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{Evaluation, Evaluator, Mcts, Rollouts, REPORT_INTERVAL};
+    use crate::board::{Board, WIDTH};
+    use crate::engine::Engine;
+    use crate::nn::{Network, DEFAULT_HIDDEN};
+
+    // Thinks every position is even and every move as good as another.
+    struct Uniform;
+
+    impl Evaluator for Uniform {
+        fn evaluate(&mut self, _board: &Board) -> Evaluation {
+            Evaluation {
+                policy: [1.0 / WIDTH as f32; WIDTH],
+                value: 0.0,
+            }
+        }
+    }
+
+    #[test]
+    fn finds_wins_and_blocks() {
+        // X has three in column 3 and O two in column 2.
+        let board = Board::from_move_string("32323").unwrap();
+        let mut engine = Mcts::with_simulations(Uniform, 200);
+        // After O's third disc in column 2, X wins in column 3.
+        let mut next = board;
+        next.make_move(2);
+        assert_eq!(engine.choose_move(&next), Some(3));
+
+        // So O must block it.
+        assert_eq!(engine.choose_move(&board), Some(3));
+        let info = engine.analyze(&board).unwrap();
+        assert_eq!(info.pv[0], 3);
+
+        // Progress is reported every REPORT_INTERVAL simulations before the end.
+        let mut reports = Vec::new();
+        let last = engine
+            .analyze_with_progress(&board, &mut |info| reports.push(info.clone()))
+            .unwrap();
+        assert_eq!(reports.len(), 200 / REPORT_INTERVAL - 1);
+        assert_eq!(last.pv[0], 3);
+    }
+
+    #[test]
+    fn single_simulation() {
+        // The root is expanded but no move is visited, so every prior is equal
+        // and the first column is played.
+        let mut engine = Mcts::with_simulations(Uniform, 1);
+        let info = engine
+            .analyze(&Board::from_move_string("0").unwrap())
+            .unwrap();
+        assert_eq!((info.depth, info.pv), (1, vec![0]));
+        assert!(engine.choose_move(&Board::new()).is_some());
+    }
+
+    #[test]
+    fn evaluators_guide_the_search() {
+        let board = Board::from_move_string("33").unwrap();
+        let mut network = Mcts::with_simulations(Network::new(&DEFAULT_HIDDEN, 3), 50);
+        let visits = network.search(&board).get_visits();
+        assert_eq!(visits.iter().sum::<u32>(), 49);
+
+        let rollouts = Rollouts::with_rng(4, StdRng::seed_from_u64(1));
+        let mut engine = Mcts::with_simulations(rollouts, 100);
+        let scores = engine.score_moves(&board);
+        assert_eq!(scores.len(), WIDTH);
+        assert!(engine.choose_move(&board).is_some());
+    }
+}
@@ -0,0 +1,134 @@
+// Misère connect 4: the player who completes four in a row loses
+//
+// The board plays the same, so misère games use `Board` unchanged and read
+// its results through an `Objective`. Moves that complete four are still
+// legal, but are only worth playing when every other move does too.
+
+use crate::board::{Cell, MoveResult, HEIGHT, WIDTH};
+use crate::rules::{Move, Rules, Standard};
+
+/// What the players are trying to do with four in a row.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Objective {
+    #[default]
+    Standard,
+    Misere,
+}
+
+impl Objective {
+    pub fn parse(name: &str) -> Result<Objective, String> {
+        match name {
+            "standard" => Ok(Objective::Standard),
+            "misere" => Ok(Objective::Misere),
+            _ => Err(format!(
+                "Unknown rules: {name} (expected standard or misere)"
+            )),
+        }
+    }
+
+    /// Turns the result `Board::make_move` returns into the result of the game.
+    pub fn interpret(self, result: MoveResult) -> MoveResult {
+        match (self, result) {
+            (Objective::Misere, MoveResult::WinX) => MoveResult::WinO,
+            (Objective::Misere, MoveResult::WinO) => MoveResult::WinX,
+            _ => result,
+        }
+    }
+}
+
+/// Misère rules on the standard board.
+#[derive(Clone, Default)]
+pub struct Misere {
+    game: Standard,
+}
+
+impl Misere {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Rules for Misere {
+    fn name(&self) -> &'static str {
+        "misere"
+    }
+
+    fn get_width(&self) -> usize {
+        WIDTH
+    }
+
+    fn get_height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn get(&self, col: usize, y: usize) -> Cell {
+        self.game.get(col, y)
+    }
+
+    fn get_current_player(&self) -> Cell {
+        self.game.get_current_player()
+    }
+
+    fn get_moves(&self) -> &[Move] {
+        self.game.get_moves()
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        self.game.legal_moves()
+    }
+
+    fn apply(&mut self, mv: Move) -> MoveResult {
+        Objective::Misere.interpret(self.game.apply(mv))
+    }
+
+    fn outcome(&self) -> MoveResult {
+        Objective::Misere.interpret(self.game.outcome())
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod misere_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{Misere, Objective};
+    use crate::board::MoveResult;
+    use crate::engine::MonteCarlo;
+    use crate::rules::{play_notation, Move, Rules};
+
+    #[test]
+    fn four_in_a_row_loses() {
+        assert_eq!(
+            Objective::Misere.interpret(MoveResult::WinX),
+            MoveResult::WinO
+        );
+        assert_eq!(
+            Objective::Standard.interpret(MoveResult::WinX),
+            MoveResult::WinX
+        );
+        assert_eq!(
+            Objective::Misere.interpret(MoveResult::Draw),
+            MoveResult::Draw
+        );
+
+        let mut game = Misere::new();
+        play_notation(&mut game, "010101").unwrap();
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::WinO);
+        assert_eq!(game.outcome(), MoveResult::WinO);
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn engine_avoids_four() {
+        let mut game = Misere::new();
+        play_notation(&mut game, "010101").unwrap();
+        let mut engine = MonteCarlo::with_rng(20, StdRng::seed_from_u64(4));
+        let scores = engine.score_variant_moves(&game);
+        assert!(scores.contains(&(Move::Drop(0), f64::NEG_INFINITY)));
+        assert_ne!(engine.choose_variant_move(&game), Some(Move::Drop(0)));
+    }
+}
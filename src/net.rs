@@ -0,0 +1,625 @@
+// Two player games over TCP with a small line protocol
+//
+// One player hosts the game and plays Red, the other joins it and plays
+// Yellow. Every message is a single line of text:
+//
+//   HELLO <version> <name>                   Client: first message after connecting
+//   WELCOME <version> <color> <moves> <status> <name>
+//                                            Host: the client's color, the moves
+//                                            played so far, "-" if there are none,
+//                                            and the status of the game
+//   MOVE <column>                            Play a column
+//   RESIGN                                   Give up the game
+//   DRAW OFFER | DRAW ACCEPT | DRAW DECLINE  Offer a draw or answer an offer
+//   CHAT <text>                              Say something to the other player
+//   ERROR <text>                             Report a message that was refused
+//
+// The status is "playing", "offer-red" or "offer-yellow" while a draw offer
+// waits for an answer, "resigned-red" or "resigned-yellow" and "draw-agreed".
+//
+// The host keeps the authoritative copy of the game. When the connection
+// drops, the client connects again and the WELCOME brings it back in sync.
+// Both ends check every move against their own board before accepting it.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::board::Cell;
+use crate::game::Game;
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+pub const DEFAULT_PORT: u16 = 4444;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Welcome {
+    pub version: u32,
+
+    // Name of the host.
+    pub name: String,
+
+    // Color played by the client.
+    pub color: Cell,
+
+    pub moves: Vec<usize>,
+
+    // Player whose draw offer is waiting for an answer.
+    pub draw_offer: Option<Cell>,
+
+    pub ending: Option<Ending>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    Hello { version: u32, name: String },
+    Welcome(Welcome),
+    Move(usize),
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Chat(String),
+    Error(String),
+}
+
+impl Message {
+    pub fn parse(line: &str) -> Result<Message, String> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let message = match (command, rest) {
+            ("HELLO", rest) => {
+                let (version, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                Message::Hello {
+                    version: parse_version(version)?,
+                    name: name.to_string(),
+                }
+            }
+            ("WELCOME", rest) => {
+                let fields: Vec<&str> = rest.splitn(5, ' ').collect();
+                let [version, color, moves, status, name] = fields[..] else {
+                    return Err(format!("invalid message: {line}"));
+                };
+                let (draw_offer, ending) = parse_status(status)?;
+                Message::Welcome(Welcome {
+                    version: parse_version(version)?,
+                    name: name.to_string(),
+                    color: parse_color(color)?,
+                    moves: parse_moves(moves)?,
+                    draw_offer,
+                    ending,
+                })
+            }
+            ("MOVE", col) => {
+                Message::Move(col.parse().map_err(|_| format!("invalid move: {col}"))?)
+            }
+            ("RESIGN", "") => Message::Resign,
+            ("DRAW", "OFFER") => Message::OfferDraw,
+            ("DRAW", "ACCEPT") => Message::AcceptDraw,
+            ("DRAW", "DECLINE") => Message::DeclineDraw,
+            ("CHAT", text) => Message::Chat(text.to_string()),
+            ("ERROR", text) => Message::Error(text.to_string()),
+            _ => return Err(format!("invalid message: {line}")),
+        };
+        Ok(message)
+    }
+
+    /// Formats the message as a line, without the newline.
+    pub fn to_line(&self) -> String {
+        match self {
+            Message::Hello { version, name } => format!("HELLO {version} {name}"),
+            Message::Welcome(welcome) => {
+                let moves: String = welcome.moves.iter().map(|col| col.to_string()).collect();
+                format!(
+                    "WELCOME {} {} {} {} {}",
+                    welcome.version,
+                    color_name(welcome.color),
+                    if moves.is_empty() { "-" } else { &moves },
+                    status_name(welcome.draw_offer, welcome.ending),
+                    welcome.name
+                )
+            }
+            Message::Move(col) => format!("MOVE {col}"),
+            Message::Resign => "RESIGN".to_string(),
+            Message::OfferDraw => "DRAW OFFER".to_string(),
+            Message::AcceptDraw => "DRAW ACCEPT".to_string(),
+            Message::DeclineDraw => "DRAW DECLINE".to_string(),
+            Message::Chat(text) => format!("CHAT {}", text.replace(['\r', '\n'], " ")),
+            Message::Error(text) => format!("ERROR {}", text.replace(['\r', '\n'], " ")),
+        }
+    }
+}
+
+fn parse_version(version: &str) -> Result<u32, String> {
+    version
+        .parse()
+        .map_err(|_| format!("invalid version: {version}"))
+}
+
+pub fn color_name(cell: Cell) -> &'static str {
+    match cell {
+        Cell::X => "red",
+        Cell::O => "yellow",
+        Cell::Empty => "nobody",
+    }
+}
+
+fn parse_color(color: &str) -> Result<Cell, String> {
+    match color {
+        "red" => Ok(Cell::X),
+        "yellow" => Ok(Cell::O),
+        _ => Err(format!("invalid color: {color}")),
+    }
+}
+
+fn parse_moves(moves: &str) -> Result<Vec<usize>, String> {
+    if moves == "-" {
+        return Ok(Vec::new());
+    }
+    moves
+        .chars()
+        .map(|c| c.to_digit(10).map(|d| d as usize))
+        .collect::<Option<_>>()
+        .ok_or(format!("invalid moves: {moves}"))
+}
+
+fn status_name(draw_offer: Option<Cell>, ending: Option<Ending>) -> String {
+    match (ending, draw_offer) {
+        (Some(Ending::Resigned(cell)), _) => format!("resigned-{}", color_name(cell)),
+        (Some(Ending::DrawAgreed), _) => "draw-agreed".to_string(),
+        (None, Some(cell)) => format!("offer-{}", color_name(cell)),
+        (None, None) => "playing".to_string(),
+    }
+}
+
+fn parse_status(status: &str) -> Result<(Option<Cell>, Option<Ending>), String> {
+    let invalid = || format!("invalid status: {status}");
+    if status == "playing" {
+        return Ok((None, None));
+    }
+    if status == "draw-agreed" {
+        return Ok((None, Some(Ending::DrawAgreed)));
+    }
+    let (kind, color) = status.split_once('-').ok_or_else(invalid)?;
+    let color = parse_color(color).map_err(|_| invalid())?;
+    match kind {
+        "offer" => Ok((Some(color), None)),
+        "resigned" => Ok((None, Some(Ending::Resigned(color)))),
+        _ => Err(invalid()),
+    }
+}
+
+/// How a game ended other than on the board.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ending {
+    Resigned(Cell),
+    DrawAgreed,
+}
+
+/// The rules of a networked game, seen from one of the players.
+pub struct Session {
+    game: Game,
+
+    // Color of the local player.
+    color: Cell,
+
+    // Player whose draw offer is waiting for an answer.
+    draw_offer: Option<Cell>,
+
+    ending: Option<Ending>,
+}
+
+impl Session {
+    pub fn new(color: Cell) -> Self {
+        Self {
+            game: Game::new(),
+            color,
+            draw_offer: None,
+            ending: None,
+        }
+    }
+
+    pub fn get_game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn get_color(&self) -> Cell {
+        self.color
+    }
+
+    pub fn get_draw_offer(&self) -> Option<Cell> {
+        self.draw_offer
+    }
+
+    pub fn get_ending(&self) -> Option<Ending> {
+        self.ending
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.ending.is_some() || self.game.is_over()
+    }
+
+    pub fn is_local_turn(&self) -> bool {
+        !self.is_over() && self.game.get_board().get_current_player() == self.color
+    }
+
+    /// Replaces the game with the one sent by the host when connecting.
+    pub fn sync(&mut self, welcome: &Welcome) -> Result<(), String> {
+        let mut game = Game::new();
+        for &col in &welcome.moves {
+            if !game.get_board().is_valid_move(col) || game.is_over() {
+                return Err(format!("the host sent an illegal move in column {col}"));
+            }
+            game.make_move(col);
+        }
+        self.game = game;
+        self.draw_offer = welcome.draw_offer;
+        self.ending = welcome.ending;
+        Ok(())
+    }
+
+    /// Plays a move for the local player, returning the message to send.
+    pub fn play(&mut self, col: usize) -> Result<Message, String> {
+        self.make_move(self.color, col)?;
+        Ok(Message::Move(col))
+    }
+
+    pub fn resign(&mut self) -> Result<Message, String> {
+        self.check_playing()?;
+        self.ending = Some(Ending::Resigned(self.color));
+        Ok(Message::Resign)
+    }
+
+    pub fn offer_draw(&mut self) -> Result<Message, String> {
+        self.offer(self.color)?;
+        Ok(Message::OfferDraw)
+    }
+
+    pub fn answer_draw(&mut self, accept: bool) -> Result<Message, String> {
+        self.answer(self.color, accept)?;
+        Ok(if accept {
+            Message::AcceptDraw
+        } else {
+            Message::DeclineDraw
+        })
+    }
+
+    /// Applies a message from the other player, refusing moves and answers
+    /// that are not allowed.
+    pub fn receive(&mut self, message: &Message) -> Result<(), String> {
+        let opponent = self.color.switch();
+        match message {
+            Message::Move(col) => self.make_move(opponent, *col),
+            Message::Resign => {
+                self.check_playing()?;
+                self.ending = Some(Ending::Resigned(opponent));
+                Ok(())
+            }
+            Message::OfferDraw => self.offer(opponent),
+            Message::AcceptDraw => self.answer(opponent, true),
+            Message::DeclineDraw => self.answer(opponent, false),
+            Message::Chat(_) | Message::Error(_) => Ok(()),
+            Message::Hello { .. } | Message::Welcome(_) => {
+                Err("unexpected handshake message".to_string())
+            }
+        }
+    }
+
+    fn check_playing(&self) -> Result<(), String> {
+        if self.is_over() {
+            return Err("the game is over".to_string());
+        }
+        Ok(())
+    }
+
+    fn make_move(&mut self, player: Cell, col: usize) -> Result<(), String> {
+        self.check_playing()?;
+        let board = self.game.get_board();
+        if board.get_current_player() != player {
+            return Err(format!("it is not {}'s turn", color_name(player)));
+        }
+        if !board.is_valid_move(col) {
+            return Err(format!("column {col} is not a valid move"));
+        }
+        self.game.make_move(col);
+        self.draw_offer = None;
+        Ok(())
+    }
+
+    fn offer(&mut self, player: Cell) -> Result<(), String> {
+        self.check_playing()?;
+        if self.draw_offer.is_some() {
+            return Err("a draw has already been offered".to_string());
+        }
+        self.draw_offer = Some(player);
+        Ok(())
+    }
+
+    fn answer(&mut self, player: Cell, accept: bool) -> Result<(), String> {
+        self.check_playing()?;
+        if self.draw_offer != Some(player.switch()) {
+            return Err("there is no draw offer to answer".to_string());
+        }
+        self.draw_offer = None;
+        if accept {
+            self.ending = Some(Ending::DrawAgreed);
+        }
+        Ok(())
+    }
+}
+
+/// A connection to the other player that sends and receives messages.
+pub struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        })
+    }
+
+    /// Returns another handle to the connection, so that one thread can wait
+    /// for messages while another sends them.
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Connection::new(self.stream.try_clone()?)
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        writeln!(self.stream, "{}", message.to_line())
+    }
+
+    /// Waits for the next message, failing if the connection is closed or the
+    /// other end sends something that is not a message.
+    pub fn receive(&mut self) -> io::Result<Message> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        }
+        Message::parse(&line).map_err(invalid_data)
+    }
+
+    pub fn close(&self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Waits for a player to join the session hosted on the listener. Answers
+/// their HELLO with the game so far and returns the connection and their name.
+pub fn accept(
+    listener: &TcpListener,
+    name: &str,
+    session: &Session,
+) -> io::Result<(Connection, String)> {
+    let (stream, _) = listener.accept()?;
+    // On some systems the stream is non-blocking if the listener is.
+    stream.set_nonblocking(false)?;
+    let mut connection = Connection::new(stream)?;
+    match connection.receive()? {
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            name: peer,
+        } => {
+            let welcome = Welcome {
+                version: PROTOCOL_VERSION,
+                name: name.to_string(),
+                color: session.get_color().switch(),
+                moves: session.get_game().get_moves().to_vec(),
+                draw_offer: session.get_draw_offer(),
+                ending: session.get_ending(),
+            };
+            connection.send(&Message::Welcome(welcome))?;
+            Ok((connection, peer))
+        }
+        Message::Hello { version, .. } => {
+            let error = format!("unsupported protocol version {version}");
+            connection.send(&Message::Error(error.clone()))?;
+            Err(invalid_data(error))
+        }
+        message => Err(invalid_data(format!(
+            "expected HELLO, got {}",
+            message.to_line()
+        ))),
+    }
+}
+
+/// Joins a game hosted at the given address.
+pub fn connect(addr: impl ToSocketAddrs, name: &str) -> io::Result<(Connection, Welcome)> {
+    let mut connection = Connection::new(TcpStream::connect(addr)?)?;
+    connection.send(&Message::Hello {
+        version: PROTOCOL_VERSION,
+        name: name.to_string(),
+    })?;
+    match connection.receive()? {
+        Message::Welcome(welcome) if welcome.version == PROTOCOL_VERSION => {
+            Ok((connection, welcome))
+        }
+        Message::Error(error) => Err(invalid_data(error)),
+        message => Err(invalid_data(format!(
+            "expected WELCOME, got {}",
+            message.to_line()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod net_tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::{accept, connect, Ending, Message, Session, Welcome};
+    use crate::board::Cell;
+
+    #[test]
+    fn message_lines() {
+        let messages = [
+            Message::Hello {
+                version: 1,
+                name: "Ada L".to_string(),
+            },
+            Message::Welcome(Welcome {
+                version: 1,
+                name: "Bob".to_string(),
+                color: Cell::O,
+                moves: vec![3, 3, 2],
+                draw_offer: Some(Cell::X),
+                ending: None,
+            }),
+            Message::Welcome(Welcome {
+                version: 1,
+                name: "Bob".to_string(),
+                color: Cell::X,
+                moves: vec![],
+                draw_offer: None,
+                ending: None,
+            }),
+            Message::Welcome(Welcome {
+                version: 1,
+                name: "Bob".to_string(),
+                color: Cell::X,
+                moves: vec![3],
+                draw_offer: None,
+                ending: Some(Ending::Resigned(Cell::O)),
+            }),
+            Message::Move(6),
+            Message::Resign,
+            Message::OfferDraw,
+            Message::AcceptDraw,
+            Message::DeclineDraw,
+            Message::Chat("good game".to_string()),
+            Message::Error("column 9 is not a valid move".to_string()),
+        ];
+        for message in messages {
+            assert_eq!(Message::parse(&message.to_line()), Ok(message));
+        }
+        assert_eq!(Message::parse("MOVE 3\r\n"), Ok(Message::Move(3)));
+        assert_eq!(
+            Message::Chat("two\nlines".to_string()).to_line(),
+            "CHAT two lines"
+        );
+
+        for line in [
+            "",
+            "MOVE",
+            "MOVE x",
+            "RESIGN now",
+            "DRAW",
+            "WELCOME 1 red - Bob",
+            "WELCOME 1 red - resigned-blue Bob",
+            "HELLO x y",
+        ] {
+            assert!(Message::parse(line).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn session_rules() {
+        let mut red = Session::new(Cell::X);
+        let mut yellow = Session::new(Cell::O);
+        assert!(yellow.play(3).is_err());
+        assert!(red.receive(&Message::Move(3)).is_err());
+
+        let message = red.play(3).unwrap();
+        yellow.receive(&message).unwrap();
+        assert!(yellow.is_local_turn());
+        assert!(yellow.receive(&Message::Move(2)).is_err());
+        assert!(yellow.play(7).is_err());
+
+        // A draw offer stands until it is answered or a move is made.
+        yellow.receive(&red.offer_draw().unwrap()).unwrap();
+        assert_eq!(yellow.get_draw_offer(), Some(Cell::X));
+        assert!(red.answer_draw(true).is_err());
+        red.receive(&yellow.play(3).unwrap()).unwrap();
+        assert_eq!(red.get_draw_offer(), None);
+
+        red.receive(&yellow.offer_draw().unwrap()).unwrap();
+        yellow.receive(&red.answer_draw(true).unwrap()).unwrap();
+        assert_eq!(red.get_ending(), Some(Ending::DrawAgreed));
+        assert_eq!(yellow.get_ending(), Some(Ending::DrawAgreed));
+        assert!(red.play(0).is_err());
+
+        let mut red = Session::new(Cell::X);
+        red.receive(&Message::Resign).unwrap();
+        assert_eq!(red.get_ending(), Some(Ending::Resigned(Cell::O)));
+        assert!(red.is_over());
+    }
+
+    #[test]
+    fn sync_checks_moves() {
+        let welcome = |moves: &[usize], draw_offer| Welcome {
+            version: 2,
+            name: "host".to_string(),
+            color: Cell::O,
+            moves: moves.to_vec(),
+            draw_offer,
+            ending: None,
+        };
+        let mut session = Session::new(Cell::O);
+        session.sync(&welcome(&[3, 3, 2], Some(Cell::X))).unwrap();
+        assert_eq!(session.get_game().get_moves(), &[3, 3, 2]);
+        assert_eq!(session.get_draw_offer(), Some(Cell::X));
+        assert!(session.is_local_turn());
+        assert!(session
+            .sync(&welcome(&[0, 0, 0, 0, 0, 0, 0], None))
+            .is_err());
+        assert!(session
+            .sync(&welcome(&[0, 1, 0, 1, 0, 1, 0, 1], None))
+            .is_err());
+        assert_eq!(session.get_game().get_moves(), &[3, 3, 2]);
+    }
+
+    #[test]
+    fn play_and_reconnect_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let (mut connection, welcome) = connect(addr, "yellow player").unwrap();
+            assert_eq!(welcome.name, "red player");
+            assert_eq!(welcome.color, Cell::O);
+            let mut session = Session::new(welcome.color);
+            session.sync(&welcome).unwrap();
+
+            session.receive(&connection.receive().unwrap()).unwrap();
+            connection.send(&session.play(4).unwrap()).unwrap();
+            assert_eq!(
+                connection.receive().unwrap(),
+                Message::Chat("hi".to_string())
+            );
+            connection.close();
+
+            // Connecting again brings back the game so far.
+            let (mut connection, welcome) = connect(addr, "yellow player").unwrap();
+            assert_eq!(welcome.moves, vec![3, 4]);
+            session.sync(&welcome).unwrap();
+            connection.send(&Message::Move(5)).unwrap();
+            assert_eq!(
+                connection.receive().unwrap(),
+                Message::Error("it is not yellow's turn".to_string())
+            );
+        });
+
+        let mut session = Session::new(Cell::X);
+        let (mut connection, peer) = accept(&listener, "red player", &session).unwrap();
+        assert_eq!(peer, "yellow player");
+        connection.send(&session.play(3).unwrap()).unwrap();
+        session.receive(&connection.receive().unwrap()).unwrap();
+        connection.send(&Message::Chat("hi".to_string())).unwrap();
+        assert!(connection.receive().is_err());
+
+        let (mut connection, _) = accept(&listener, "red player", &session).unwrap();
+        let message = connection.receive().unwrap();
+        let error = session.receive(&message).unwrap_err();
+        connection.send(&Message::Error(error)).unwrap();
+        client.join().unwrap();
+        assert_eq!(session.get_game().get_moves(), &[3, 4]);
+    }
+}
@@ -0,0 +1,342 @@
+// Small policy and value network for guiding the tree search, run on the CPU
+//
+// The input is two planes of the board seen by the player to move: their own
+// discs, then the opponent's, each column by column from the bottom. Fully
+// connected layers with ReLU activations lead to a policy head with one logit
+// per column and a value head giving the expected result for the player to
+// move, between -1 for a loss and 1 for a win.
+//
+// A weights file is a 20 byte header, the hidden layer sizes and then the
+// parameters:
+//
+//   offset  0  magic "C4NN"
+//           4  format version: u32
+//           8  board width: u16, board height: u16
+//          12  hidden layer count: u32
+//          16  CRC-32 of everything after the header: u32
+//          20  hidden layer sizes: u32 each
+//              parameters: f32 each, for each hidden layer, then the policy
+//              head and the value head, the weights one output at a time
+//              followed by the biases
+//
+// All numbers are little endian.
+
+use std::fs;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::{Board, HEIGHT, WIDTH};
+use crate::mcts::{Evaluation, Evaluator};
+
+const MAGIC: &[u8; 4] = b"C4NN";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 20;
+
+/// Number of network inputs: one per cell for each player.
+pub const INPUTS: usize = 2 * WIDTH * HEIGHT;
+
+/// Hidden layer sizes of a new network when none are given.
+pub const DEFAULT_HIDDEN: [usize; 2] = [128, 64];
+
+/// A fully connected layer.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layer {
+    pub inputs: usize,
+    pub outputs: usize,
+
+    // Weights of output o from input i are at o * inputs + i.
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+}
+
+impl Layer {
+    // Starts with small random weights scaled to the number of inputs.
+    fn new(inputs: usize, outputs: usize, rng: &mut StdRng) -> Self {
+        let limit = (6.0 / inputs as f32).sqrt();
+        Self {
+            inputs,
+            outputs,
+            weights: (0..inputs * outputs)
+                .map(|_| rng.gen_range(-limit..limit))
+                .collect(),
+            biases: vec![0.0; outputs],
+        }
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.weights
+            .chunks(self.inputs)
+            .zip(&self.biases)
+            .map(|(row, bias)| bias + row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>())
+            .collect()
+    }
+
+    pub(crate) fn parameters(&self) -> impl Iterator<Item = &f32> {
+        self.weights.iter().chain(&self.biases)
+    }
+
+    pub(crate) fn parameters_mut(&mut self) -> impl Iterator<Item = &mut f32> {
+        self.weights.iter_mut().chain(&mut self.biases)
+    }
+}
+
+/// Activations of every layer for one input, as kept for training.
+pub struct Activations {
+    // The input followed by the output of each hidden layer after ReLU.
+    pub layers: Vec<Vec<f32>>,
+
+    pub logits: Vec<f32>,
+
+    // Value before tanh.
+    pub value: f32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Network {
+    pub hidden: Vec<Layer>,
+    pub policy: Layer,
+    pub value: Layer,
+}
+
+impl Network {
+    /// A randomly initialised network with the given hidden layer sizes.
+    pub fn new(hidden: &[usize], seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut inputs = INPUTS;
+        let mut layers = Vec::new();
+        for &outputs in hidden {
+            layers.push(Layer::new(inputs, outputs, &mut rng));
+            inputs = outputs;
+        }
+        Self {
+            hidden: layers,
+            policy: Layer::new(inputs, WIDTH, &mut rng),
+            value: Layer::new(inputs, 1, &mut rng),
+        }
+    }
+
+    pub fn get_hidden_sizes(&self) -> Vec<usize> {
+        self.hidden.iter().map(|layer| layer.outputs).collect()
+    }
+
+    /// Runs the network on an encoded board, keeping every layer's output.
+    pub fn forward(&self, input: &[f32]) -> Activations {
+        let mut layers = vec![input.to_vec()];
+        for layer in &self.hidden {
+            let output = layer.forward(layers.last().unwrap());
+            layers.push(output.into_iter().map(|x| x.max(0.0)).collect());
+        }
+        let last = layers.last().unwrap();
+        let logits = self.policy.forward(last);
+        let value = self.value.forward(last)[0];
+        Activations {
+            layers,
+            logits,
+            value,
+        }
+    }
+
+    pub(crate) fn layers(&self) -> impl Iterator<Item = &Layer> {
+        self.hidden.iter().chain([&self.policy, &self.value])
+    }
+
+    pub(crate) fn layers_mut(&mut self) -> impl Iterator<Item = &mut Layer> {
+        self.hidden
+            .iter_mut()
+            .chain([&mut self.policy, &mut self.value])
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for size in self.get_hidden_sizes() {
+            body.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+        for parameter in self.layers().flat_map(Layer::parameters) {
+            body.extend_from_slice(&parameter.to_le_bytes());
+        }
+        let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(WIDTH as u16).to_le_bytes());
+        bytes.extend_from_slice(&(HEIGHT as u16).to_le_bytes());
+        bytes.extend_from_slice(&(self.hidden.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    /// Reads a network from the bytes of a weights file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, String> {
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+            return Err("not a weights file".to_string());
+        }
+        let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let version = u32_at(4);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "unsupported format version {version} (expected {FORMAT_VERSION})"
+            ));
+        }
+        let (width, height) = (u16_at(8) as usize, u16_at(10) as usize);
+        if (width, height) != (WIDTH, HEIGHT) {
+            return Err(format!(
+                "made for a {width}x{height} board, not {WIDTH}x{HEIGHT}"
+            ));
+        }
+        if crc32fast::hash(&bytes[HEADER_LEN..]) != u32_at(16) {
+            return Err("checksum mismatch".to_string());
+        }
+
+        let count = u32_at(12) as usize;
+        let sizes_end = count
+            .checked_mul(4)
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .filter(|&end| end <= bytes.len())
+            .ok_or("truncated layer sizes")?;
+        let sizes: Vec<usize> = (HEADER_LEN..sizes_end)
+            .step_by(4)
+            .map(|i| u32_at(i) as usize)
+            .collect();
+        if sizes.contains(&0) {
+            return Err("empty hidden layer".to_string());
+        }
+
+        // Check the size before allocating, as the header could ask for any amount.
+        let expected = parameter_count(&sizes).ok_or("too many parameters")?;
+        let found = bytes.len() - sizes_end;
+        if expected.checked_mul(4) != Some(found) {
+            return Err(format!(
+                "expected {expected} parameters, found {found} bytes"
+            ));
+        }
+        let mut network = Network::new(&sizes, 0);
+        let values = bytes[sizes_end..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()));
+        for (parameter, value) in network
+            .layers_mut()
+            .flat_map(Layer::parameters_mut)
+            .zip(values)
+        {
+            *parameter = value;
+        }
+        Ok(network)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes()).map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn load(path: &str) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+        Network::from_bytes(&bytes).map_err(|e| format!("{path}: {e}"))
+    }
+}
+
+// Number of parameters of a network with the given hidden layer sizes, or
+// None if it overflows.
+fn parameter_count(hidden: &[usize]) -> Option<usize> {
+    let layer = |inputs: usize, outputs: usize| inputs.checked_add(1)?.checked_mul(outputs);
+    let mut count: usize = 0;
+    let mut inputs = INPUTS;
+    for &outputs in hidden {
+        count = count.checked_add(layer(inputs, outputs)?)?;
+        inputs = outputs;
+    }
+    // The policy and value heads.
+    count
+        .checked_add(layer(inputs, WIDTH)?)?
+        .checked_add(layer(inputs, 1)?)
+}
+
+impl Evaluator for Network {
+    fn evaluate(&mut self, board: &Board) -> Evaluation {
+        let activations = self.forward(&encode(board));
+        Evaluation {
+            policy: softmax(&activations.logits, board),
+            value: activations.value.tanh(),
+        }
+    }
+}
+
+/// Encodes the board as the network's input planes.
+pub fn encode(board: &Board) -> Vec<f32> {
+    let player = board.get_current_player();
+    let mut input = vec![0.0; INPUTS];
+    for col in 0..WIDTH {
+        for y in 0..board.get_height(col) {
+            let plane = if board.get(col, y) == player { 0 } else { 1 };
+            input[plane * WIDTH * HEIGHT + col * HEIGHT + y] = 1.0;
+        }
+    }
+    input
+}
+
+/// Turns logits into probabilities over the valid moves of the board.
+pub fn softmax(logits: &[f32], board: &Board) -> [f32; WIDTH] {
+    let valid = |col: usize| board.is_valid_move(col);
+    let max = (0..WIDTH)
+        .filter(|&col| valid(col))
+        .map(|col| logits[col])
+        .fold(f32::NEG_INFINITY, f32::max);
+    let mut policy = [0.0; WIDTH];
+    for col in (0..WIDTH).filter(|&col| valid(col)) {
+        policy[col] = (logits[col] - max).exp();
+    }
+    let total: f32 = policy.iter().sum();
+    if total > 0.0 {
+        policy.iter_mut().for_each(|p| *p /= total);
+    }
+    policy
+}
+
+#[cfg(test)]
+mod nn_tests {
+    use super::{encode, Network, DEFAULT_HIDDEN, INPUTS};
+    use crate::board::{Board, HEIGHT, WIDTH};
+    use crate::mcts::Evaluator;
+
+    #[test]
+    fn evaluates_valid_moves() {
+        let mut network = Network::new(&DEFAULT_HIDDEN, 1);
+        // Column 0 is full.
+        let board = Board::from_move_string("0000003").unwrap();
+        let input = encode(&board);
+        assert_eq!(input.len(), INPUTS);
+        // O is to move: X's four discs and O's three in column 0.
+        assert_eq!(input[..WIDTH * HEIGHT].iter().sum::<f32>(), 3.0);
+        assert_eq!(input[WIDTH * HEIGHT..].iter().sum::<f32>(), 4.0);
+
+        let evaluation = network.evaluate(&board);
+        assert_eq!(evaluation.policy[0], 0.0);
+        assert!((evaluation.policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!((-1.0..=1.0).contains(&evaluation.value));
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let network = Network::new(&[16, 8], 2);
+        let bytes = network.to_bytes();
+        assert_eq!(&bytes[0..4], b"C4NN");
+        assert_eq!(Network::from_bytes(&bytes).unwrap(), network);
+
+        let mut damaged = bytes.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(Network::from_bytes(&damaged)
+            .unwrap_err()
+            .contains("checksum"));
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+        assert!(Network::from_bytes(b"C4TB").is_err());
+
+        // Malformed layer sizes with a valid checksum are refused, not allocated.
+        for size in [0, u32::MAX] {
+            let mut bytes = Network::new(&[4], 2).to_bytes();
+            bytes[20..24].copy_from_slice(&size.to_le_bytes());
+            let checksum = crc32fast::hash(&bytes[20..]);
+            bytes[16..20].copy_from_slice(&checksum.to_le_bytes());
+            assert!(Network::from_bytes(&bytes).is_err(), "{size}");
+        }
+    }
+}
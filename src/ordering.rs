@@ -0,0 +1,316 @@
+// Move ordering for alpha-beta search
+//
+// Alpha-beta prunes the most when the best move is searched first. The moves
+// of a position are sorted by, in order of priority:
+//
+//   1. Threats: the number of new cells where the move leaves its player one
+//      piece away from four in a row.
+//   2. History: how often, weighted by depth, the move caused a cutoff anywhere.
+//   3. Killer moves: the last two moves that caused a cutoff at the same ply.
+//   4. Center first: columns closer to the center take part in more lines.
+//
+// Each heuristic can be turned off to measure how much it helps.
+
+use std::cmp::Reverse;
+
+use crate::board::{Board, Cell, HEIGHT, WIDTH};
+
+// Distance of each column from the center.
+const CENTER_DISTANCE: [usize; WIDTH] = [3, 2, 1, 0, 1, 2, 3];
+
+// Threats, history, killer rank and distance from the center, compared in order.
+type SortKey = (u32, u64, usize, Reverse<usize>);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Heuristics {
+    pub center_first: bool,
+    pub threats: bool,
+    pub killers: bool,
+    pub history: bool,
+}
+
+impl Heuristics {
+    pub const ALL: Heuristics = Heuristics {
+        center_first: true,
+        threats: true,
+        killers: true,
+        history: true,
+    };
+
+    pub const NONE: Heuristics = Heuristics {
+        center_first: false,
+        threats: false,
+        killers: false,
+        history: false,
+    };
+
+    pub const NAMES: [&'static str; 4] = ["center", "threats", "killers", "history"];
+
+    /// Parses a comma separated list of heuristic names, or "all" or "none".
+    pub fn parse(names: &str) -> Result<Heuristics, String> {
+        match names {
+            "all" => return Ok(Heuristics::ALL),
+            "none" => return Ok(Heuristics::NONE),
+            _ => {}
+        }
+        let mut heuristics = Heuristics::NONE;
+        for name in names.split(',') {
+            match name {
+                "center" => heuristics.center_first = true,
+                "threats" => heuristics.threats = true,
+                "killers" => heuristics.killers = true,
+                "history" => heuristics.history = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown heuristic: {name} (expected all, none or some of {})",
+                        Heuristics::NAMES.join(", ")
+                    ))
+                }
+            }
+        }
+        Ok(heuristics)
+    }
+}
+
+/// Counts how often the search stopped early, to measure the ordering.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct CutoffStats {
+    // Positions whose search stopped at a move scoring at least beta.
+    pub cutoffs: u64,
+
+    // Cutoffs caused by the first move searched.
+    pub first_move_cutoffs: u64,
+
+    // Moves searched in positions that were cut off, including the move that
+    // caused the cutoff.
+    pub moves_to_cutoff: u64,
+
+    // Positions where every move was searched.
+    pub full_searches: u64,
+}
+
+impl CutoffStats {
+    pub fn add(&mut self, other: &CutoffStats) {
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.moves_to_cutoff += other.moves_to_cutoff;
+        self.full_searches += other.full_searches;
+    }
+
+    /// Fraction of searched positions that were cut off.
+    pub fn cutoff_rate(&self) -> f64 {
+        self.cutoffs as f64 / (self.cutoffs + self.full_searches).max(1) as f64
+    }
+
+    /// Fraction of cutoffs caused by the first move, the ideal being 1.
+    pub fn first_move_rate(&self) -> f64 {
+        self.first_move_cutoffs as f64 / self.cutoffs.max(1) as f64
+    }
+
+    /// Mean number of moves searched before a cutoff, the ideal being 1.
+    pub fn mean_moves_to_cutoff(&self) -> f64 {
+        self.moves_to_cutoff as f64 / self.cutoffs.max(1) as f64
+    }
+}
+
+/// The valid moves of a position, in the order to search them.
+pub struct MoveList {
+    moves: [usize; WIDTH],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn as_slice(&self) -> &[usize] {
+        &self.moves[..self.len]
+    }
+}
+
+pub struct MoveOrdering {
+    heuristics: Heuristics,
+
+    // Two most recent moves causing a cutoff at each ply, newest first.
+    killers: [[Option<usize>; 2]; WIDTH * HEIGHT],
+
+    // Cutoff scores for each player and cell.
+    history: [[u64; WIDTH * HEIGHT]; 2],
+}
+
+impl MoveOrdering {
+    pub fn new(heuristics: Heuristics) -> Self {
+        Self {
+            heuristics,
+            killers: [[None; 2]; WIDTH * HEIGHT],
+            history: [[0; WIDTH * HEIGHT]; 2],
+        }
+    }
+
+    pub fn get_heuristics(&self) -> Heuristics {
+        self.heuristics
+    }
+
+    /// Forgets the killer moves and history learned so far.
+    pub fn clear(&mut self) {
+        self.killers = [[None; 2]; WIDTH * HEIGHT];
+        self.history = [[0; WIDTH * HEIGHT]; 2];
+    }
+
+    pub fn order(&self, board: &Board) -> MoveList {
+        let mut list = MoveList {
+            moves: [0; WIDTH],
+            len: 0,
+        };
+        let mut keys: [SortKey; WIDTH] = [(0, 0, 0, Reverse(0)); WIDTH];
+        for col in 0..WIDTH {
+            if board.is_valid_move(col) {
+                keys[list.len] = self.sort_key(board, col);
+                list.moves[list.len] = col;
+                list.len += 1;
+            }
+        }
+
+        // Insertion sort, highest key first, keeping equal keys in column order.
+        for i in 1..list.len {
+            let mut j = i;
+            while j > 0 && keys[j - 1] < keys[j] {
+                keys.swap(j - 1, j);
+                list.moves.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+        list
+    }
+
+    fn sort_key(&self, board: &Board, col: usize) -> SortKey {
+        let ply = board.get_move_count();
+        let killer = match self.killers[ply] {
+            _ if !self.heuristics.killers => 0,
+            [Some(first), _] if first == col => 2,
+            [_, Some(second)] if second == col => 1,
+            _ => 0,
+        };
+        let threats = if self.heuristics.threats {
+            new_threats(board, col)
+        } else {
+            0
+        };
+        let history = if self.heuristics.history {
+            self.history[player(board)][cell(board, col)]
+        } else {
+            0
+        };
+        let center = if self.heuristics.center_first {
+            CENTER_DISTANCE[col]
+        } else {
+            0
+        };
+        (threats, history, killer, Reverse(center))
+    }
+
+    /// Remembers a move that caused a cutoff.
+    pub fn record_cutoff(&mut self, board: &Board, col: usize) {
+        let ply = board.get_move_count();
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(col) {
+            killers[1] = killers[0];
+            killers[0] = Some(col);
+        }
+        // Cutoffs far from the end of the game save the most work.
+        let depth = (WIDTH * HEIGHT - ply) as u64;
+        self.history[player(board)][cell(board, col)] += depth * depth;
+    }
+}
+
+// Number of threats the player to move has after the move in the column but
+// not before it.
+fn new_threats(board: &Board, col: usize) -> u32 {
+    let mut before = *board;
+    before.pass();
+    let mut after = *board;
+    after.make_move(col);
+    after.count_threats().saturating_sub(before.count_threats())
+}
+
+// Index of the player to move in the history tables. Passes make it differ
+// from the parity of the move count.
+fn player(board: &Board) -> usize {
+    match board.get_current_player() {
+        Cell::X => 0,
+        _ => 1,
+    }
+}
+
+// Index of the cell a move in the column would fill.
+fn cell(board: &Board, col: usize) -> usize {
+    col * HEIGHT + board.get_height(col)
+}
+
+#[cfg(test)]
+mod ordering_tests {
+    use super::{new_threats, Heuristics, MoveOrdering};
+    use crate::board::{Board, WIDTH};
+
+    #[test]
+    fn parse_heuristics() {
+        assert_eq!(Heuristics::parse("all"), Ok(Heuristics::ALL));
+        assert_eq!(Heuristics::parse("none"), Ok(Heuristics::NONE));
+        let heuristics = Heuristics::parse("center,history").unwrap();
+        assert!(heuristics.center_first && heuristics.history);
+        assert!(!heuristics.threats && !heuristics.killers);
+        assert!(Heuristics::parse("center,luck").is_err());
+    }
+
+    #[test]
+    fn static_order() {
+        let board = Board::new();
+        let ordering = MoveOrdering::new(Heuristics::NONE);
+        assert_eq!(ordering.order(&board).as_slice(), &[0, 1, 2, 3, 4, 5, 6]);
+
+        let ordering = MoveOrdering::new(Heuristics::parse("center").unwrap());
+        assert_eq!(ordering.order(&board).as_slice(), &[3, 2, 4, 1, 5, 0, 6]);
+
+        // Full columns are left out.
+        let board = Board::from_move_string("333333").unwrap();
+        assert_eq!(ordering.order(&board).as_slice(), &[2, 4, 1, 5, 0, 6]);
+    }
+
+    #[test]
+    fn threats_first() {
+        // X has pieces in columns 1 and 2. Playing 3 makes two threats, and
+        // playing 0 or 4 one, with ties broken towards the center.
+        let board = Board::from_move_string("1626").unwrap();
+        let ordering = MoveOrdering::new(Heuristics::ALL);
+        assert_eq!(&ordering.order(&board).as_slice()[..3], &[3, 4, 0]);
+    }
+
+    #[test]
+    fn killers_and_history() {
+        let board = Board::from_move_string("3").unwrap();
+        let mut ordering = MoveOrdering::new(Heuristics::parse("killers").unwrap());
+        ordering.record_cutoff(&board, 6);
+        ordering.record_cutoff(&board, 5);
+        assert_eq!(&ordering.order(&board).as_slice()[..3], &[5, 6, 0]);
+
+        let mut ordering = MoveOrdering::new(Heuristics::parse("history").unwrap());
+        ordering.record_cutoff(&board, 6);
+        ordering.record_cutoff(&Board::from_move_string("32").unwrap(), 5);
+        assert_eq!(&ordering.order(&board).as_slice()[..2], &[6, 0]);
+        ordering.clear();
+        assert_eq!(ordering.order(&board).as_slice()[0], 0);
+
+        // History belongs to the player to move, also after a pass.
+        let mut passed = Board::from_move_string("3").unwrap();
+        passed.pass();
+        ordering.record_cutoff(&passed, 6);
+        let board = Board::from_move_string("33").unwrap();
+        assert_eq!(ordering.order(&board).as_slice()[0], 6);
+    }
+
+    #[test]
+    fn threats_count_only_new_ones() {
+        // X already threatens to finish a diagonal in column 3, row 3, and
+        // only playing column 5 adds another threat.
+        let board = Board::from_move_string("66545446").unwrap();
+        let threats: Vec<u32> = (0..WIDTH).map(|col| new_threats(&board, col)).collect();
+        assert_eq!(threats, [0, 0, 0, 0, 0, 1, 0]);
+    }
+}
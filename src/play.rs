@@ -1,56 +1,475 @@
 // Plays connect 4 using Monte Carlo
+//
+// A position is scored by playing games to the end from it with a rollout
+// policy and counting the results for one player. The score is the share of
+// the games won, with a draw counted as half a win, so 1 means every game was
+// won, 0 every game lost and 0.5 an even position.
 
 use crate::board::{self, Board, Cell, MoveResult};
+use crate::eval::Weights;
+use crate::misere::Objective;
 use rand::{thread_rng, Rng};
 
-const NUM_GAMES: usize = 5;
-
-pub fn monte_carlo(board: &Board, cell: Cell) -> f64 {
-    let mut rng = thread_rng();
-    let mut wins: isize = 0;
-    let mut moves = 0;
-    for _ in 0..NUM_GAMES {
-        let mut sim_board = board.clone();
-        let mut sim_cell = cell;
-        loop {
-            let mut valid_moves = Vec::new();
-            for x in 0..board::WIDTH {
-                if sim_board.is_valid_move(x) {
-                    valid_moves.push(x);
+// Default number of random games played per evaluation.
+pub const NUM_GAMES: usize = 5;
+
+/// Multiple of the standard error giving a 95% confidence interval.
+pub const Z_95: f64 = 1.96;
+
+/// How moves are chosen in the games played out from a position.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Policy {
+    /// Any valid column, all equally likely.
+    Uniform,
+
+    /// A winning move if there is one, else a move blocking the opponent's
+    /// win, else any valid column. Under misère rules, any column that does
+    /// not complete four, as there is nothing to block.
+    Tactical,
+
+    /// Wins first, then columns weighted by exp(evaluation / temperature) of
+    /// the position after the move, using the static evaluation weights. A
+    /// temperature of zero or less always picks the best evaluated column.
+    /// Under misère rules columns completing four are left out and the
+    /// evaluation changes sign.
+    Softmax { weights: Weights, temperature: f64 },
+}
+
+pub const POLICY_NAMES: [&str; 3] = ["uniform", "tactical", "softmax"];
+
+impl Policy {
+    /// The named policy, with the default evaluation weights for softmax.
+    pub fn parse(name: &str) -> Result<Policy, String> {
+        match name {
+            "uniform" => Ok(Policy::Uniform),
+            "tactical" => Ok(Policy::Tactical),
+            "softmax" => Ok(Policy::Softmax {
+                weights: Weights::default(),
+                temperature: 1.0,
+            }),
+            _ => Err(format!(
+                "Unknown rollout policy: {name} (expected one of {})",
+                POLICY_NAMES.join(", ")
+            )),
+        }
+    }
+
+    /// Picks a move for the player to move, or None if the board is full.
+    pub fn choose<R: Rng>(
+        &self,
+        board: &Board,
+        objective: Objective,
+        rng: &mut R,
+    ) -> Option<usize> {
+        let mut valid: Vec<usize> = (0..board::WIDTH)
+            .filter(|&col| board.is_valid_move(col))
+            .collect();
+        if valid.is_empty() {
+            return None;
+        }
+        if *self != Policy::Uniform {
+            match objective {
+                Objective::Standard => {
+                    if let Some(&col) = valid.iter().find(|&&col| wins(board, col)) {
+                        return Some(col);
+                    }
+                }
+                // Completing four loses, so only do it when every move does.
+                Objective::Misere => {
+                    let safe: Vec<usize> = valid
+                        .iter()
+                        .copied()
+                        .filter(|&col| !wins(board, col))
+                        .collect();
+                    if !safe.is_empty() {
+                        valid = safe;
+                    }
                 }
             }
-            if valid_moves.is_empty() {
-                break;
+        }
+        match self {
+            Policy::Uniform => {}
+            Policy::Tactical if objective == Objective::Misere => {}
+            Policy::Tactical => {
+                // A pass lets the opponent show where they would win.
+                let mut opponent = *board;
+                opponent.pass();
+                if let Some(&col) = valid.iter().find(|&&col| wins(&opponent, col)) {
+                    return Some(col);
+                }
             }
-            let index = rng.gen_range(0..valid_moves.len());
-            let x = valid_moves[index];
-            let result = sim_board.make_move(x);
-            moves += 1;
-            if result == MoveResult::WinX || result == MoveResult::WinO {
-                //println!("Game Result: {x}: {result:?}");
-                //sim_board.print();
-                if Cell::from(result) == cell {
-                    wins += 1;
-                } else {
-                    wins -= 1;
+            Policy::Softmax {
+                weights,
+                temperature,
+            } => {
+                let values: Vec<f64> = valid
+                    .iter()
+                    .map(|&col| {
+                        let mut next = *board;
+                        next.make_move(col);
+                        match objective {
+                            Objective::Standard => -weights.evaluate(&next),
+                            Objective::Misere => weights.evaluate(&next),
+                        }
+                    })
+                    .collect();
+                let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                if *temperature <= 0.0 {
+                    let best = values.iter().position(|&value| value == max).unwrap();
+                    return Some(valid[best]);
+                }
+                let odds: Vec<f64> = values
+                    .iter()
+                    .map(|value| ((value - max) / temperature).exp())
+                    .collect();
+                let mut pick = rng.gen_range(0.0..odds.iter().sum::<f64>());
+                for (&col, odds) in valid.iter().zip(&odds) {
+                    if pick < *odds {
+                        return Some(col);
+                    }
+                    pick -= odds;
                 }
-                break;
             }
-            sim_cell = match sim_cell {
-                Cell::X => Cell::O,
-                Cell::O => Cell::X,
-                Cell::Empty => unreachable!(),
-            };
-            if sim_board.is_full() {
-                //println!("Board is full");
-                //sim_board.print();
-                break;
+        }
+        Some(valid[rng.gen_range(0..valid.len())])
+    }
+}
+
+// Whether the player to move wins by playing in the column.
+fn wins(board: &Board, col: usize) -> bool {
+    let mut next = *board;
+    matches!(next.make_move(col), MoveResult::WinX | MoveResult::WinO)
+}
+
+/// Results of the games played from a position, for one player.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Stats {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+impl Stats {
+    pub fn get_games(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Share of the games won, counting draws as half. Even with no games.
+    pub fn score(&self) -> f64 {
+        match self.get_games() {
+            0 => 0.5,
+            games => (self.wins as f64 + 0.5 * self.draws as f64) / games as f64,
+        }
+    }
+
+    /// Interval around the score holding the true score with the confidence
+    /// the multiple of the standard error z gives, such as `Z_95`. Uses the
+    /// normal approximation, so it is rough for a handful of games.
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        let games = self.get_games();
+        if games == 0 {
+            return (0.0, 1.0);
+        }
+        let score = self.score();
+        // Variance of one game's result, which is 1, 0.5 or 0.
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games as f64;
+        let margin = z * (variance / games as f64).sqrt();
+        ((score - margin).max(0.0), (score + margin).min(1.0))
+    }
+}
+
+/// One column at the root, scored for the player making the move.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Candidate {
+    pub col: usize,
+    pub mover: Cell,
+
+    // Result of the game after the move under the objective,
+    // `MoveResult::None` if the game goes on.
+    pub result: MoveResult,
+
+    // Whether the opponent can complete four straight after the move.
+    pub allows_win: bool,
+
+    // Games played out after the move, none if the move decides the game.
+    pub stats: Stats,
+}
+
+impl Candidate {
+    /// Share of the games the mover wins, counting draws as half. A move
+    /// ending the game scores its result and one allowing a win scores 0.
+    pub fn score(&self) -> f64 {
+        match self.result {
+            MoveResult::WinX | MoveResult::WinO if self.is_win() => 1.0,
+            MoveResult::WinX | MoveResult::WinO => 0.0,
+            MoveResult::Draw => 0.5,
+            _ if self.allows_win => 0.0,
+            _ => self.stats.score(),
+        }
+    }
+
+    /// Whether the move wins the game on the spot.
+    pub fn is_win(&self) -> bool {
+        matches!(self.result, MoveResult::WinX | MoveResult::WinO)
+            && Cell::from(self.result) == self.mover
+    }
+}
+
+/// Makes each valid move on a copy of the board, scores the position after
+/// it for the mover and returns the moves best first. Wins come before
+/// everything else, and ties go to the lower column.
+pub fn evaluate_moves<R: Rng>(
+    board: &Board,
+    num_games: usize,
+    policy: Policy,
+    objective: Objective,
+    rng: &mut R,
+) -> Vec<Candidate> {
+    let mover = board.get_current_player();
+    let mut candidates = Vec::new();
+    for col in 0..board::WIDTH {
+        let mut next = *board;
+        let result = objective.interpret(next.make_move(col));
+        let mut candidate = Candidate {
+            col,
+            mover,
+            result,
+            allows_win: false,
+            stats: Stats::default(),
+        };
+        match result {
+            MoveResult::Illegal => continue,
+            MoveResult::None if objective == Objective::Standard => {
+                candidate.allows_win = (0..board::WIDTH).any(|reply| wins(&next, reply));
+                if !candidate.allows_win {
+                    candidate.stats = playouts(&next, mover, num_games, policy, objective, rng);
+                }
+            }
+            MoveResult::None => {
+                candidate.stats = playouts(&next, mover, num_games, policy, objective, rng);
+            }
+            _ => {}
+        }
+        candidates.push(candidate);
+    }
+    candidates.sort_by(|a, b| {
+        (b.is_win(), b.score())
+            .partial_cmp(&(a.is_win(), a.score()))
+            .unwrap()
+            .then(a.col.cmp(&b.col))
+    });
+    candidates
+}
+
+pub fn monte_carlo(board: &Board, cell: Cell, num_games: usize) -> f64 {
+    monte_carlo_for(board, cell, num_games, Objective::Standard)
+}
+
+/// Like `monte_carlo`, for games with the given objective.
+pub fn monte_carlo_for(board: &Board, cell: Cell, num_games: usize, objective: Objective) -> f64 {
+    playouts(
+        board,
+        cell,
+        num_games,
+        Policy::Uniform,
+        objective,
+        &mut thread_rng(),
+    )
+    .score()
+}
+
+/// Plays games to the end from the board with the policy and counts the
+/// results for the player `cell`.
+pub fn playouts<R: Rng>(
+    board: &Board,
+    cell: Cell,
+    num_games: usize,
+    policy: Policy,
+    objective: Objective,
+    rng: &mut R,
+) -> Stats {
+    let mut stats = Stats::default();
+    for _ in 0..num_games {
+        let mut sim_board = *board;
+        match objective.interpret(playout(&mut sim_board, policy, objective, rng)) {
+            result @ (MoveResult::WinX | MoveResult::WinO) if Cell::from(result) == cell => {
+                stats.wins += 1
+            }
+            MoveResult::WinX | MoveResult::WinO => stats.losses += 1,
+            _ => stats.draws += 1,
+        }
+    }
+    stats
+}
+
+/// Plays moves chosen by the policy for the objective until the game is
+/// over. Returns the result of the final move as `Board::make_move` gives it,
+/// or `MoveResult::None` if the board had no valid moves to begin with.
+pub fn playout<R: Rng>(
+    board: &mut Board,
+    policy: Policy,
+    objective: Objective,
+    rng: &mut R,
+) -> MoveResult {
+    let mut result = MoveResult::None;
+    while result == MoveResult::None {
+        match policy.choose(board, objective, rng) {
+            Some(col) => result = board.make_move(col),
+            None => break,
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod play_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{evaluate_moves, monte_carlo, playouts, Policy, Stats, Z_95};
+    use crate::board::MoveResult;
+    use crate::board::{Board, Cell};
+    use crate::misere::Objective;
+
+    #[test]
+    fn scores_per_game() {
+        let stats = Stats {
+            wins: 6,
+            draws: 2,
+            losses: 2,
+        };
+        assert_eq!(stats.get_games(), 10);
+        assert_eq!(stats.score(), 0.7);
+        let (low, high) = stats.confidence_interval(Z_95);
+        assert!(low < 0.7 && 0.7 < high && high - low < 0.6, "{low} {high}");
+        assert_eq!(Stats::default().score(), 0.5);
+
+        let all_wins = Stats {
+            wins: 4,
+            ..Stats::default()
+        };
+        assert_eq!(all_wins.confidence_interval(Z_95), (1.0, 1.0));
+
+        // Uniform games count one result per game, however long.
+        let score = monte_carlo(&Board::new(), Cell::X, 10);
+        assert_eq!((score * 20.0).fract(), 0.0);
+
+        // X to move wins in column 0, which the tactical policy always finds.
+        let board = Board::from_move_string("010101").unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        let play = |cell, rng: &mut StdRng| {
+            playouts(&board, cell, 5, Policy::Tactical, Objective::Standard, rng).score()
+        };
+        assert_eq!(play(Cell::X, &mut rng), 1.0);
+        assert_eq!(play(Cell::O, &mut rng), 0.0);
+    }
+
+    #[test]
+    fn tactical_policy_wins_and_blocks() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // X to move can win in column 0.
+        let board = Board::from_move_string("010101").unwrap();
+        assert_eq!(
+            Policy::Tactical.choose(&board, Objective::Standard, &mut rng),
+            Some(0)
+        );
+
+        // O to move must block column 3.
+        let board = Board::from_move_string("06162").unwrap();
+        for _ in 0..10 {
+            assert_eq!(
+                Policy::Tactical.choose(&board, Objective::Standard, &mut rng),
+                Some(3)
+            );
+        }
+        let softmax = Policy::parse("softmax").unwrap();
+        assert!(softmax
+            .choose(&board, Objective::Standard, &mut rng)
+            .is_some());
+        // Without a temperature softmax always plays the best evaluated move.
+        let Policy::Softmax { weights, .. } = softmax else {
+            unreachable!()
+        };
+        let greedy = Policy::Softmax {
+            weights,
+            temperature: 0.0,
+        };
+        for _ in 0..10 {
+            assert_eq!(
+                greedy.choose(&board, Objective::Standard, &mut rng),
+                Some(3)
+            );
+        }
+        assert!(Policy::parse("greedy").is_err());
+
+        // Under misère rules X avoids completing four in column 0, and O
+        // does not block X's three along the bottom.
+        let board = Board::from_move_string("010101").unwrap();
+        for policy in [Policy::Tactical, softmax] {
+            for _ in 0..10 {
+                let col = policy.choose(&board, Objective::Misere, &mut rng);
+                assert_ne!(col, Some(0));
             }
         }
+        let board = Board::from_move_string("06162").unwrap();
+        assert!((0..20)
+            .any(|_| Policy::Tactical.choose(&board, Objective::Misere, &mut rng) != Some(3)));
+    }
+
+    #[test]
+    fn policies_play_whole_games() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for name in super::POLICY_NAMES {
+            let policy = Policy::parse(name).unwrap();
+            let stats = playouts(
+                &Board::new(),
+                Cell::X,
+                20,
+                policy,
+                Objective::Standard,
+                &mut rng,
+            );
+            assert_eq!(stats.get_games(), 20, "{name}");
+        }
     }
-    if moves == 0 {
-        0.0
-    } else {
-        wins as f64 / moves as f64
+
+    #[test]
+    fn evaluates_each_move() {
+        let mut rng = StdRng::seed_from_u64(4);
+        // O to move must block column 3, and every other move lets X win.
+        let board = Board::from_move_string("06162").unwrap();
+        let candidates = evaluate_moves(&board, 20, Policy::Uniform, Objective::Standard, &mut rng);
+        assert_eq!(candidates.len(), 7);
+        assert_eq!(candidates[0].col, 3);
+        assert_eq!(candidates[0].stats.get_games(), 20);
+        for candidate in &candidates[1..] {
+            assert!(candidate.allows_win, "{candidate:?}");
+            assert_eq!((candidate.score(), candidate.stats.get_games()), (0.0, 0));
+        }
+        // Ties go to the lower column.
+        assert!(candidates[1..]
+            .windows(2)
+            .all(|pair| pair[0].col < pair[1].col));
+
+        // X to move wins in column 0, which ranks first.
+        let board = Board::from_move_string("010101").unwrap();
+        let candidates = evaluate_moves(&board, 5, Policy::Uniform, Objective::Standard, &mut rng);
+        assert_eq!(
+            (candidates[0].col, candidates[0].result),
+            (0, MoveResult::WinX)
+        );
+        assert_eq!(candidates[0].score(), 1.0);
+
+        // Under misère rules the same move loses.
+        let candidates = evaluate_moves(&board, 5, Policy::Uniform, Objective::Misere, &mut rng);
+        let four = candidates
+            .iter()
+            .find(|candidate| candidate.col == 0)
+            .unwrap();
+        assert_eq!((four.result, four.score()), (MoveResult::WinO, 0.0));
+        assert!(!candidates[0].is_win());
     }
 }
@@ -0,0 +1,282 @@
+// Time controlled Monte Carlo engine that keeps thinking on the opponent's time
+//
+// After the engine moves, `ponder` starts a thread that plays random games
+// from every position the opponent's reply can lead to. When the reply
+// arrives, the games played from that position are kept and the engine's own
+// thinking time adds more games on top of them.
+
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::board::{Board, Cell, MoveResult, WIDTH};
+use crate::engine::Engine;
+use crate::misere::Objective;
+use crate::play::{self, Policy};
+use crate::tablebase;
+
+// Results of the random games played after one move.
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    games: u64,
+
+    // Sum of the results for the player making the move: 1 for a win, 0 for a
+    // draw and -1 for a loss.
+    total: f64,
+}
+
+// A position with the games played after each of its moves.
+struct Root {
+    board: Board,
+
+    // Result of making each move, so that moves ending the game are not played out.
+    results: [MoveResult; WIDTH],
+
+    // Scores of the moves leading to tablebase positions, which are not played out either.
+    known: [Option<f64>; WIDTH],
+
+    stats: [Stats; WIDTH],
+}
+
+impl Root {
+    fn new(board: Board) -> Self {
+        let mut results = [MoveResult::Illegal; WIDTH];
+        let mut known = [None; WIDTH];
+        for col in 0..WIDTH {
+            let mut next = board;
+            results[col] = next.make_move(col);
+            if results[col] == MoveResult::None {
+                known[col] = tablebase::probe(&next).map(|score| -score.signum() as f64);
+            }
+        }
+        Self {
+            board,
+            results,
+            known,
+            stats: [Stats::default(); WIDTH],
+        }
+    }
+
+    fn get_games(&self) -> u64 {
+        self.stats.iter().map(|stats| stats.games).sum()
+    }
+
+    // Plays one random game after each move that does not end the game.
+    fn play_round(&mut self, rng: &mut StdRng) {
+        let player = self.board.get_current_player();
+        for col in 0..WIDTH {
+            if self.results[col] != MoveResult::None || self.known[col].is_some() {
+                continue;
+            }
+            let mut next = self.board;
+            next.make_move(col);
+            let result = play::playout(&mut next, Policy::Uniform, Objective::Standard, rng);
+            let stats = &mut self.stats[col];
+            stats.games += 1;
+            stats.total += match result {
+                MoveResult::WinX | MoveResult::WinO if Cell::from(result) == player => 1.0,
+                MoveResult::WinX | MoveResult::WinO => -1.0,
+                _ => 0.0,
+            };
+        }
+    }
+
+    // Mean result of each valid move, with immediate wins scored as infinite.
+    fn scores(&self) -> Vec<(usize, f64)> {
+        let mut scores = Vec::new();
+        for (col, stats) in self.stats.iter().enumerate() {
+            let score = match self.results[col] {
+                MoveResult::Illegal => continue,
+                MoveResult::WinX | MoveResult::WinO => f64::INFINITY,
+                MoveResult::Draw => 0.0,
+                MoveResult::None => match (self.known[col], stats.games) {
+                    (Some(score), _) => score,
+                    (None, 0) => 0.0,
+                    (None, games) => stats.total / games as f64,
+                },
+            };
+            scores.push((col, score));
+        }
+        scores
+    }
+}
+
+// Plays rounds of random games from each root in turn until the deadline, if
+// any, or until asked to stop.
+fn search(roots: &mut [Root], deadline: Option<Instant>, stop: &AtomicBool, rng: &mut StdRng) {
+    loop {
+        for root in roots.iter_mut() {
+            if stop.load(Ordering::Relaxed) || deadline.is_some_and(|d| Instant::now() >= d) {
+                return;
+            }
+            root.play_round(rng);
+        }
+        if roots.is_empty() {
+            return;
+        }
+    }
+}
+
+struct Pondering {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Vec<Root>>,
+}
+
+/// Monte Carlo engine that plays random games for a fixed time per move, and
+/// for as long as it is left pondering.
+pub struct Ponderer {
+    think_time: Duration,
+    rng: StdRng,
+    pondering: Option<Pondering>,
+
+    // Games behind the last scores, and how many of those were played while pondering.
+    games: u64,
+    reused_games: u64,
+}
+
+impl Ponderer {
+    pub fn new(think_time: Duration) -> Self {
+        Self::with_rng(think_time, StdRng::from_entropy())
+    }
+
+    pub fn with_rng(think_time: Duration, rng: StdRng) -> Self {
+        Self {
+            think_time,
+            rng,
+            pondering: None,
+            games: 0,
+            reused_games: 0,
+        }
+    }
+
+    pub fn get_games(&self) -> u64 {
+        self.games
+    }
+
+    pub fn get_reused_games(&self) -> u64 {
+        self.reused_games
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.is_some()
+    }
+
+    /// Stops pondering, returning the positions that were searched.
+    fn stop_pondering(&mut self) -> Vec<Root> {
+        match self.pondering.take() {
+            Some(pondering) => {
+                pondering.stop.store(true, Ordering::Relaxed);
+                pondering.thread.join().unwrap()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Drop for Ponderer {
+    fn drop(&mut self) {
+        self.stop_pondering();
+    }
+}
+
+impl Engine for Ponderer {
+    fn name(&self) -> &'static str {
+        "ponder"
+    }
+
+    fn score_moves(&mut self, board: &Board) -> Vec<(usize, f64)> {
+        let mut root = self
+            .stop_pondering()
+            .into_iter()
+            .find(|root| root.board == *board)
+            .unwrap_or_else(|| Root::new(*board));
+        self.reused_games = root.get_games();
+
+        let deadline = Instant::now() + self.think_time;
+        let stop = AtomicBool::new(false);
+        search(
+            slice::from_mut(&mut root),
+            Some(deadline),
+            &stop,
+            &mut self.rng,
+        );
+        self.games = root.get_games();
+        root.scores()
+    }
+
+    fn ponder(&mut self, board: &Board) {
+        self.stop_pondering();
+        let mut roots: Vec<Root> = (0..WIDTH)
+            .filter_map(|col| {
+                let mut next = *board;
+                (next.make_move(col) == MoveResult::None).then(|| Root::new(next))
+            })
+            .collect();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut rng = StdRng::from_rng(&mut self.rng).unwrap();
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                search(&mut roots, None, &stop, &mut rng);
+                roots
+            })
+        };
+        self.pondering = Some(Pondering { stop, thread });
+    }
+}
+
+#[cfg(test)]
+mod ponder_tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::Ponderer;
+    use crate::board::Board;
+    use crate::engine::Engine;
+
+    fn ponderer(think_time: Duration) -> Ponderer {
+        Ponderer::with_rng(think_time, StdRng::seed_from_u64(5))
+    }
+
+    #[test]
+    fn reuses_games_from_pondering() {
+        let mut engine = ponderer(Duration::ZERO);
+        let board = Board::from_move_string("3").unwrap();
+        engine.ponder(&board);
+        assert!(engine.is_pondering());
+        thread::sleep(Duration::from_millis(50));
+
+        // Without time to think, every game comes from pondering.
+        let scores = engine.score_moves(&Board::from_move_string("32").unwrap());
+        assert!(!engine.is_pondering());
+        assert_eq!(scores.len(), 7);
+        assert!(engine.get_reused_games() > 0);
+        assert_eq!(engine.get_games(), engine.get_reused_games());
+    }
+
+    #[test]
+    fn unexpected_positions_start_over() {
+        let mut engine = ponderer(Duration::from_millis(10));
+        engine.ponder(&Board::from_move_string("3").unwrap());
+        thread::sleep(Duration::from_millis(10));
+        engine.score_moves(&Board::new());
+        assert_eq!(engine.get_reused_games(), 0);
+        assert!(engine.get_games() > 0);
+    }
+
+    #[test]
+    fn takes_immediate_win() {
+        // X can win in columns 1, 3 and 5.
+        let board = Board::from_move_string("242512646300052352144344621036").unwrap();
+        let mut engine = ponderer(Duration::from_millis(10));
+        assert_eq!(engine.choose_move(&board), Some(1));
+    }
+}
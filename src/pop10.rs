@@ -0,0 +1,279 @@
+// Pop 10: fill the board, then pop your own discs out of the bottom
+//
+// The players first fill the board a row at a time, so a drop must go in one
+// of the lowest columns. After that each turn pops one of the mover's discs
+// out of the bottom row. A disc that was part of four in a row is set aside
+// and the mover goes again; any other disc must be dropped back in, in a
+// different column if there is room, which ends the turn. The first player
+// to set aside ten discs wins. A player with no disc to pop draws the game.
+
+use crate::board::{Cell, MoveResult, HEIGHT, WIDTH};
+use crate::rules::{Move, Rules};
+
+const SET_ASIDE_TO_WIN: usize = 10;
+
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pop10 {
+    // Cell of column col, row y is at col * HEIGHT + y.
+    cells: [Cell; WIDTH * HEIGHT],
+    player: Cell,
+    moves: Vec<Move>,
+
+    // Whether the board has been filled and popping has begun.
+    popping: bool,
+
+    // Column of a disc just popped without making four, which the mover
+    // must now drop back in.
+    returning: Option<usize>,
+
+    set_aside: [usize; 2],
+    result: MoveResult,
+}
+
+impl Default for Pop10 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pop10 {
+    pub fn new() -> Self {
+        Self {
+            cells: [Cell::Empty; WIDTH * HEIGHT],
+            player: Cell::X,
+            moves: Vec::new(),
+            popping: false,
+            returning: None,
+            set_aside: [0; 2],
+            result: MoveResult::None,
+        }
+    }
+
+    /// Returns how many discs the player has set aside.
+    pub fn get_set_aside(&self, player: Cell) -> usize {
+        match player {
+            Cell::X => self.set_aside[0],
+            Cell::O => self.set_aside[1],
+            Cell::Empty => 0,
+        }
+    }
+
+    /// Returns the column of a popped disc waiting to be dropped back in.
+    pub fn get_returning(&self) -> Option<usize> {
+        self.returning
+    }
+
+    fn get_column_height(&self, col: usize) -> usize {
+        (0..HEIGHT)
+            .take_while(|&y| self.cells[col * HEIGHT + y] != Cell::Empty)
+            .count()
+    }
+
+    // Whether the disc at col, y is part of four in a row.
+    fn makes_four(&self, col: usize, y: usize) -> bool {
+        let player = self.cells[col * HEIGHT + y];
+        let at = |col: isize, y: isize| {
+            (0..WIDTH as isize).contains(&col) && (0..HEIGHT as isize).contains(&y)
+        };
+        DIRECTIONS.into_iter().any(|(dx, dy)| {
+            let run = |sign: isize| {
+                (1..4)
+                    .take_while(|&steps| {
+                        let (col, y) = (
+                            col as isize + sign * dx * steps,
+                            y as isize + sign * dy * steps,
+                        );
+                        at(col, y) && self.cells[col as usize * HEIGHT + y as usize] == player
+                    })
+                    .count()
+            };
+            run(1) + run(-1) + 1 >= 4
+        })
+    }
+
+    fn drop_disc(&mut self, col: usize) {
+        let y = self.get_column_height(col);
+        self.cells[col * HEIGHT + y] = self.player;
+    }
+
+    fn pop_disc(&mut self, col: usize) {
+        let column = &mut self.cells[col * HEIGHT..(col + 1) * HEIGHT];
+        column.rotate_left(1);
+        column[HEIGHT - 1] = Cell::Empty;
+    }
+}
+
+impl Rules for Pop10 {
+    fn name(&self) -> &'static str {
+        "pop10"
+    }
+
+    fn get_width(&self) -> usize {
+        WIDTH
+    }
+
+    fn get_height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn get(&self, col: usize, y: usize) -> Cell {
+        self.cells[col * HEIGHT + y]
+    }
+
+    fn get_current_player(&self) -> Cell {
+        self.player
+    }
+
+    fn get_moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        let open = |col: &usize| self.get_column_height(*col) < HEIGHT;
+        if !self.popping {
+            let lowest = (0..WIDTH).map(|col| self.get_column_height(col)).min();
+            return (0..WIDTH)
+                .filter(|&col| Some(self.get_column_height(col)) == lowest)
+                .map(Move::Drop)
+                .collect();
+        }
+        if let Some(popped) = self.returning {
+            let others: Vec<Move> = (0..WIDTH)
+                .filter(|&col| col != popped)
+                .filter(open)
+                .map(Move::Drop)
+                .collect();
+            return if others.is_empty() {
+                vec![Move::Drop(popped)]
+            } else {
+                others
+            };
+        }
+        (0..WIDTH)
+            .filter(|&col| self.cells[col * HEIGHT] == self.player)
+            .map(Move::Pop)
+            .collect()
+    }
+
+    fn apply(&mut self, mv: Move) -> MoveResult {
+        if !self.is_legal(mv) {
+            return MoveResult::Illegal;
+        }
+        self.moves.push(mv);
+        match mv {
+            Move::Drop(col) => {
+                self.drop_disc(col);
+                self.returning = None;
+                self.player = self.player.switch();
+                if !self.cells.contains(&Cell::Empty) {
+                    self.popping = true;
+                }
+            }
+            Move::Pop(col) => {
+                let scored = self.makes_four(col, 0);
+                self.pop_disc(col);
+                if scored {
+                    let index = if self.player == Cell::X { 0 } else { 1 };
+                    self.set_aside[index] += 1;
+                    if self.set_aside[index] >= SET_ASIDE_TO_WIN {
+                        self.result = if index == 0 {
+                            MoveResult::WinX
+                        } else {
+                            MoveResult::WinO
+                        };
+                        return self.result;
+                    }
+                } else {
+                    self.returning = Some(col);
+                }
+            }
+        }
+        if self.legal_moves().is_empty() {
+            self.result = MoveResult::Draw;
+        }
+        self.result
+    }
+
+    fn outcome(&self) -> MoveResult {
+        self.result
+    }
+
+    fn status(&self) -> Option<String> {
+        let mut status = format!(
+            "Set aside: X {}, O {}",
+            self.set_aside[0], self.set_aside[1]
+        );
+        if let Some(col) = self.returning {
+            status += &format!(
+                ". {} drops back the disc popped from column {col}",
+                self.player.to_char()
+            );
+        }
+        Some(status)
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod pop10_tests {
+    use super::Pop10;
+    use crate::board::{Cell, MoveResult};
+    use crate::rules::{play_notation, Move, Rules};
+
+    // Fills the board with four X's at the left of the bottom row.
+    const SETUP: &str = "041526301234560123456012345601234560123456";
+
+    #[test]
+    fn fills_a_row_at_a_time() {
+        let mut game = Pop10::new();
+        assert_eq!(game.apply(Move::Drop(3)), MoveResult::None);
+        assert_eq!(game.apply(Move::Drop(3)), MoveResult::Illegal);
+        assert_eq!(game.apply(Move::Pop(3)), MoveResult::Illegal);
+        play_notation(&mut game, "012456").unwrap();
+        assert_eq!(game.legal_moves().len(), 7);
+
+        let mut game = Pop10::new();
+        play_notation(&mut game, SETUP).unwrap();
+        assert_eq!(game.get_current_player(), Cell::X);
+        assert!(game
+            .legal_moves()
+            .iter()
+            .all(|mv| matches!(mv, Move::Pop(_))));
+    }
+
+    #[test]
+    fn pops() {
+        let mut game = Pop10::new();
+        play_notation(&mut game, SETUP).unwrap();
+        // Part of four on the bottom row: set aside, and X goes again.
+        assert_eq!(game.apply(Move::Pop(0)), MoveResult::None);
+        assert_eq!(game.get_set_aside(Cell::X), 1);
+        assert_eq!(game.get_current_player(), Cell::X);
+        assert_eq!(game.get(0, 5), Cell::Empty);
+
+        // Not part of four: it goes back in, in the only column with room.
+        assert_eq!(game.apply(Move::Pop(3)), MoveResult::None);
+        assert_eq!(game.get_set_aside(Cell::X), 1);
+        assert_eq!(game.get_returning(), Some(3));
+        assert_eq!(game.legal_moves(), [Move::Drop(0)]);
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::None);
+        assert_eq!(game.get_current_player(), Cell::O);
+    }
+
+    #[test]
+    fn tenth_disc_wins() {
+        let mut game = Pop10::new();
+        play_notation(&mut game, SETUP).unwrap();
+        game.set_aside[0] = 9;
+        assert_eq!(game.apply(Move::Pop(0)), MoveResult::WinX);
+        assert!(game.legal_moves().is_empty());
+    }
+}
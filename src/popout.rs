@@ -0,0 +1,282 @@
+// Pop Out: Connect 4 where a player may instead pop one of their own discs
+// out of the bottom of a column, dropping the discs above it
+//
+// A pop can complete lines anywhere in the column's reach, for either player,
+// so the whole board is checked after it. If a pop completes four in a row
+// for both players, `SimultaneousFour` decides the game. A full board does not
+// end the game, as pops are still possible, but a player left without a legal
+// move draws. A position repeated three times with the same player to move is
+// also a draw.
+//
+// Moves use the notation of `rules::Move`.
+
+use crate::board::{Cell, MoveResult, HEIGHT, WIDTH};
+use crate::rules::{Move, Rules};
+
+// Same bit layout as `Board`: column col, row y is bit col * 8 + y.
+const PADDED_HEIGHT: usize = 8;
+const COLUMN_MASK: u64 = (1 << HEIGHT) - 1;
+
+// A position that occurs this many times is drawn.
+const REPETITIONS: usize = 3;
+
+/// Who wins when a pop completes four in a row for both players.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SimultaneousFour {
+    #[default]
+    MoverWins,
+    Draw,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PopOut {
+    // Discs of X and of O.
+    pieces: [u64; 2],
+
+    rule: SimultaneousFour,
+    moves: Vec<Move>,
+
+    // Every position so far, with the player to move, to find repetitions.
+    history: Vec<[u64; 2]>,
+
+    result: MoveResult,
+}
+
+impl Default for PopOut {
+    fn default() -> Self {
+        Self::new(SimultaneousFour::default())
+    }
+}
+
+impl PopOut {
+    pub fn new(rule: SimultaneousFour) -> Self {
+        Self {
+            pieces: [0; 2],
+            rule,
+            moves: Vec::new(),
+            history: vec![[0; 2]],
+            result: MoveResult::None,
+        }
+    }
+
+    fn get_column_height(&self, col: usize) -> usize {
+        column(self.mask(), col).count_ones() as usize
+    }
+
+    /// Returns how many times the current position has occurred, counting this time.
+    pub fn get_repetitions(&self) -> usize {
+        let current = self.history.last().unwrap();
+        let parity = self.history.len() % 2;
+        self.history
+            .iter()
+            .enumerate()
+            .filter(|&(i, position)| (i + 1) % 2 == parity && position == current)
+            .count()
+    }
+
+    fn mask(&self) -> u64 {
+        self.pieces[0] | self.pieces[1]
+    }
+}
+
+// The discs in a column, shifted down to the lowest bits.
+fn column(pieces: u64, col: usize) -> u64 {
+    (pieces >> (col * PADDED_HEIGHT)) & COLUMN_MASK
+}
+
+// Whether the discs contain four in a row anywhere. The empty rows above each
+// column keep lines from wrapping into the next column.
+fn has_four(pieces: u64) -> bool {
+    [1, PADDED_HEIGHT, PADDED_HEIGHT - 1, PADDED_HEIGHT + 1]
+        .into_iter()
+        .any(|shift| {
+            let pairs = pieces & (pieces >> shift);
+            pairs & (pairs >> (2 * shift)) != 0
+        })
+}
+
+impl Rules for PopOut {
+    fn name(&self) -> &'static str {
+        "popout"
+    }
+
+    fn get_width(&self) -> usize {
+        WIDTH
+    }
+
+    fn get_height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn get(&self, col: usize, y: usize) -> Cell {
+        let bit = 1 << (col * PADDED_HEIGHT + y);
+        if self.pieces[0] & bit != 0 {
+            Cell::X
+        } else if self.pieces[1] & bit != 0 {
+            Cell::O
+        } else {
+            Cell::Empty
+        }
+    }
+
+    fn get_current_player(&self) -> Cell {
+        match self.moves.len() & 1 {
+            0 => Cell::X,
+            1 => Cell::O,
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        (0..WIDTH)
+            .flat_map(|col| [Move::Drop(col), Move::Pop(col)])
+            .filter(|&mv| self.is_legal(mv))
+            .collect()
+    }
+
+    fn is_legal(&self, mv: Move) -> bool {
+        if self.is_terminal() || mv.get_column() >= WIDTH {
+            return false;
+        }
+        match mv {
+            Move::Drop(col) => self.get_column_height(col) < HEIGHT,
+            Move::Pop(col) => self.get(col, 0) == self.get_current_player(),
+        }
+    }
+
+    fn apply(&mut self, mv: Move) -> MoveResult {
+        if !self.is_legal(mv) {
+            return MoveResult::Illegal;
+        }
+        let player = self.moves.len() % 2;
+        match mv {
+            Move::Drop(col) => {
+                self.pieces[player] |= 1 << (col * PADDED_HEIGHT + self.get_column_height(col));
+            }
+            Move::Pop(col) => {
+                for pieces in &mut self.pieces {
+                    let column = column(*pieces, col) >> 1;
+                    *pieces &= !(COLUMN_MASK << (col * PADDED_HEIGHT));
+                    *pieces |= column << (col * PADDED_HEIGHT);
+                }
+            }
+        }
+        self.moves.push(mv);
+        self.history.push(self.pieces);
+
+        let mover = has_four(self.pieces[player]);
+        let opponent = has_four(self.pieces[1 - player]);
+        let win = |player| {
+            if player == 0 {
+                MoveResult::WinX
+            } else {
+                MoveResult::WinO
+            }
+        };
+        self.result = match (mover, opponent) {
+            (true, true) if self.rule == SimultaneousFour::Draw => MoveResult::Draw,
+            (true, _) => win(player),
+            (false, true) => win(1 - player),
+            (false, false) if self.get_repetitions() >= REPETITIONS => MoveResult::Draw,
+            (false, false) => MoveResult::None,
+        };
+        if self.result == MoveResult::None && self.legal_moves().is_empty() {
+            self.result = MoveResult::Draw;
+        }
+        self.result
+    }
+
+    fn outcome(&self) -> MoveResult {
+        self.result
+    }
+
+    fn status(&self) -> Option<String> {
+        match self.get_repetitions() {
+            repetitions if repetitions >= REPETITIONS => Some("Draw by repetition".to_string()),
+            1 => None,
+            repetitions => Some(format!("Position repeated {repetitions} times")),
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod popout_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{Move, PopOut, SimultaneousFour};
+    use crate::board::{Cell, MoveResult};
+    use crate::engine::MonteCarlo;
+    use crate::rules::{play_notation, Rules};
+
+    // X pops column 3, making four in a row on the bottom row for both players.
+    const DOUBLE_FOUR: &str = "3330011226";
+
+    fn play(notation: &str, rule: SimultaneousFour) -> PopOut {
+        let mut game = PopOut::new(rule);
+        play_notation(&mut game, notation).unwrap();
+        game
+    }
+
+    #[test]
+    fn pops() {
+        let mut game = PopOut::default();
+        assert!(!game.is_legal(Move::Pop(3)));
+        game.apply(Move::Drop(3));
+        // Only your own discs can be popped.
+        assert!(!game.is_legal(Move::Pop(3)));
+        game.apply(Move::Drop(3));
+        assert_eq!(game.apply(Move::Pop(3)), MoveResult::None);
+        assert_eq!((game.get(3, 0), game.get(3, 1)), (Cell::O, Cell::Empty));
+        assert_eq!(game.get_column_height(3), 1);
+        assert!(game.is_legal(Move::Pop(3)));
+        assert!(play_notation(&mut PopOut::default(), "3p3").is_err());
+    }
+
+    #[test]
+    fn pop_can_win_for_the_opponent() {
+        // O has three on the bottom row and a disc above X's in column 3.
+        let mut game = play("33606152", SimultaneousFour::MoverWins);
+        assert_eq!(game.apply(Move::Pop(3)), MoveResult::WinO);
+        assert!(game.is_terminal());
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::Illegal);
+    }
+
+    #[test]
+    fn simultaneous_fours() {
+        let mut game = play(DOUBLE_FOUR, SimultaneousFour::MoverWins);
+        assert_eq!(game.apply(Move::Pop(3)), MoveResult::WinX);
+        let mut game = play(DOUBLE_FOUR, SimultaneousFour::Draw);
+        assert_eq!(game.apply(Move::Pop(3)), MoveResult::Draw);
+    }
+
+    #[test]
+    fn repetition_draw() {
+        // Dropping and popping back to the empty board, which then occurs a third time.
+        let mut game = play("34p3p434p3", SimultaneousFour::MoverWins);
+        assert_eq!(game.outcome(), MoveResult::None);
+        assert_eq!(game.get_repetitions(), 2);
+        assert_eq!(game.apply(Move::Pop(4)), MoveResult::Draw);
+        assert_eq!(game.get_repetitions(), 3);
+    }
+
+    #[test]
+    fn engine_pops_to_win_and_avoids_losing_pops() {
+        let mut engine = MonteCarlo::with_rng(20, StdRng::seed_from_u64(3));
+        let game = play(DOUBLE_FOUR, SimultaneousFour::MoverWins);
+        assert_eq!(engine.choose_variant_move(&game), Some(Move::Pop(3)));
+
+        let game = play("33606152", SimultaneousFour::MoverWins);
+        let scores = engine.score_variant_moves(&game);
+        assert!(scores.contains(&(Move::Pop(3), f64::NEG_INFINITY)));
+        assert_ne!(engine.choose_variant_move(&game), Some(Move::Pop(3)));
+    }
+}
@@ -0,0 +1,224 @@
+// Text records of games that can be saved and replayed
+//
+// A record is a few tag lines followed by the moves as a string of column
+// digits, for example:
+//
+//   [Red "human"]
+//   [Yellow "solver"]
+//   [Result "0-1"]
+//   33244
+//
+// The result is "1-0" when Red wins, "0-1" when Yellow wins, "1/2-1/2" for a
+// draw and "*" for a game still in progress. Games that do not start from the
+// empty board with Red to move also have a Position tag, in the notation of
+// `setup::parse_position`, and games with a handicap a Handicap tag giving
+// the number of extra discs the player to move at the start drops.
+//
+// Within tag values, a backslash or double quote is escaped with a backslash.
+
+use std::fmt::Write;
+
+use crate::board::{Board, MoveResult};
+use crate::game::Game;
+use crate::setup::{self, Setup};
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Record {
+    pub red: String,
+    pub yellow: String,
+    pub setup: Setup,
+    pub moves: Vec<usize>,
+}
+
+impl Record {
+    pub fn new(game: &Game, red: &str, yellow: &str) -> Self {
+        Self {
+            red: red.to_string(),
+            yellow: yellow.to_string(),
+            setup: *game.get_setup(),
+            moves: game.get_moves().to_vec(),
+        }
+    }
+
+    /// Replays the moves from the setup, failing if any of them is illegal.
+    pub fn replay(&self) -> Result<Game, String> {
+        let mut game = Game::from_setup(self.setup);
+        for (i, &col) in self.moves.iter().enumerate() {
+            if game.make_move(col) == MoveResult::Illegal {
+                return Err(format!("move {} in column {col} is illegal", i + 1));
+            }
+        }
+        Ok(game)
+    }
+
+    /// Returns the value of the result tag for the game after the recorded moves.
+    pub fn get_result(&self) -> &'static str {
+        match self.replay().map(|game| game.get_result()) {
+            Ok(MoveResult::WinX) => "1-0",
+            Ok(MoveResult::WinO) => "0-1",
+            Ok(MoveResult::Draw) => "1/2-1/2",
+            _ => "*",
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "[Red \"{}\"]", escape(&self.red)).unwrap();
+        writeln!(text, "[Yellow \"{}\"]", escape(&self.yellow)).unwrap();
+        writeln!(text, "[Result \"{}\"]", self.get_result()).unwrap();
+        let board = self.setup.get_board();
+        if *board != Board::new() {
+            writeln!(text, "[Position \"{}\"]", setup::position_string(board)).unwrap();
+        }
+        if self.setup.get_handicap() > 0 {
+            writeln!(text, "[Handicap \"{}\"]", self.setup.get_handicap()).unwrap();
+        }
+        let moves: String = self.moves.iter().map(|col| col.to_string()).collect();
+        writeln!(text, "{moves}").unwrap();
+        text
+    }
+
+    /// Parses a record, checking that the moves are legal and match the result tag.
+    pub fn parse(text: &str) -> Result<Record, String> {
+        let mut record = Record {
+            red: String::new(),
+            yellow: String::new(),
+            setup: Setup::standard(),
+            moves: Vec::new(),
+        };
+        let mut result = None;
+        let mut board = Board::new();
+        let mut handicap = 0;
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(tag) = line.strip_prefix('[') {
+                let (name, value) = parse_tag(tag).ok_or(format!("invalid tag: {line}"))?;
+                match name {
+                    "Red" => record.red = value,
+                    "Yellow" => record.yellow = value,
+                    "Result" => result = Some(value),
+                    "Position" => board = setup::parse_position(&value)?,
+                    "Handicap" => {
+                        handicap = value
+                            .parse()
+                            .map_err(|_| format!("invalid handicap: {value}"))?
+                    }
+                    _ => {}
+                }
+            } else {
+                for c in line.chars() {
+                    let col = c.to_digit(10).ok_or(format!("invalid move: {c}"))?;
+                    record.moves.push(col as usize);
+                }
+            }
+        }
+
+        record.setup = Setup::new(board, handicap);
+        record.replay()?;
+        match result {
+            Some(result) if result != record.get_result() => {
+                Err(format!("result {result} does not match the moves"))
+            }
+            _ => Ok(record),
+        }
+    }
+}
+
+// Splits `Name "value"]` into its name and unescaped value.
+fn parse_tag(tag: &str) -> Option<(&str, String)> {
+    let (name, value) = tag.strip_suffix(']')?.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name, unescape(value)?))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Undoes `escape`, failing on a lone backslash or an unescaped quote.
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                c @ ('\\' | '"') => unescaped.push(c),
+                _ => return None,
+            },
+            '"' => return None,
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod record_tests {
+    use super::Record;
+    use crate::game::Game;
+    use crate::setup::{self, Setup};
+
+    #[test]
+    fn round_trip() {
+        let mut game = Game::new();
+        for col in [3, 3, 2, 4, 4] {
+            game.make_move(col);
+        }
+        let record = Record::new(&game, "human", "solver");
+        let text = record.to_text();
+        assert_eq!(
+            text,
+            "[Red \"human\"]\n[Yellow \"solver\"]\n[Result \"*\"]\n33244\n"
+        );
+        assert_eq!(Record::parse(&text).unwrap(), record);
+        assert_eq!(record.replay().unwrap(), game);
+    }
+
+    #[test]
+    fn finished_game() {
+        let text = "[Red \"a\"]\n[Yellow \"b\"]\n[Result \"1-0\"]\n0101010\n";
+        let record = Record::parse(text).unwrap();
+        assert_eq!(record.to_text(), text);
+
+        assert!(Record::parse("[Result \"0-1\"]\n0101010\n").is_err());
+        assert!(Record::parse("01010101\n").is_err());
+        assert!(Record::parse("0000000\n").is_err());
+        assert!(Record::parse("[Red human]\n").is_err());
+    }
+
+    #[test]
+    fn escaped_names() {
+        let record = Record::new(&Game::new(), "the \"pro\"", "C:\\bot");
+        let text = record.to_text();
+        assert!(text.starts_with("[Red \"the \\\"pro\\\"\"]\n[Yellow \"C:\\\\bot\"]\n"));
+        assert_eq!(Record::parse(&text).unwrap(), record);
+        assert!(Record::parse("[Red \"a\"b\"]\n").is_err());
+        assert!(Record::parse("[Red \"a\\\"]\n").is_err());
+    }
+
+    #[test]
+    fn setup_and_handicap() {
+        let board =
+            setup::parse_position("......./......./......./......./......./...X... O").unwrap();
+        // O drops two discs to start and goes on to win in column 2.
+        let mut game = Game::from_setup(Setup::new(board, 1));
+        for col in [2, 2, 0, 2, 0, 2] {
+            game.make_move(col);
+        }
+        let record = Record::new(&game, "coach", "student");
+        let text = record.to_text();
+        assert_eq!(
+            text,
+            "[Red \"coach\"]\n[Yellow \"student\"]\n[Result \"0-1\"]\n\
+             [Position \"......./......./......./......./......./...X... O\"]\n\
+             [Handicap \"1\"]\n220202\n"
+        );
+        assert_eq!(Record::parse(&text).unwrap(), record);
+        assert_eq!(record.replay().unwrap(), game);
+
+        // Without the handicap the same moves are a different game.
+        let text = text.replace("[Handicap \"1\"]\n", "").replace("0-1", "*");
+        assert_ne!(Record::parse(&text).unwrap().replay().unwrap(), game);
+        assert!(Record::parse("[Position \"0\"]\n").is_err());
+        assert!(Record::parse("[Handicap \"-1\"]\n").is_err());
+    }
+}
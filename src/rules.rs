@@ -0,0 +1,314 @@
+// Rules of connect 4 and its variants behind a common interface
+//
+// Every variant is played by dropping discs into columns, and some also let a
+// player pop a disc out of the bottom of a column, so all of them share the
+// `Move` type and its notation. The Monte Carlo engine and the front end of
+// the play command only use `Rules`, so they work for every variant. The other
+// engines and front ends still work on `Board`, whose bitboards they need for
+// speed, for the solver's hints or for the network protocol.
+
+use std::fmt;
+
+use crate::board::{Board, Cell, MoveResult, HEIGHT, WIDTH};
+use crate::connectn::ConnectN;
+use crate::cylinder::Cylinder;
+use crate::misere::Misere;
+use crate::pop10::Pop10;
+use crate::popout::{PopOut, SimultaneousFour};
+
+// Moves are written as a column digit for a drop and 'p' then a column digit
+// for a pop, so "3344p3" drops twice in columns 3 and 4 and then pops column 3.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Move {
+    Drop(usize),
+    Pop(usize),
+}
+
+impl Move {
+    pub fn get_column(self) -> usize {
+        match self {
+            Move::Drop(col) | Move::Pop(col) => col,
+        }
+    }
+
+    /// Parses moves written like "3344p3". Whether the columns exist is up to
+    /// the rules of the game they are played in.
+    pub fn parse_list(notation: &str) -> Result<Vec<Move>, String> {
+        let mut moves = Vec::new();
+        let mut chars = notation.chars();
+        while let Some(c) = chars.next() {
+            let (pop, digit) = match c {
+                'p' => (true, chars.next()),
+                _ => (false, Some(c)),
+            };
+            let col = digit
+                .and_then(|digit| digit.to_digit(10))
+                .map(|col| col as usize)
+                .ok_or(format!("Invalid move notation: {notation}"))?;
+            moves.push(if pop { Move::Pop(col) } else { Move::Drop(col) });
+        }
+        Ok(moves)
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Move::Drop(col) => write!(f, "{col}"),
+            Move::Pop(col) => write!(f, "p{col}"),
+        }
+    }
+}
+
+/// Writes moves in the notation read by `Move::parse_list`.
+pub fn notation(moves: &[Move]) -> String {
+    moves.iter().map(Move::to_string).collect()
+}
+
+pub trait Rules {
+    fn name(&self) -> &'static str;
+
+    fn get_width(&self) -> usize;
+    fn get_height(&self) -> usize;
+    fn get(&self, col: usize, y: usize) -> Cell;
+
+    fn get_current_player(&self) -> Cell;
+
+    /// Returns the moves played so far.
+    fn get_moves(&self) -> &[Move];
+
+    /// Returns every move the current player may make, none once the game is over.
+    fn legal_moves(&self) -> Vec<Move>;
+
+    fn is_legal(&self, mv: Move) -> bool {
+        self.legal_moves().contains(&mv)
+    }
+
+    /// Makes a move for the current player. Returns `MoveResult::Illegal`,
+    /// leaving the game unchanged, if the move is not allowed.
+    fn apply(&mut self, mv: Move) -> MoveResult;
+
+    /// Returns the result of the game so far, `MoveResult::None` while it goes on.
+    fn outcome(&self) -> MoveResult;
+
+    fn is_terminal(&self) -> bool {
+        matches!(
+            self.outcome(),
+            MoveResult::WinX | MoveResult::WinO | MoveResult::Draw
+        )
+    }
+
+    /// Describes any state not shown on the board, such as captured discs.
+    fn status(&self) -> Option<String> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules>;
+
+    fn print(&self) {
+        for row in (0..self.get_height()).rev() {
+            for col in 0..self.get_width() {
+                print!("|{} ", self.get(col, row).to_char());
+            }
+            println!("|");
+        }
+        for _col in 0..self.get_width() {
+            print!("+--");
+        }
+        println!("+");
+        for col in 0..self.get_width() {
+            print!("|{} ", col);
+        }
+        println!("|");
+        if let Some(status) = self.status() {
+            println!("{status}");
+        }
+        println!();
+    }
+}
+
+impl Clone for Box<dyn Rules> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+pub const VARIANT_NAMES: [&str; 8] = [
+    "standard",
+    "misere",
+    "connect5",
+    "five-in-a-row",
+    "cylinder",
+    "torus",
+    "popout",
+    "pop10",
+];
+
+/// Returns a new game of the named variant.
+pub fn from_name(name: &str) -> Option<Box<dyn Rules>> {
+    match name {
+        "standard" => Some(Box::new(Standard::new())),
+        "misere" => Some(Box::new(Misere::new())),
+        "connect5" => Some(Box::new(ConnectN::new(9, 6, 5))),
+        "five-in-a-row" => Some(Box::new(ConnectN::five_in_a_row())),
+        "cylinder" => Some(Box::new(Cylinder::new())),
+        "torus" => Some(Box::new(ConnectN::torus(WIDTH, HEIGHT, 4))),
+        "popout" => Some(Box::new(PopOut::new(SimultaneousFour::MoverWins))),
+        "pop10" => Some(Box::new(Pop10::new())),
+        _ => None,
+    }
+}
+
+/// Plays moves written like "3344p3", which must all be legal.
+pub fn play_notation(rules: &mut dyn Rules, notation: &str) -> Result<(), String> {
+    for mv in Move::parse_list(notation)? {
+        if rules.apply(mv) == MoveResult::Illegal {
+            return Err(format!("Illegal move {mv} in {notation}"));
+        }
+    }
+    Ok(())
+}
+
+/// Standard connect 4 on the 7x6 board.
+#[derive(Clone)]
+pub struct Standard {
+    board: Board,
+    moves: Vec<Move>,
+    result: MoveResult,
+}
+
+impl Default for Standard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Standard {
+    pub fn new() -> Self {
+        Self {
+            board: Board::new(),
+            moves: Vec::new(),
+            result: MoveResult::None,
+        }
+    }
+
+    pub fn get_board(&self) -> &Board {
+        &self.board
+    }
+}
+
+impl Rules for Standard {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+
+    fn get_width(&self) -> usize {
+        WIDTH
+    }
+
+    fn get_height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn get(&self, col: usize, y: usize) -> Cell {
+        self.board.get(col, y)
+    }
+
+    fn get_current_player(&self) -> Cell {
+        self.board.get_current_player()
+    }
+
+    fn get_moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        (0..WIDTH)
+            .filter(|&col| self.board.is_valid_move(col))
+            .map(Move::Drop)
+            .collect()
+    }
+
+    fn apply(&mut self, mv: Move) -> MoveResult {
+        match mv {
+            Move::Drop(col) if !self.is_terminal() && col < WIDTH => {
+                if !self.board.is_valid_move(col) {
+                    return MoveResult::Illegal;
+                }
+                self.result = self.board.make_move(col);
+                self.moves.push(mv);
+                self.result
+            }
+            _ => MoveResult::Illegal,
+        }
+    }
+
+    fn outcome(&self) -> MoveResult {
+        self.result
+    }
+
+    fn box_clone(&self) -> Box<dyn Rules> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod rules_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{from_name, notation, play_notation, Move, Standard, VARIANT_NAMES};
+    use crate::board::{Board, MoveResult};
+    use crate::engine::MonteCarlo;
+    use crate::rules::Rules;
+
+    #[test]
+    fn notation_round_trip() {
+        let moves = Move::parse_list("38p8").unwrap();
+        assert_eq!(moves, [Move::Drop(3), Move::Drop(8), Move::Pop(8)]);
+        assert_eq!(notation(&moves), "38p8");
+        for bad in ["p", "pp", "x", "37p"] {
+            assert!(Move::parse_list(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn standard_follows_the_board() {
+        let mut game = Standard::new();
+        assert!(play_notation(&mut game, "37").is_err());
+        let mut game = Standard::new();
+        play_notation(&mut game, "3344556").unwrap();
+        assert_eq!(game.outcome(), MoveResult::WinX);
+        assert!(game.legal_moves().is_empty());
+        assert_eq!(game.apply(Move::Drop(0)), MoveResult::Illegal);
+        let mut board = Board::new();
+        board.make_moves(&[3, 3, 4, 4, 5, 5, 6]);
+        assert_eq!(*game.get_board(), board);
+        assert_eq!(notation(game.get_moves()), "3344556");
+    }
+
+    #[test]
+    fn engine_plays_every_variant() {
+        let mut engine = MonteCarlo::with_rng(2, StdRng::seed_from_u64(1));
+        for name in VARIANT_NAMES {
+            let mut game = from_name(name).unwrap();
+            for _ in 0..100 {
+                let Some(mv) = engine.choose_variant_move(game.as_ref()) else {
+                    break;
+                };
+                assert_ne!(game.apply(mv), MoveResult::Illegal, "{name}");
+            }
+        }
+
+        // Takes an immediate win in a wider game.
+        let mut game = from_name("connect5").unwrap();
+        play_notation(game.as_mut(), "00112233").unwrap();
+        assert_eq!(
+            engine.choose_variant_move(game.as_ref()),
+            Some(Move::Drop(4))
+        );
+    }
+}
@@ -0,0 +1,501 @@
+// Local HTTP server for playing games and querying the engines with JSON
+//
+// Endpoints:
+//   GET  /games                      List all games
+//   POST /games                      Create a game: {"red": name, "yellow": name}
+//   GET  /games/<id>                 Board state of a game
+//   POST /games/<id>/moves           Play a column: {"column": 3}
+//   POST /games/<id>/engine-move     Let an engine or level play: {"engine": "solver"}
+//   GET  /games/<id>/analysis        Engine scores and proven outcomes, ?engine=<name>
+//   GET  /games/<id>/record          Download the game record as text
+//   POST /games/<id>/self-play       Let two engines play the game out:
+//                                    {"red": name, "yellow": name, "delay_ms": 500}
+//   GET  /games/<id>/stream          WebSocket for watching the game live
+//
+// Player names in a new game are optional and default to "human". The engine
+// defaults to Monte Carlo. Errors are returned as {"error": message}.
+//
+// Any number of spectators can watch a game over its stream. They receive the
+// game state when they connect and then JSON messages as the game goes on,
+// including the engine's progress while it searches:
+//
+//   {"type": "state", ...}                 The game state, as from GET /games/<id>
+//   {"type": "info", "engine": name, "depth": 12, "score": 3.0, "pv": [3, 2]}
+//                                          An engine's analysis while it thinks
+//   {"type": "move", "column": 3, ...}     A move and the game state after it
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, ReadWrite, Request, Response};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::board::{Cell, MoveResult, HEIGHT, WIDTH};
+use crate::difficulty::{self, Leveled};
+use crate::engine::{self, Engine, Info};
+use crate::game::Game;
+use crate::hint;
+use crate::record::Record;
+
+type Games = Arc<Mutex<BTreeMap<u64, ServerGame>>>;
+
+type Socket = WebSocket<Box<dyn ReadWrite + Send>>;
+
+struct ServerGame {
+    id: u64,
+    game: Game,
+    red: String,
+    yellow: String,
+
+    // Queues of the messages for each spectator's WebSocket. Each is written
+    // by its own thread, so that a slow spectator never holds up the games.
+    spectators: Vec<Sender<String>>,
+
+    // Whether engines are playing the game out in the background.
+    self_play: bool,
+}
+
+enum Body {
+    Json(Value),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+pub struct Server {
+    http: tiny_http::Server,
+    games: Games,
+}
+
+impl Server {
+    /// Listens on the given address, such as "127.0.0.1:8080". Use port 0 to
+    /// pick any free port.
+    pub fn bind(addr: &str) -> Result<Server, String> {
+        let http = tiny_http::Server::http(addr).map_err(|e| format!("{addr}: {e}"))?;
+        Ok(Server {
+            http,
+            games: Arc::new(Mutex::new(BTreeMap::new())),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.http.server_addr().to_ip().unwrap()
+    }
+
+    /// Handles requests one at a time, forever.
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            self.handle(request);
+        }
+    }
+
+    fn handle(&self, mut request: Request) {
+        if is_websocket(&request) {
+            return self.watch(request);
+        }
+        let result = self.route(&mut request);
+        respond(request, result);
+    }
+
+    // Upgrades a request for /games/<id>/stream to a WebSocket and adds it to
+    // the spectators of the game.
+    fn watch(&self, request: Request) {
+        let segments: Vec<&str> = request.url().split('/').filter(|s| !s.is_empty()).collect();
+        let id = match segments.as_slice() {
+            ["games", id, "stream"] => id
+                .parse()
+                .ok()
+                .filter(|id| self.games.lock().unwrap().contains_key(id)),
+            _ => None,
+        };
+        let (Some(id), Some(key)) = (id, header_value(&request, "Sec-WebSocket-Key")) else {
+            return respond(request, Err(HttpError::new(404, "unknown game")));
+        };
+
+        let accept = derive_accept_key(key.as_bytes());
+        let header = Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap();
+        let stream = request.upgrade("websocket", Response::empty(101).with_header(header));
+        let socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || spectate(socket, receiver));
+
+        let mut games = self.games.lock().unwrap();
+        let game = games.get_mut(&id).unwrap();
+        let mut state = game.state();
+        state["type"] = json!("state");
+        if sender.send(state.to_string()).is_ok() {
+            game.spectators.push(sender);
+        }
+    }
+
+    fn route(&self, request: &mut Request) -> Result<Body, HttpError> {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let method = request.method().clone();
+        let mut games = self.games.lock().unwrap();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["games"]) => {
+                let list: Vec<Value> = games.values().map(ServerGame::summary).collect();
+                Ok(Body::Json(Value::Array(list)))
+            }
+            (Method::Post, ["games"]) => {
+                let body = read_json(request)?;
+                let id = games.keys().next_back().map_or(1, |id| id + 1);
+                let game = ServerGame {
+                    id,
+                    game: Game::new(),
+                    red: player_name(&body, "red")?,
+                    yellow: player_name(&body, "yellow")?,
+                    spectators: Vec::new(),
+                    self_play: false,
+                };
+                let state = game.state();
+                games.insert(id, game);
+                Ok(Body::Json(state))
+            }
+            (method, ["games", id, rest @ ..]) => {
+                let id: u64 = id
+                    .parse()
+                    .map_err(|_| HttpError::new(404, "unknown game"))?;
+                let game = games
+                    .get_mut(&id)
+                    .ok_or(HttpError::new(404, "unknown game"))?;
+                match (method, rest) {
+                    (Method::Get, []) => Ok(Body::Json(game.state())),
+                    (Method::Post, ["moves"]) => {
+                        let body = read_json(request)?;
+                        let col = body["column"]
+                            .as_u64()
+                            .ok_or(HttpError::new(400, "expected {\"column\": <number>}"))?;
+                        game.check_playable()?;
+                        game.make_move(col as usize)?;
+                        Ok(Body::Json(game.state()))
+                    }
+                    (Method::Post, ["engine-move"]) => {
+                        let body = read_json(request)?;
+                        let mut engine = engine_by_name(body["engine"].as_str())?;
+                        game.check_playable()?;
+                        // Think without holding the lock, like `self_play`, then
+                        // check that the game did not move on in the meantime.
+                        let board = *game.game.get_board();
+                        drop(games);
+                        let name = engine.name();
+                        let info = engine
+                            .analyze_with_progress(&board, &mut |info| {
+                                report(&self.games, id, name, info)
+                            })
+                            .ok_or(HttpError::new(409, "no valid moves"))?;
+                        let mut games = self.games.lock().unwrap();
+                        let game = games
+                            .get_mut(&id)
+                            .ok_or(HttpError::new(404, "unknown game"))?;
+                        game.check_playable()?;
+                        if game.game.get_board().get_move_count() != board.get_move_count() {
+                            return Err(HttpError::new(
+                                409,
+                                "the game changed while the engine was thinking",
+                            ));
+                        }
+                        game.report(name, &info);
+                        game.make_move(info.pv[0])?;
+                        let mut state = game.state();
+                        state["engine_move"] = json!(info.pv[0]);
+                        Ok(Body::Json(state))
+                    }
+                    (Method::Post, ["self-play"]) => {
+                        let body = read_json(request)?;
+                        let red = engine_by_name(body["red"].as_str())?.name();
+                        let yellow = engine_by_name(body["yellow"].as_str())?.name();
+                        let delay = match &body["delay_ms"] {
+                            Value::Null => Duration::ZERO,
+                            value => Duration::from_millis(
+                                value
+                                    .as_u64()
+                                    .ok_or(HttpError::new(400, "delay_ms must be a number"))?,
+                            ),
+                        };
+                        game.check_playable()?;
+                        game.red = red.to_string();
+                        game.yellow = yellow.to_string();
+                        game.self_play = true;
+                        let games = Arc::clone(&self.games);
+                        thread::spawn(move || self_play(games, id, [red, yellow], delay));
+                        Ok(Body::Json(game.state()))
+                    }
+                    (Method::Get, ["analysis"]) => {
+                        let name = query_param(query, "engine");
+                        let mut engine = engine_by_name(name)?;
+                        let game = game.game.clone();
+                        drop(games);
+                        Ok(Body::Json(analysis(engine.as_mut(), &game)?))
+                    }
+                    (Method::Get, ["stream"]) => {
+                        Err(HttpError::new(400, "expected a WebSocket upgrade"))
+                    }
+                    (Method::Get, ["record"]) => {
+                        let record = Record::new(&game.game, &game.red, &game.yellow);
+                        Ok(Body::Text(record.to_text()))
+                    }
+                    _ => Err(HttpError::new(404, "not found")),
+                }
+            }
+            _ => Err(HttpError::new(404, "not found")),
+        }
+    }
+}
+
+impl ServerGame {
+    fn check_playable(&self) -> Result<(), HttpError> {
+        if self.game.is_over() {
+            Err(HttpError::new(409, "the game is over"))
+        } else if self.self_play {
+            Err(HttpError::new(409, "engines are playing this game"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn make_move(&mut self, col: usize) -> Result<(), HttpError> {
+        if self.game.is_over() {
+            return Err(HttpError::new(409, "the game is over"));
+        }
+        if self.game.make_move(col) == MoveResult::Illegal {
+            return Err(HttpError::new(
+                400,
+                format!("column {col} is not a valid move"),
+            ));
+        }
+        if self.game.is_over() {
+            self.self_play = false;
+        }
+        let mut message = self.state();
+        message["type"] = json!("move");
+        message["column"] = json!(col);
+        self.broadcast(&message);
+        Ok(())
+    }
+
+    fn report(&mut self, engine: &str, info: &Info) {
+        self.broadcast(&json!({
+            "type": "info",
+            "engine": engine,
+            "depth": info.depth,
+            "score": info.score,
+            "pv": info.pv,
+        }));
+    }
+
+    // Queues a message for every spectator, forgetting those that have disconnected.
+    fn broadcast(&mut self, message: &Value) {
+        let text = message.to_string();
+        self.spectators
+            .retain(|spectator| spectator.send(text.clone()).is_ok());
+    }
+
+    fn summary(&self) -> Value {
+        json!({
+            "id": self.id,
+            "red": self.red,
+            "yellow": self.yellow,
+            "moves": self.game.get_move_string(),
+            "result": result_name(&self.game),
+        })
+    }
+
+    // The summary plus the board rows from top to bottom, as printed by `Board::print`.
+    fn state(&self) -> Value {
+        let board = self.game.get_board();
+        let rows: Vec<String> = (0..HEIGHT)
+            .rev()
+            .map(|row| {
+                (0..WIDTH)
+                    .map(|col| board.get(col, row).to_char())
+                    .collect()
+            })
+            .collect();
+        let to_move = match board.get_current_player() {
+            _ if self.game.is_over() => Value::Null,
+            Cell::X => json!("red"),
+            _ => json!("yellow"),
+        };
+        let valid_moves: Vec<usize> = (0..WIDTH)
+            .filter(|&col| !self.game.is_over() && board.is_valid_move(col))
+            .collect();
+
+        let mut state = self.summary();
+        state["rows"] = json!(rows);
+        state["to_move"] = to_move;
+        state["valid_moves"] = json!(valid_moves);
+        state["self_play"] = json!(self.self_play);
+        state
+    }
+}
+
+// Plays engine moves until the game is over. The engines think without holding
+// the lock, which is safe because other moves are refused during self-play.
+fn self_play(games: Games, id: u64, names: [&'static str; 2], delay: Duration) {
+    let mut engines = names.map(|name| engine_by_name(Some(name)).unwrap());
+    loop {
+        let board = *games.lock().unwrap()[&id].game.get_board();
+        // Red moves first.
+        let engine = &mut engines[board.get_move_count() % 2];
+        let name = engine.name();
+        let info = engine.analyze_with_progress(&board, &mut |info| report(&games, id, name, info));
+
+        let mut games = games.lock().unwrap();
+        let game = games.get_mut(&id).unwrap();
+        if let Some(info) = info {
+            game.report(name, &info);
+            let _ = game.make_move(info.pv[0]);
+        }
+        if game.game.is_over() {
+            return;
+        }
+        drop(games);
+        thread::sleep(delay);
+    }
+}
+
+// Passes on an engine's progress to the spectators of a game while it thinks.
+fn report(games: &Games, id: u64, name: &str, info: &Info) {
+    if let Some(game) = games.lock().unwrap().get_mut(&id) {
+        game.report(name, info);
+    }
+}
+
+// Writes the queued messages to a spectator's WebSocket until either side is gone.
+fn spectate(mut socket: Socket, messages: Receiver<String>) {
+    for text in messages {
+        if socket.send(Message::text(text)).is_err() {
+            return;
+        }
+    }
+}
+
+fn respond(request: Request, result: Result<Body, HttpError>) {
+    let (status, content_type, body) = match result {
+        Ok(Body::Json(value)) => (200, "application/json", value.to_string()),
+        Ok(Body::Text(text)) => (200, "text/plain; charset=utf-8", text),
+        Err(error) => (
+            error.status,
+            "application/json",
+            json!({ "error": error.message }).to_string(),
+        ),
+    };
+    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    let _ = request.respond(response);
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+fn is_websocket(request: &Request) -> bool {
+    header_value(request, "Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+fn read_json(request: &mut Request) -> Result<Value, HttpError> {
+    let mut text = String::new();
+    request
+        .as_reader()
+        .read_to_string(&mut text)
+        .map_err(|e| HttpError::new(400, e.to_string()))?;
+    if text.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(&text).map_err(|e| HttpError::new(400, format!("invalid JSON: {e}")))
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn player_name(body: &Value, color: &str) -> Result<String, HttpError> {
+    match &body[color] {
+        Value::Null => Ok("human".to_string()),
+        Value::String(name) => Ok(name.clone()),
+        _ => Err(HttpError::new(400, format!("{color} must be a string"))),
+    }
+}
+
+// Looks up an engine or a difficulty level, defaulting to Monte Carlo.
+fn engine_by_name(name: Option<&str>) -> Result<Box<dyn Engine>, HttpError> {
+    let name = name.unwrap_or("montecarlo");
+    engine::from_name(name)
+        .or_else(|| {
+            difficulty::from_name(name)
+                .map(|level| Box::new(Leveled::new(level)) as Box<dyn Engine>)
+        })
+        .ok_or(HttpError::new(400, format!("unknown engine: {name}")))
+}
+
+fn result_name(game: &Game) -> Value {
+    match game.get_result() {
+        MoveResult::WinX => json!("red"),
+        MoveResult::WinO => json!("yellow"),
+        MoveResult::Draw => json!("draw"),
+        _ => Value::Null,
+    }
+}
+
+// Scores that are not finite, such as immediate wins, are reported as null.
+fn analysis(engine: &mut dyn Engine, game: &Game) -> Result<Value, HttpError> {
+    if game.is_over() {
+        return Err(HttpError::new(409, "the game is over"));
+    }
+    let board = game.get_board();
+    let scores = engine.score_moves(board);
+    let mut best_move: Option<(usize, f64)> = None;
+    for &(col, score) in &scores {
+        if best_move.is_none_or(|(_, best_score)| score > best_score) {
+            best_move = Some((col, score));
+        }
+    }
+    let scores: Vec<Value> = scores
+        .into_iter()
+        .map(|(col, score)| json!({ "column": col, "score": score }))
+        .collect();
+    let outcomes: Vec<Value> = hint::prove_outcomes(board)
+        .iter()
+        .map(|outcome| outcome.map_or(Value::Null, |outcome| json!(outcome.label())))
+        .collect();
+    Ok(json!({
+        "engine": engine.name(),
+        "best_move": best_move.map(|(col, _)| col),
+        "scores": scores,
+        "outcomes": outcomes,
+    }))
+}
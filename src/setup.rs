@@ -0,0 +1,184 @@
+// Starting positions other than the empty board, for coaching and handicap
+// games
+//
+// A position is written as its rows from top to bottom, separated by '/',
+// with '.' for an empty cell, then a space and the player to move:
+//
+//   ......./......./......./......./...O.../..XX... O
+//
+// Every disc must rest on the bottom or on another disc. Either player may
+// have moved first, so the players have the same number of discs or one of
+// them has one more, in which case the other is to move. A handicap lets the
+// player to move at the start drop that many extra discs before the
+// opponent's first turn.
+
+use crate::board::{Board, Cell, MoveResult, HEIGHT, WIDTH};
+
+/// Where a game starts.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Setup {
+    board: Board,
+
+    // Extra discs the player to move drops before the opponent's first turn.
+    handicap: usize,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl Setup {
+    pub fn new(board: Board, handicap: usize) -> Self {
+        Self { board, handicap }
+    }
+
+    /// The empty board with X to move and no handicap.
+    pub fn standard() -> Self {
+        Self::new(Board::new(), 0)
+    }
+
+    pub fn is_standard(&self) -> bool {
+        *self == Self::standard()
+    }
+
+    pub fn get_board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn get_handicap(&self) -> usize {
+        self.handicap
+    }
+}
+
+/// Parses a position written like "......./......./......./......./...O.../..XX... O".
+pub fn parse_position(text: &str) -> Result<Board, String> {
+    let invalid = |reason: &str| format!("Invalid position ({reason}): {text}");
+    let (rows, player) = text
+        .trim()
+        .split_once(' ')
+        .ok_or(invalid("no player to move"))?;
+    let to_move = match player.trim() {
+        "X" => Cell::X,
+        "O" => Cell::O,
+        _ => return Err(invalid("the player to move must be X or O")),
+    };
+    let rows: Vec<&str> = rows.split('/').collect();
+    if rows.len() != HEIGHT || rows.iter().any(|row| row.chars().count() != WIDTH) {
+        return Err(invalid("expected 6 rows of 7 cells"));
+    }
+
+    let mut cells = [[Cell::Empty; HEIGHT]; WIDTH];
+    for (i, row) in rows.iter().enumerate() {
+        for (col, c) in row.chars().enumerate() {
+            cells[col][HEIGHT - 1 - i] = match c {
+                '.' => Cell::Empty,
+                'X' => Cell::X,
+                'O' => Cell::O,
+                _ => return Err(invalid(&format!("unknown cell '{c}'"))),
+            };
+        }
+    }
+
+    let count = |player| {
+        cells
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell == player)
+            .count()
+    };
+    let (x, o) = (count(Cell::X), count(Cell::O));
+    let parity_ok = match to_move {
+        Cell::X => x == o || o == x + 1,
+        _ => x == o || x == o + 1,
+    };
+    if !parity_ok {
+        return Err(invalid("the disc counts do not fit the player to move"));
+    }
+
+    // Drop the discs column by column, passing whenever the other player's
+    // disc comes next.
+    let mut board = Board::new();
+    for (col, column) in cells.iter().enumerate() {
+        let height = column
+            .iter()
+            .take_while(|&&cell| cell != Cell::Empty)
+            .count();
+        if column[height..].iter().any(|&cell| cell != Cell::Empty) {
+            return Err(invalid(&format!("a disc floats in column {col}")));
+        }
+        for &cell in &column[..height] {
+            if board.get_current_player() != cell {
+                board.pass();
+            }
+            match board.make_move(col) {
+                MoveResult::None => {}
+                MoveResult::Draw => return Err(invalid("the board is full")),
+                _ => return Err(invalid("the game is already won")),
+            }
+        }
+    }
+    if board.get_current_player() != to_move {
+        board.pass();
+    }
+    Ok(board)
+}
+
+/// Writes a position in the form read by `parse_position`.
+pub fn position_string(board: &Board) -> String {
+    let rows: Vec<String> = (0..HEIGHT)
+        .rev()
+        .map(|y| (0..WIDTH).map(|col| board.get(col, y).to_char()).collect())
+        .collect();
+    format!(
+        "{} {}",
+        rows.join("/"),
+        board.get_current_player().to_char()
+    )
+}
+
+#[cfg(test)]
+mod setup_tests {
+    use super::{parse_position, position_string};
+    use crate::board::{Board, Cell};
+
+    const EMPTY_ROWS: &str = "......./......./......./......./.......";
+
+    #[test]
+    fn round_trip() {
+        let board = Board::from_move_string("3324").unwrap();
+        let text = position_string(&board);
+        assert_eq!(text, format!("{}/...O.../..XXO.. X", &EMPTY_ROWS[..31]));
+        assert_eq!(parse_position(&text).unwrap(), board);
+
+        // O to move on the empty board, as when O starts with a handicap.
+        let board = parse_position(&format!("{EMPTY_ROWS}/....... O")).unwrap();
+        assert_eq!(board.get_current_player(), Cell::O);
+        assert_eq!(position_string(&board), format!("{EMPTY_ROWS}/....... O"));
+
+        // O moved first and X is to move.
+        let board = parse_position(&format!("{EMPTY_ROWS}/...O... X")).unwrap();
+        assert_eq!((board.get(3, 0), board.get_move_count()), (Cell::O, 1));
+        assert_eq!(board.get_current_player(), Cell::X);
+    }
+
+    #[test]
+    fn invalid_positions() {
+        for (text, reason) in [
+            (format!("{EMPTY_ROWS}/......."), "no player"),
+            (format!("{EMPTY_ROWS} X"), "rows"),
+            (format!("{EMPTY_ROWS}/...Z... O"), "unknown cell"),
+            (format!("{EMPTY_ROWS}/...X... X"), "disc counts"),
+            (format!("{EMPTY_ROWS}/..XXX.. O"), "disc counts"),
+            (format!("{}/...X.../....... O", &EMPTY_ROWS[..31]), "floats"),
+            (
+                format!("{}/...X.../...X.../...X.../...XOOO O", &EMPTY_ROWS[..15]),
+                "already won",
+            ),
+        ] {
+            let error = parse_position(&text).unwrap_err();
+            assert!(error.contains(reason), "{text}: {error}");
+        }
+    }
+}
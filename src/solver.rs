@@ -0,0 +1,253 @@
+// Solves connect 4 positions exactly using negamax with alpha-beta pruning
+
+use crate::board::{Board, MoveResult, HEIGHT, WIDTH};
+use std::sync::Arc;
+
+use crate::misere::Objective;
+use crate::ordering::{CutoffStats, Heuristics, MoveOrdering};
+use crate::tablebase::{self, Tablebase};
+
+// Scores are from the point of view of the player to move. A win is scored by
+// how early it happens: (WIDTH * HEIGHT + 1 - moves) / 2, where moves is the
+// number of moves played before the winning one. A draw scores 0 and a loss is
+// the negation of the opponent's win score. In misère games the player who
+// completes four loses, so moves is the number played before the losing one.
+pub struct Solver {
+    // Number of positions visited since the last reset.
+    node_count: u64,
+
+    ordering: MoveOrdering,
+    cutoffs: CutoffStats,
+
+    // Exact scores of late positions, ending the search when it reaches them.
+    // Only used for standard games.
+    tablebase: Option<Arc<Tablebase>>,
+
+    objective: Objective,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self::with_heuristics(Heuristics::ALL)
+    }
+
+    /// Creates a solver with the given move ordering, looking up positions
+    /// in the installed tablebase if there is one.
+    pub fn with_heuristics(heuristics: Heuristics) -> Self {
+        Self {
+            node_count: 0,
+            ordering: MoveOrdering::new(heuristics),
+            cutoffs: CutoffStats::default(),
+            tablebase: tablebase::installed(),
+            objective: Objective::Standard,
+        }
+    }
+
+    /// Creates a solver for games with the given objective.
+    pub fn with_objective(objective: Objective) -> Self {
+        Self {
+            objective,
+            ..Self::new()
+        }
+    }
+
+    pub fn get_objective(&self) -> Objective {
+        self.objective
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    pub fn get_node_count(&self) -> u64 {
+        self.node_count
+    }
+
+    pub fn get_cutoff_stats(&self) -> CutoffStats {
+        self.cutoffs
+    }
+
+    /// Clears the counters and what the move ordering has learned, so the next
+    /// solve does not depend on earlier ones.
+    pub fn reset(&mut self) {
+        self.node_count = 0;
+        self.cutoffs = CutoffStats::default();
+        self.ordering.clear();
+    }
+
+    /// Returns the exact score of the board for the player to move.
+    /// The position must not already be won by the previous move.
+    pub fn solve(&mut self, board: &Board) -> i32 {
+        self.negamax(
+            board,
+            -(WIDTH as i32) * HEIGHT as i32,
+            (WIDTH * HEIGHT) as i32,
+        )
+    }
+
+    /// Returns the score of the board if it lies within (alpha, beta). Otherwise
+    /// returns an upper bound when the score is <= alpha, or a lower bound when
+    /// the score is >= beta.
+    fn negamax(&mut self, board: &Board, mut alpha: i32, mut beta: i32) -> i32 {
+        self.node_count += 1;
+        let moves = board.get_move_count() as i32;
+        if board.is_full() {
+            return 0;
+        }
+
+        let max = match self.objective {
+            Objective::Standard => {
+                if (0..WIDTH).any(|col| completes_four(board, col)) {
+                    return (WIDTH as i32 * HEIGHT as i32 + 1 - moves) / 2;
+                }
+                if let Some(score) = self.tablebase.as_ref().and_then(|t| t.lookup(board)) {
+                    return score;
+                }
+                // We cannot win with this move, so the best we can do is win with the next one.
+                (WIDTH as i32 * HEIGHT as i32 - 1 - moves) / 2
+            }
+            Objective::Misere => {
+                if (0..WIDTH).all(|col| !board.is_valid_move(col) || completes_four(board, col)) {
+                    return -(WIDTH as i32 * HEIGHT as i32 + 1 - moves) / 2;
+                }
+                // The soonest we can win is the opponent's next move losing.
+                (WIDTH as i32 * HEIGHT as i32 - moves) / 2
+            }
+        };
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        let moves = self.ordering.order(board);
+        for (i, &col) in moves.as_slice().iter().enumerate() {
+            let mut next = *board;
+            if matches!(next.make_move(col), MoveResult::WinX | MoveResult::WinO) {
+                // Only in misère games, where completing four loses.
+                continue;
+            }
+            let score = -self.negamax(&next, -beta, -alpha);
+            if score >= beta {
+                self.cutoffs.cutoffs += 1;
+                self.cutoffs.moves_to_cutoff += i as u64 + 1;
+                if i == 0 {
+                    self.cutoffs.first_move_cutoffs += 1;
+                }
+                self.ordering.record_cutoff(board, col);
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        self.cutoffs.full_searches += 1;
+        alpha
+    }
+}
+
+fn completes_four(board: &Board, col: usize) -> bool {
+    let mut next = *board;
+    matches!(next.make_move(col), MoveResult::WinX | MoveResult::WinO)
+}
+
+#[cfg(test)]
+mod solver_tests {
+    use super::Solver;
+    use crate::board::{Board, MoveResult, HEIGHT, WIDTH};
+    use crate::misere::Objective;
+    use crate::ordering::{CutoffStats, Heuristics};
+    use crate::testset;
+
+    // Scores a misère position by playing out every line.
+    fn misere_minimax(board: &Board) -> i32 {
+        let moves = board.get_move_count() as i32;
+        let mut best = i32::MIN;
+        for col in 0..WIDTH {
+            let mut next = *board;
+            let score = match next.make_move(col) {
+                MoveResult::Illegal => continue,
+                MoveResult::WinX | MoveResult::WinO => {
+                    -(WIDTH as i32 * HEIGHT as i32 + 1 - moves) / 2
+                }
+                MoveResult::Draw => 0,
+                MoveResult::None => -misere_minimax(&next),
+            };
+            best = best.max(score);
+        }
+        best
+    }
+
+    #[test]
+    fn immediate_win() {
+        let board = Board::from_move_string("010101").unwrap();
+        let mut solver = Solver::new();
+        assert_eq!(solver.solve(&board), (WIDTH * HEIGHT + 1 - 6) as i32 / 2);
+        assert_eq!(solver.get_node_count(), 1);
+    }
+
+    #[test]
+    fn double_threat() {
+        // X threatens to complete the bottom row at both ends, so O cannot stop it.
+        let board = Board::from_move_string("11223").unwrap();
+        let mut solver = Solver::new();
+        assert_eq!(solver.solve(&board), -((WIDTH * HEIGHT + 1 - 6) as i32 / 2));
+    }
+
+    #[test]
+    fn misere() {
+        let mut solver = Solver::with_objective(Objective::Misere);
+
+        // Late positions from the test set, played on until nine cells are left
+        // without anyone completing four.
+        let cases = testset::parse(include_str!("../testsets/end.txt")).unwrap();
+        for case in &cases[..10] {
+            let mut board = Board::from_move_string(&case.moves).unwrap();
+            while board.get_move_count() < WIDTH * HEIGHT - 9 {
+                let col = (0..WIDTH)
+                    .find(|&col| {
+                        let mut next = board;
+                        next.make_move(col) == MoveResult::None
+                    })
+                    .unwrap();
+                board.make_move(col);
+            }
+            solver.reset();
+            assert_eq!(
+                solver.solve(&board),
+                misere_minimax(&board),
+                "{}",
+                case.moves
+            );
+        }
+    }
+
+    #[test]
+    fn ordering_keeps_scores_and_saves_nodes() {
+        let text = include_str!("../testsets/end.txt");
+        let cases = testset::parse(text).unwrap();
+        let mut nodes = [0; 2];
+        let mut stats = [CutoffStats::default(); 2];
+        for (i, heuristics) in [Heuristics::ALL, Heuristics::NONE].into_iter().enumerate() {
+            let mut solver = Solver::with_heuristics(heuristics);
+            for case in &cases[..20] {
+                solver.reset();
+                let board = Board::from_move_string(&case.moves).unwrap();
+                assert_eq!(solver.solve(&board), case.score, "{}", case.moves);
+                nodes[i] += solver.get_node_count();
+                stats[i].add(&solver.get_cutoff_stats());
+            }
+        }
+        assert!(nodes[0] < nodes[1]);
+        assert!(stats[0].cutoffs > 0);
+        assert!(stats[0].first_move_rate() > stats[1].first_move_rate());
+        assert!(stats[0].mean_moves_to_cutoff() >= 1.0);
+    }
+}
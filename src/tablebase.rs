@@ -0,0 +1,455 @@
+// Endgame tablebase: exact scores of every position reachable from an opening
+//
+// The generator plays every line from the opening until it has a given number
+// of moves played, and solves each position it reaches by full minimax over
+// the positions after it. The lines are not remembered, so time grows with
+// their number as well as with the positions after them. Reaching those
+// positions from the empty board is far too much work, so the opening is
+// normally a late position of interest a few moves before.
+//
+// Tablebase files are memory mapped and searched in place, so only the pages
+// a lookup touches are read from disk. A file is a 32 byte header followed by
+// the entries sorted by position key:
+//
+//   offset  0  magic "C4TB"
+//           4  format version: u32
+//           8  board width: u16, board height: u16
+//          12  generator version: u32
+//          16  min moves: u32
+//          20  CRC-32 of the entries: u32
+//          24  entry count: u64
+//          32  entries: u64 each, the position key shifted left by 8 bits,
+//              with the score as an i8 in the low byte
+//
+// All numbers are little endian. Opening a file only checks the header, while
+// `verify` reads every entry.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+
+use memmap2::Mmap;
+
+use crate::board::{Board, MoveResult, HEIGHT, WIDTH};
+
+const MAGIC: &[u8; 4] = b"C4TB";
+const FORMAT_VERSION: u32 = 2;
+const HEADER_LEN: usize = 32;
+const ENTRY_LEN: usize = 8;
+
+/// Version of the generator, raised whenever it would write different entries.
+pub const GENERATOR_VERSION: u32 = 1;
+
+// Tablebase used by the engines when they are created.
+static INSTALLED: RwLock<Option<Arc<Tablebase>>> = RwLock::new(None);
+
+/// Makes the engines look up positions in the tablebase. Solvers keep the
+/// tablebase that was installed when they were created.
+pub fn install(tablebase: Tablebase) {
+    *INSTALLED.write().unwrap() = Some(Arc::new(tablebase));
+}
+
+pub fn installed() -> Option<Arc<Tablebase>> {
+    INSTALLED.read().unwrap().clone()
+}
+
+/// Looks up the board in the installed tablebase, if any.
+pub fn probe(board: &Board) -> Option<i32> {
+    INSTALLED.read().unwrap().as_ref()?.lookup(board)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Header {
+    pub format_version: u32,
+    pub width: usize,
+    pub height: usize,
+    pub generator_version: u32,
+
+    // Positions with fewer moves played are not in the table.
+    pub min_moves: usize,
+
+    pub checksum: u32,
+    pub count: u64,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(MAGIC);
+        bytes[4..8].copy_from_slice(&self.format_version.to_le_bytes());
+        bytes[8..10].copy_from_slice(&(self.width as u16).to_le_bytes());
+        bytes[10..12].copy_from_slice(&(self.height as u16).to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.generator_version.to_le_bytes());
+        bytes[16..20].copy_from_slice(&(self.min_moves as u32).to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.count.to_le_bytes());
+        bytes
+    }
+
+    // Reads the header of a file, checking that it can be used with this board.
+    fn parse(bytes: &[u8]) -> Result<Header, String> {
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+            return Err("not a tablebase file".to_string());
+        }
+        let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let header = Header {
+            format_version: u32_at(4),
+            width: u16_at(8) as usize,
+            height: u16_at(10) as usize,
+            generator_version: u32_at(12),
+            min_moves: u32_at(16) as usize,
+            checksum: u32_at(20),
+            count: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+        };
+        if header.format_version != FORMAT_VERSION {
+            return Err(format!(
+                "unsupported format version {} (expected {FORMAT_VERSION}), regenerate the file",
+                header.format_version
+            ));
+        }
+        if (header.width, header.height) != (WIDTH, HEIGHT) {
+            return Err(format!(
+                "made for a {}x{} board, not {WIDTH}x{HEIGHT}",
+                header.width, header.height
+            ));
+        }
+        let len = (bytes.len() - HEADER_LEN) as u64;
+        if len != header.count.saturating_mul(ENTRY_LEN as u64) {
+            return Err(format!(
+                "expected {} entries, found {len} bytes of entries",
+                header.count
+            ));
+        }
+        Ok(header)
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "format {}, {}x{} board, generator {}, {} positions with at least {} moves, checksum {:08x}",
+            self.format_version,
+            self.width,
+            self.height,
+            self.generator_version,
+            self.count,
+            self.min_moves,
+            self.checksum
+        )
+    }
+}
+
+// The bytes of a whole tablebase file.
+#[derive(Debug)]
+enum Data {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Owned(bytes) => bytes,
+            Data::Mapped(map) => map,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Tablebase {
+    header: Header,
+    data: Data,
+}
+
+impl PartialEq for Tablebase {
+    fn eq(&self, other: &Tablebase) -> bool {
+        *self.data == *other.data
+    }
+}
+
+impl Tablebase {
+    /// Solves every position reachable from the opening that has at least
+    /// `min_moves` moves played and is not over.
+    pub fn generate(opening: &Board, min_moves: usize) -> Tablebase {
+        let mut generator = Generator {
+            min_moves,
+            scores: HashMap::new(),
+        };
+        generator.visit(opening);
+        let mut entries: Vec<u64> = generator
+            .scores
+            .into_iter()
+            .map(|(key, score)| key << 8 | score as u8 as u64)
+            .collect();
+        entries.sort_unstable();
+
+        let mut bytes = vec![0; HEADER_LEN];
+        for entry in &entries {
+            bytes.extend_from_slice(&entry.to_le_bytes());
+        }
+        let header = Header {
+            format_version: FORMAT_VERSION,
+            width: WIDTH,
+            height: HEIGHT,
+            generator_version: GENERATOR_VERSION,
+            min_moves,
+            checksum: crc32fast::hash(&bytes[HEADER_LEN..]),
+            count: entries.len() as u64,
+        };
+        bytes[..HEADER_LEN].copy_from_slice(&header.to_bytes());
+        Tablebase {
+            header,
+            data: Data::Owned(bytes),
+        }
+    }
+
+    pub fn get_header(&self) -> Header {
+        self.header
+    }
+
+    pub fn get_min_moves(&self) -> usize {
+        self.header.min_moves
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.count == 0
+    }
+
+    fn entry(&self, index: usize) -> u64 {
+        let start = HEADER_LEN + index * ENTRY_LEN;
+        u64::from_le_bytes(self.data[start..start + ENTRY_LEN].try_into().unwrap())
+    }
+
+    /// Returns the score of the board for the player to move, in the units of
+    /// `Solver::solve`, if the board is in the table.
+    pub fn lookup(&self, board: &Board) -> Option<i32> {
+        if board.get_move_count() < self.header.min_moves {
+            return None;
+        }
+        let key = board.key();
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry = self.entry(middle);
+            match (entry >> 8).cmp(&key) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Some(entry as u8 as i8 as i32),
+            }
+        }
+        None
+    }
+
+    /// Checks the checksum and that the entries are sorted with valid scores.
+    pub fn verify(&self) -> Result<(), String> {
+        let checksum = crc32fast::hash(&self.data[HEADER_LEN..]);
+        if checksum != self.header.checksum {
+            return Err(format!(
+                "checksum {checksum:08x} does not match the header's {:08x}",
+                self.header.checksum
+            ));
+        }
+        let max_score = (WIDTH * HEIGHT) as i32 / 2;
+        for index in 0..self.len() {
+            let entry = self.entry(index);
+            let score = entry as u8 as i8 as i32;
+            if score.abs() > max_score {
+                return Err(format!("entry {index} has an invalid score {score}"));
+            }
+            if index > 0 && self.entry(index - 1) >> 8 >= entry >> 8 {
+                return Err(format!("entry {index} is out of order"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Reads a tablebase from the bytes of a file, checking only the header.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tablebase, String> {
+        Ok(Tablebase {
+            header: Header::parse(bytes)?,
+            data: Data::Owned(bytes.to_vec()),
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.as_bytes()).map_err(|e| format!("{path}: {e}"))
+    }
+
+    /// Maps a tablebase file into memory, checking only the header.
+    pub fn load(path: &str) -> Result<Tablebase, String> {
+        let file = File::open(path).map_err(|e| format!("{path}: {e}"))?;
+        // SAFETY: the map is only read. Changing the file while it is mapped
+        // gives wrong scores, which `verify` detects, and truncating it makes
+        // lookups past the new end crash, so files must not be rewritten in use.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("{path}: {e}"))?;
+        Ok(Tablebase {
+            header: Header::parse(&map).map_err(|e| format!("{path}: {e}"))?,
+            data: Data::Mapped(map),
+        })
+    }
+}
+
+struct Generator {
+    min_moves: usize,
+
+    // Scores of the solved positions, by key.
+    scores: HashMap<u64, i8>,
+}
+
+impl Generator {
+    // Plays every line from a position before `min_moves` until it reaches
+    // `min_moves`, and solves the position there unless another line already
+    // did. Nothing is kept about the positions before, so lines that transpose
+    // into each other are played through again.
+    fn visit(&mut self, board: &Board) {
+        if board.get_move_count() >= self.min_moves {
+            self.solve(board);
+            return;
+        }
+        for col in 0..WIDTH {
+            let mut next = *board;
+            if next.make_move(col) == MoveResult::None {
+                self.visit(&next);
+            }
+        }
+    }
+
+    // Returns the exact score of a position that is not over, storing it along
+    // with the scores of every position after it.
+    fn solve(&mut self, board: &Board) -> i32 {
+        if let Some(&score) = self.scores.get(&board.key()) {
+            return score as i32;
+        }
+        let moves = board.get_move_count() as i32;
+        let mut best = -(WIDTH as i32) * HEIGHT as i32;
+        for col in 0..WIDTH {
+            let mut next = *board;
+            let score = match next.make_move(col) {
+                MoveResult::Illegal => continue,
+                MoveResult::WinX | MoveResult::WinO => {
+                    (WIDTH as i32 * HEIGHT as i32 + 1 - moves) / 2
+                }
+                MoveResult::Draw => 0,
+                MoveResult::None => -self.solve(&next),
+            };
+            best = best.max(score);
+        }
+        self.scores.insert(board.key(), best as i8);
+        best
+    }
+}
+
+#[cfg(test)]
+mod tablebase_tests {
+    use std::sync::Arc;
+    use std::{env, fs, process};
+
+    use super::{Tablebase, HEADER_LEN};
+    use crate::board::{Board, MoveResult, WIDTH};
+    use crate::solver::Solver;
+
+    // An end game position that O wins, with 18 empty cells.
+    const OPENING: &str = "242512646300052352144344";
+
+    #[test]
+    fn matches_the_solver() {
+        let opening = Board::from_move_string(OPENING).unwrap();
+        let tablebase = Tablebase::generate(&opening, 30);
+        assert!(!tablebase.is_empty());
+        assert_eq!(tablebase.lookup(&opening), None);
+
+        // Check the positions along a few lines from the opening.
+        let mut solver = Solver::new();
+        for first in 0..WIDTH {
+            let mut board = opening;
+            let mut col = first;
+            while board.make_move(col) == MoveResult::None {
+                if board.get_move_count() >= 30 {
+                    let score = tablebase.lookup(&board);
+                    assert_eq!(score, Some(solver.solve(&board)), "{board:?}");
+                }
+                col = (col * 3 + 1) % WIDTH;
+                while !board.is_valid_move(col) {
+                    col = (col + 1) % WIDTH;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn solver_uses_tablebase() {
+        let opening = Board::from_move_string("13005600613243222035061464214413").unwrap();
+        let tablebase = Arc::new(Tablebase::generate(&opening, 34));
+        let mut plain = Solver::new();
+        let mut probing = Solver::new();
+        probing.set_tablebase(Some(tablebase));
+        assert_eq!(probing.solve(&opening), plain.solve(&opening));
+        assert!(probing.get_node_count() < plain.get_node_count());
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let opening = Board::from_move_string("2425126463000523521443446210").unwrap();
+        let tablebase = Tablebase::generate(&opening, 32);
+        assert_eq!(tablebase.verify(), Ok(()));
+
+        let path = env::temp_dir().join(format!("connect4-tablebase-{}.c4tb", process::id()));
+        let path = path.to_str().unwrap();
+        tablebase.save(path).unwrap();
+        let loaded = Tablebase::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded, tablebase);
+        assert_eq!(loaded.get_header(), tablebase.get_header());
+        assert_eq!(loaded.verify(), Ok(()));
+
+        // Play the lowest column that does not end the game up to the table.
+        let mut board = opening;
+        while board.get_move_count() < 32 {
+            let col = (0..WIDTH)
+                .find(|&col| {
+                    let mut next = board;
+                    next.make_move(col) == MoveResult::None
+                })
+                .unwrap();
+            board.make_move(col);
+        }
+        assert_eq!(loaded.lookup(&board), Some(Solver::new().solve(&board)));
+    }
+
+    #[test]
+    fn damaged_files() {
+        let opening = Board::from_move_string("2425126463000523521443446210").unwrap();
+        let bytes = Tablebase::generate(&opening, 32).as_bytes().to_vec();
+        assert!(Tablebase::from_bytes(b"C4XX").is_err());
+        assert!(Tablebase::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // Files from an older format or for another board size are refused.
+        let mut old = bytes.clone();
+        old[4] = 1;
+        assert!(Tablebase::from_bytes(&old).is_err());
+        let mut wide = bytes.clone();
+        wide[8] = 8;
+        assert!(Tablebase::from_bytes(&wide).is_err());
+
+        // Changed entries are only found by verifying.
+        let mut changed = bytes.clone();
+        changed[HEADER_LEN] ^= 1;
+        let tablebase = Tablebase::from_bytes(&changed).unwrap();
+        assert!(tablebase.verify().unwrap_err().contains("checksum"));
+    }
+}
@@ -0,0 +1,217 @@
+// Generates and runs solver test sets
+//
+// A test set is a text file with one position per line: a move string of
+// column digits followed by the exact score of the position for the player to
+// move. Blank lines and lines starting with '#' are ignored.
+
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::board::{self, Board, MoveResult};
+use crate::ordering::{CutoffStats, Heuristics};
+use crate::solver::Solver;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difficulty {
+    Begin,
+    Middle,
+    End,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Begin, Difficulty::Middle, Difficulty::End];
+
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        Difficulty::ALL.into_iter().find(|d| d.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Begin => "begin",
+            Difficulty::Middle => "middle",
+            Difficulty::End => "end",
+        }
+    }
+
+    /// Number of moves already played in positions of this difficulty.
+    pub fn ply_range(self) -> RangeInclusive<usize> {
+        match self {
+            Difficulty::Begin => 20..=24,
+            Difficulty::Middle => 25..=29,
+            Difficulty::End => 30..=35,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TestCase {
+    pub moves: String,
+    pub score: i32,
+}
+
+/// Parses the contents of a test set file.
+pub fn parse(text: &str) -> Result<Vec<TestCase>, String> {
+    let mut cases = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(moves), Some(score), None) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(format!("line {}: expected '<moves> <score>'", i + 1));
+        };
+        if Board::from_move_string(moves).is_none() {
+            return Err(format!("line {}: invalid position '{moves}'", i + 1));
+        }
+        let score = score
+            .parse()
+            .map_err(|_| format!("line {}: invalid score '{score}'", i + 1))?;
+        cases.push(TestCase {
+            moves: moves.to_string(),
+            score,
+        });
+    }
+    Ok(cases)
+}
+
+/// Formats test cases in the format read by `parse`.
+pub fn format(difficulty: Difficulty, cases: &[TestCase]) -> String {
+    let plies = difficulty.ply_range();
+    let mut text = format!(
+        "# {} test set: {} positions after {} to {} moves\n",
+        difficulty.name(),
+        cases.len(),
+        plies.start(),
+        plies.end()
+    );
+    for case in cases {
+        writeln!(text, "{} {}", case.moves, case.score).unwrap();
+    }
+    text
+}
+
+/// Generates positions by random play and labels them with the solver.
+/// Positions where the game has already ended are skipped.
+pub fn generate<R: Rng>(difficulty: Difficulty, count: usize, rng: &mut R) -> Vec<TestCase> {
+    let mut solver = Solver::new();
+    let mut cases = Vec::with_capacity(count);
+    while cases.len() < count {
+        let ply = rng.gen_range(difficulty.ply_range());
+        let mut board = Board::new();
+        let mut moves = String::new();
+        while moves.len() < ply {
+            let col = rng.gen_range(0..board::WIDTH);
+            if !board.is_valid_move(col) {
+                continue;
+            }
+            if board.make_move(col) != MoveResult::None {
+                break;
+            }
+            moves.push_str(&col.to_string());
+        }
+        if moves.len() == ply {
+            let score = solver.solve(&board);
+            cases.push(TestCase { moves, score });
+        }
+    }
+    cases
+}
+
+pub struct Report {
+    pub positions: usize,
+    pub total_time: Duration,
+    pub total_nodes: u64,
+    pub cutoffs: CutoffStats,
+    // Test cases where the solver disagreed, along with the solver's score.
+    pub failures: Vec<(TestCase, i32)>,
+}
+
+impl Report {
+    pub fn mean_time(&self) -> Duration {
+        self.total_time / self.positions.max(1) as u32
+    }
+
+    pub fn mean_nodes(&self) -> f64 {
+        self.total_nodes as f64 / self.positions.max(1) as f64
+    }
+
+    pub fn nodes_per_second(&self) -> f64 {
+        self.total_nodes as f64 / self.total_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Solves every test case with the given move ordering, checking the solver's
+/// score against the expected one.
+pub fn run(cases: &[TestCase], heuristics: Heuristics) -> Report {
+    let mut solver = Solver::with_heuristics(heuristics);
+    let mut report = Report {
+        positions: cases.len(),
+        total_time: Duration::ZERO,
+        total_nodes: 0,
+        cutoffs: CutoffStats::default(),
+        failures: Vec::new(),
+    };
+    for case in cases {
+        let board = Board::from_move_string(&case.moves).unwrap();
+        solver.reset();
+        let start = Instant::now();
+        let score = solver.solve(&board);
+        report.total_time += start.elapsed();
+        report.total_nodes += solver.get_node_count();
+        report.cutoffs.add(&solver.get_cutoff_stats());
+        if score != case.score {
+            report.failures.push((case.clone(), score));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod testset_tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{format, generate, parse, run, Difficulty, TestCase};
+    use crate::ordering::Heuristics;
+
+    #[test]
+    fn parse_lines() {
+        let cases = parse("# comment\n\n0123 -2\n  33 5  \n").unwrap();
+        assert_eq!(
+            cases,
+            vec![
+                TestCase {
+                    moves: "0123".to_string(),
+                    score: -2
+                },
+                TestCase {
+                    moves: "33".to_string(),
+                    score: 5
+                },
+            ]
+        );
+        assert!(parse("0123").is_err());
+        assert!(parse("0123 x").is_err());
+        assert!(parse("0123 1 2").is_err());
+        assert!(parse("0101010 1").is_err());
+    }
+
+    #[test]
+    fn generate_and_run() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let cases = generate(Difficulty::End, 3, &mut rng);
+        assert_eq!(parse(&format(Difficulty::End, &cases)).unwrap(), cases);
+
+        let report = run(&cases, Heuristics::ALL);
+        assert_eq!(report.positions, 3);
+        assert!(report.failures.is_empty());
+        assert!(report.total_nodes <= run(&cases, Heuristics::NONE).total_nodes);
+
+        let mut wrong = cases[0].clone();
+        wrong.score += 1;
+        assert_eq!(run(&[wrong], Heuristics::NONE).failures.len(), 1);
+    }
+}
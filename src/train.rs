@@ -0,0 +1,538 @@
+// Self-play training for the policy and value network
+//
+// Each iteration the best network so far plays games against itself with tree
+// search, recording for every position the share of the search spent on each
+// move and the final result for the player to move. The samples and their
+// mirror images are saved as a shard, a candidate network is trained on the
+// most recent shards, and it replaces the best network only if it wins a
+// match against it.
+//
+// A shard file is a 24 byte header followed by the samples:
+//
+//   offset  0  magic "C4SP"
+//           4  format version: u32
+//           8  board width: u16, board height: u16
+//          12  CRC-32 of the samples: u32
+//          16  sample count: u64
+//          24  samples, 48 bytes each: the discs of the player to move and of
+//              the opponent as u64 bitmaps, bit col * height + y, then the
+//              visit share of each column as f32 and the outcome as f32
+//
+// All numbers are little endian.
+
+use std::fs;
+
+use rand::Rng;
+
+use crate::board::{Board, Cell, MoveResult, HEIGHT, WIDTH};
+use crate::mcts::Mcts;
+use crate::nn::{Layer, Network, INPUTS};
+
+const MAGIC: &[u8; 4] = b"C4SP";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 24;
+const SAMPLE_LEN: usize = 16 + 4 * WIDTH + 4;
+
+/// Moves at the start of each game chosen in proportion to the search's
+/// visits rather than by the most visited, so that games differ.
+pub const RANDOM_MOVES: usize = 8;
+
+/// Share of the match points a candidate needs to replace the best network.
+pub const ACCEPT_SCORE: f64 = 0.55;
+
+/// Games in the match that decides whether a candidate is kept.
+pub const MATCH_GAMES: usize = 20;
+
+/// Number of most recent shards a candidate is trained on.
+pub const SHARD_WINDOW: usize = 10;
+
+/// Fewest simulations for a search to visit any move, as the first one only
+/// expands the root.
+pub const MIN_SIMULATIONS: usize = 2;
+
+/// One position from a self-play game.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sample {
+    // Discs of the player to move and of the opponent, bit col * HEIGHT + y.
+    pub own: u64,
+    pub opponent: u64,
+
+    // Share of the search's visits given to each column.
+    pub policy: [f32; WIDTH],
+
+    // Result of the game for the player to move: 1, 0 or -1.
+    pub outcome: f32,
+}
+
+impl Sample {
+    pub fn new(board: &Board, policy: [f32; WIDTH], outcome: f32) -> Self {
+        let player = board.get_current_player();
+        let (mut own, mut opponent) = (0, 0);
+        for col in 0..WIDTH {
+            for y in 0..board.get_height(col) {
+                let bit = 1 << (col * HEIGHT + y);
+                if board.get(col, y) == player {
+                    own |= bit;
+                } else {
+                    opponent |= bit;
+                }
+            }
+        }
+        Self {
+            own,
+            opponent,
+            policy,
+            outcome,
+        }
+    }
+
+    /// The network input for the position, as `nn::encode` gives it.
+    pub fn input(&self) -> Vec<f32> {
+        let mut input = vec![0.0; INPUTS];
+        for i in 0..WIDTH * HEIGHT {
+            input[i] = (self.own >> i & 1) as f32;
+            input[WIDTH * HEIGHT + i] = (self.opponent >> i & 1) as f32;
+        }
+        input
+    }
+
+    /// The same position and policy reflected left to right.
+    pub fn mirror(&self) -> Sample {
+        let flip = |bits: u64| {
+            (0..WIDTH).fold(0, |flipped, col| {
+                let column = bits >> (col * HEIGHT) & ((1 << HEIGHT) - 1);
+                flipped | column << ((WIDTH - 1 - col) * HEIGHT)
+            })
+        };
+        let mut policy = self.policy;
+        policy.reverse();
+        Sample {
+            own: flip(self.own),
+            opponent: flip(self.opponent),
+            policy,
+            outcome: self.outcome,
+        }
+    }
+}
+
+/// Adds the mirror image of each sample.
+pub fn augment(samples: &[Sample]) -> Vec<Sample> {
+    samples
+        .iter()
+        .flat_map(|sample| [*sample, sample.mirror()])
+        .collect()
+}
+
+pub fn shard_to_bytes(samples: &[Sample]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(samples.len() * SAMPLE_LEN);
+    for sample in samples {
+        body.extend_from_slice(&sample.own.to_le_bytes());
+        body.extend_from_slice(&sample.opponent.to_le_bytes());
+        for share in sample.policy {
+            body.extend_from_slice(&share.to_le_bytes());
+        }
+        body.extend_from_slice(&sample.outcome.to_le_bytes());
+    }
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(WIDTH as u16).to_le_bytes());
+    bytes.extend_from_slice(&(HEIGHT as u16).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    bytes.extend_from_slice(&(samples.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+pub fn shard_from_bytes(bytes: &[u8]) -> Result<Vec<Sample>, String> {
+    if bytes.len() < HEADER_LEN || &bytes[0..4] != MAGIC {
+        return Err("not a shard file".to_string());
+    }
+    let u16_at = |i: usize| u16::from_le_bytes(bytes[i..i + 2].try_into().unwrap());
+    let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let version = u32_at(4);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "unsupported format version {version} (expected {FORMAT_VERSION})"
+        ));
+    }
+    let (width, height) = (u16_at(8) as usize, u16_at(10) as usize);
+    if (width, height) != (WIDTH, HEIGHT) {
+        return Err(format!(
+            "made for a {width}x{height} board, not {WIDTH}x{HEIGHT}"
+        ));
+    }
+    let count = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+    let body = &bytes[HEADER_LEN..];
+    if body.len() as u64 != count.saturating_mul(SAMPLE_LEN as u64) {
+        return Err(format!(
+            "expected {count} samples, found {} bytes of samples",
+            body.len()
+        ));
+    }
+    if crc32fast::hash(body) != u32_at(12) {
+        return Err("checksum mismatch".to_string());
+    }
+
+    let f32_at = |chunk: &[u8], i: usize| f32::from_le_bytes(chunk[i..i + 4].try_into().unwrap());
+    Ok(body
+        .chunks(SAMPLE_LEN)
+        .map(|chunk| {
+            let mut policy = [0.0; WIDTH];
+            for (col, share) in policy.iter_mut().enumerate() {
+                *share = f32_at(chunk, 16 + 4 * col);
+            }
+            Sample {
+                own: u64::from_le_bytes(chunk[0..8].try_into().unwrap()),
+                opponent: u64::from_le_bytes(chunk[8..16].try_into().unwrap()),
+                policy,
+                outcome: f32_at(chunk, 16 + 4 * WIDTH),
+            }
+        })
+        .collect())
+}
+
+pub fn write_shard(path: &str, samples: &[Sample]) -> Result<(), String> {
+    fs::write(path, shard_to_bytes(samples)).map_err(|e| format!("{path}: {e}"))
+}
+
+pub fn read_shard(path: &str) -> Result<Vec<Sample>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    shard_from_bytes(&bytes).map_err(|e| format!("{path}: {e}"))
+}
+
+// Share of the search's visits given to each column, or an even share of the
+// valid columns if the search visited none.
+fn visit_shares(board: &Board, visits: &[u32; WIDTH]) -> [f32; WIDTH] {
+    let total: u32 = visits.iter().sum();
+    if total > 0 {
+        return visits.map(|count| count as f32 / total as f32);
+    }
+    let valid = (0..WIDTH).filter(|&col| board.is_valid_move(col)).count();
+    let mut shares = [0.0; WIDTH];
+    for (col, share) in shares.iter_mut().enumerate() {
+        if board.is_valid_move(col) {
+            *share = 1.0 / valid as f32;
+        }
+    }
+    shares
+}
+
+// Picks a column in proportion to its visits, or the most visited one. If
+// the search visited none, any valid column is equally likely.
+fn pick_move<R: Rng>(
+    board: &Board,
+    visits: &[u32; WIDTH],
+    proportional: bool,
+    rng: &mut R,
+) -> usize {
+    let total: u32 = visits.iter().sum();
+    if total == 0 {
+        let valid: Vec<usize> = (0..WIDTH).filter(|&col| board.is_valid_move(col)).collect();
+        return valid[rng.gen_range(0..valid.len())];
+    }
+    if proportional {
+        let mut pick = rng.gen_range(0..total);
+        for (col, &count) in visits.iter().enumerate() {
+            if pick < count {
+                return col;
+            }
+            pick -= count;
+        }
+    }
+    let best = visits.iter().max().unwrap();
+    visits.iter().position(|count| count == best).unwrap()
+}
+
+/// Plays games of the network against itself and returns a sample for every
+/// position played.
+pub fn self_play<R: Rng>(
+    network: &Network,
+    games: usize,
+    simulations: usize,
+    rng: &mut R,
+) -> Vec<Sample> {
+    let mut engine = Mcts::with_simulations(network.clone(), simulations);
+    let mut samples = Vec::new();
+    for _ in 0..games {
+        let mut board = Board::new();
+        let mut positions = Vec::new();
+        let result = loop {
+            let visits = engine.search(&board).get_visits();
+            let policy = visit_shares(&board, &visits);
+            let col = pick_move(&board, &visits, board.get_move_count() < RANDOM_MOVES, rng);
+            positions.push((board, policy));
+            let result = board.make_move(col);
+            if result != MoveResult::None {
+                break result;
+            }
+        };
+        for (board, policy) in positions {
+            let outcome = match result {
+                MoveResult::Draw => 0.0,
+                _ if board.get_current_player() == Cell::from(result) => 1.0,
+                _ => -1.0,
+            };
+            samples.push(Sample::new(&board, policy, outcome));
+        }
+    }
+    samples
+}
+
+/// Settings for training a network on samples.
+#[derive(Clone, Copy, Debug)]
+pub struct Training {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+
+    // Weight decay, pulling every weight towards zero.
+    pub l2: f32,
+}
+
+impl Default for Training {
+    fn default() -> Self {
+        Self {
+            epochs: 2,
+            batch_size: 64,
+            learning_rate: 0.01,
+            l2: 1e-4,
+        }
+    }
+}
+
+/// Value and policy loss of the network on a sample: the squared error of the
+/// value plus the cross entropy of the policy.
+pub fn loss(network: &Network, sample: &Sample) -> f32 {
+    let activations = network.forward(&sample.input());
+    let value = activations.value.tanh() - sample.outcome;
+    let policy = softmax(&activations.logits);
+    let cross_entropy: f32 = sample
+        .policy
+        .iter()
+        .zip(&policy)
+        .map(|(target, p)| -target * p.max(1e-12).ln())
+        .sum();
+    value * value + cross_entropy
+}
+
+// Softmax over every column, so that training also pushes down full columns.
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / total).collect()
+}
+
+// Adds the layer's gradients for one input to `grad` and returns the gradient
+// of the loss with respect to the input.
+fn backward(layer: &Layer, grad: &mut Layer, input: &[f32], output_grad: &[f32]) -> Vec<f32> {
+    let mut input_grad = vec![0.0; layer.inputs];
+    for (o, &g) in output_grad.iter().enumerate() {
+        if g == 0.0 {
+            continue;
+        }
+        grad.biases[o] += g;
+        let row = o * layer.inputs;
+        for i in 0..layer.inputs {
+            grad.weights[row + i] += g * input[i];
+            input_grad[i] += g * layer.weights[row + i];
+        }
+    }
+    input_grad
+}
+
+// Adds the gradients of `loss` for one sample to `grad`.
+fn accumulate(network: &Network, grad: &mut Network, sample: &Sample) {
+    let activations = network.forward(&sample.input());
+    let last = activations.layers.last().unwrap();
+
+    let policy = softmax(&activations.logits);
+    let logits_grad: Vec<f32> = policy
+        .iter()
+        .zip(&sample.policy)
+        .map(|(p, target)| p - target)
+        .collect();
+    let value = activations.value.tanh();
+    let value_grad = 2.0 * (value - sample.outcome) * (1.0 - value * value);
+
+    let mut hidden_grad = backward(&network.policy, &mut grad.policy, last, &logits_grad);
+    let from_value = backward(&network.value, &mut grad.value, last, &[value_grad]);
+    hidden_grad
+        .iter_mut()
+        .zip(from_value)
+        .for_each(|(g, v)| *g += v);
+
+    for index in (0..network.hidden.len()).rev() {
+        // No gradient flows through a unit that ReLU cut off.
+        let output = &activations.layers[index + 1];
+        for (g, &x) in hidden_grad.iter_mut().zip(output) {
+            if x <= 0.0 {
+                *g = 0.0;
+            }
+        }
+        hidden_grad = backward(
+            &network.hidden[index],
+            &mut grad.hidden[index],
+            &activations.layers[index],
+            &hidden_grad,
+        );
+    }
+}
+
+/// Trains the network by minibatch gradient descent and returns the mean
+/// loss over the samples before each epoch's updates.
+pub fn train<R: Rng>(
+    network: &mut Network,
+    samples: &[Sample],
+    training: &Training,
+    rng: &mut R,
+) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..samples.len()).collect();
+    let mut losses = Vec::new();
+    for _ in 0..training.epochs {
+        let total: f32 = samples.iter().map(|sample| loss(network, sample)).sum();
+        losses.push(total / samples.len().max(1) as f32);
+
+        for i in (1..order.len()).rev() {
+            order.swap(i, rng.gen_range(0..=i));
+        }
+        for batch in order.chunks(training.batch_size.max(1)) {
+            let mut grad = network.clone();
+            grad.layers_mut()
+                .flat_map(Layer::parameters_mut)
+                .for_each(|g| *g = 0.0);
+            for &index in batch {
+                accumulate(network, &mut grad, &samples[index]);
+            }
+            let scale = training.learning_rate / batch.len() as f32;
+            for (parameter, g) in network
+                .layers_mut()
+                .flat_map(Layer::parameters_mut)
+                .zip(grad.layers().flat_map(Layer::parameters))
+            {
+                *parameter -= scale * g + training.learning_rate * training.l2 * *parameter;
+            }
+        }
+    }
+    losses
+}
+
+/// Plays a match between two networks, each taking X in half the games, and
+/// returns the candidate's share of the points with a draw worth half.
+pub fn play_match<R: Rng>(
+    candidate: &Network,
+    best: &Network,
+    games: usize,
+    simulations: usize,
+    rng: &mut R,
+) -> f64 {
+    let mut engines = [
+        Mcts::with_simulations(candidate.clone(), simulations),
+        Mcts::with_simulations(best.clone(), simulations),
+    ];
+    let mut points = 0.0;
+    for game in 0..games {
+        // The candidate plays X in even games.
+        let candidate_cell = if game % 2 == 0 { Cell::X } else { Cell::O };
+        let mut board = Board::new();
+        let result = loop {
+            let engine = if board.get_current_player() == candidate_cell {
+                &mut engines[0]
+            } else {
+                &mut engines[1]
+            };
+            let visits = engine.search(&board).get_visits();
+            // A couple of varied opening moves keep the games from repeating.
+            let col = pick_move(&board, &visits, board.get_move_count() < 2, rng);
+            let result = board.make_move(col);
+            if result != MoveResult::None {
+                break result;
+            }
+        };
+        points += match result {
+            MoveResult::Draw => 0.5,
+            _ if Cell::from(result) == candidate_cell => 1.0,
+            _ => 0.0,
+        };
+    }
+    points / games.max(1) as f64
+}
+
+#[cfg(test)]
+mod train_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::{
+        augment, loss, self_play, shard_from_bytes, shard_to_bytes, train, Sample, Training,
+    };
+    use crate::board::{Board, WIDTH};
+    use crate::nn::{encode, Network};
+
+    #[test]
+    fn samples_mirror_and_round_trip() {
+        let board = Board::from_move_string("0014").unwrap();
+        let sample = Sample::new(&board, [0.5, 0.25, 0.25, 0.0, 0.0, 0.0, 0.0], -1.0);
+        assert_eq!(sample.input(), encode(&board));
+
+        let mirrored = Board::from_move_string("6652").unwrap();
+        let mirror = sample.mirror();
+        assert_eq!(mirror.input(), encode(&mirrored));
+        assert_eq!(mirror.policy[WIDTH - 1], 0.5);
+        assert_eq!(mirror.mirror(), sample);
+
+        let samples = augment(&[sample]);
+        assert_eq!(samples, vec![sample, mirror]);
+        let bytes = shard_to_bytes(&samples);
+        assert_eq!(shard_from_bytes(&bytes).unwrap(), samples);
+        assert!(shard_from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut damaged = bytes.clone();
+        damaged[30] ^= 1;
+        assert!(shard_from_bytes(&damaged).unwrap_err().contains("checksum"));
+    }
+
+    #[test]
+    fn self_play_labels_outcomes() {
+        let network = Network::new(&[16], 1);
+        let mut rng = StdRng::seed_from_u64(2);
+        let samples = self_play(&network, 1, 16, &mut rng);
+        assert!(samples.len() >= 7);
+        for sample in &samples {
+            assert!((sample.policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+        // The players to move alternate, so decisive outcomes alternate too.
+        for pair in samples.windows(2) {
+            assert_eq!(pair[0].outcome, -pair[1].outcome);
+        }
+
+        // A single simulation visits no move, so the targets are even.
+        for sample in self_play(&network, 1, 1, &mut rng) {
+            assert!((sample.policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn training_lowers_the_loss() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut network = Network::new(&[32], 4);
+        let samples = augment(&[
+            Sample::new(&Board::new(), [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 1.0),
+            Sample::new(
+                &Board::from_move_string("3").unwrap(),
+                [0.0, 0.0, 0.5, 0.5, 0.0, 0.0, 0.0],
+                -1.0,
+            ),
+        ]);
+        let before: f32 = samples.iter().map(|sample| loss(&network, sample)).sum();
+        let training = Training {
+            epochs: 100,
+            learning_rate: 0.05,
+            batch_size: 2,
+            ..Training::default()
+        };
+        train(&mut network, &samples, &training, &mut rng);
+        let after: f32 = samples.iter().map(|sample| loss(&network, sample)).sum();
+        assert!(after < before / 4.0, "{before} -> {after}");
+    }
+}
@@ -0,0 +1,349 @@
+// Full screen terminal UI for playing connect 4
+
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::Duration;
+
+use connect4::board::{Board, Cell, MoveResult, HEIGHT, WIDTH};
+use connect4::engine::Engine;
+use connect4::game::Game;
+use connect4::hint::{self, Outcome};
+use connect4::misere::Objective;
+use connect4::play::{self, Policy};
+use connect4::setup::Setup;
+use connect4::solver::Solver;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, PrintStyledContent, StyledContent, Stylize};
+use crossterm::{cursor, execute, queue, terminal};
+use rand::thread_rng;
+
+// Time a dropped piece spends on each row while falling.
+const DROP_DELAY: Duration = Duration::from_millis(40);
+
+// Screen rows of the different parts of the UI.
+const CURSOR_ROW: u16 = 2;
+const BOARD_ROW: u16 = 3;
+const OUTCOME_ROW: u16 = BOARD_ROW + HEIGHT as u16 + 2;
+const STATUS_ROW: u16 = OUTCOME_ROW + 2;
+
+const HELP: &str = "←/→ select  ↓/enter drop  u undo  h hint  n new game  q quit";
+
+// Puts the terminal into raw mode on the alternate screen and restores it on drop.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter(out: &mut Stdout) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Tui {
+    out: Stdout,
+    game: Game,
+
+    // Engine used for hints.
+    engine: Box<dyn Engine>,
+
+    // Engine playing yellow when playing against the computer.
+    opponent: Option<Box<dyn Engine>>,
+
+    // Proven outcome of each column, shown after asking for a hint.
+    outcomes: Option<[Option<Outcome>; WIDTH]>,
+
+    // Column the next piece will be dropped into.
+    cursor: usize,
+
+    status: String,
+    evaluation: String,
+
+    // Whether completing four wins or, in misère games, loses.
+    objective: Objective,
+
+    // Where each new game starts.
+    setup: Setup,
+}
+
+pub fn run(
+    engine: Box<dyn Engine>,
+    opponent: Option<Box<dyn Engine>>,
+    objective: Objective,
+    setup: Setup,
+) -> io::Result<()> {
+    let mut out = io::stdout();
+    let _raw = RawTerminal::enter(&mut out)?;
+    let mut tui = Tui {
+        out,
+        game: Game::new(),
+        engine,
+        opponent,
+        outcomes: None,
+        cursor: WIDTH / 2,
+        status: String::new(),
+        evaluation: String::new(),
+        objective,
+        setup,
+    };
+    tui.new_game()?;
+    tui.event_loop()
+}
+
+impl Tui {
+    fn event_loop(&mut self) -> io::Result<()> {
+        loop {
+            self.draw(None)?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+                KeyCode::Right => self.cursor = (self.cursor + 1).min(WIDTH - 1),
+                KeyCode::Char(c) if c.to_digit(10).is_some_and(|d| (d as usize) < WIDTH) => {
+                    self.cursor = c.to_digit(10).unwrap() as usize;
+                }
+                KeyCode::Down | KeyCode::Enter | KeyCode::Char(' ') => self.drop_piece()?,
+                KeyCode::Char('u') => self.undo(),
+                KeyCode::Char('h') => self.hint(),
+                KeyCode::Char('n') => self.new_game()?,
+                _ => {}
+            }
+        }
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        self.game = Game::from_setup(self.setup);
+        self.cursor = WIDTH / 2;
+        self.update_status();
+        self.computer_moves()
+    }
+
+    fn drop_piece(&mut self) -> io::Result<()> {
+        let col = self.cursor;
+        if self.game.is_over() {
+            self.status = "The game is over. Press n for a new game.".to_string();
+            return Ok(());
+        }
+        if !self.game.get_board().is_valid_move(col) {
+            self.status = format!("Column {col} is full.");
+            return Ok(());
+        }
+        self.animate_move(col)?;
+        self.computer_moves()
+    }
+
+    // Plays the computer's moves until it is the player's turn, which takes
+    // more than one move while the computer has a handicap.
+    fn computer_moves(&mut self) -> io::Result<()> {
+        if !self.is_computer_turn() {
+            return Ok(());
+        }
+        while self.is_computer_turn() {
+            let board = *self.game.get_board();
+            self.status = format!(
+                "{} ({}) is thinking...",
+                player_name(board.get_current_player()),
+                self.opponent.as_ref().unwrap().name()
+            );
+            self.draw(None)?;
+            match self.opponent.as_mut().unwrap().choose_move(&board) {
+                Some(col) => self.animate_move(col)?,
+                None => break,
+            }
+        }
+        if !self.game.is_over() {
+            let board = *self.game.get_board();
+            self.opponent.as_mut().unwrap().ponder(&board);
+        }
+        Ok(())
+    }
+
+    fn is_computer_turn(&self) -> bool {
+        self.opponent.is_some()
+            && !self.game.is_over()
+            && self.game.get_board().get_current_player() == Cell::O
+    }
+
+    // Makes the move after showing the piece falling into place.
+    fn animate_move(&mut self, col: usize) -> io::Result<()> {
+        let board = *self.game.get_board();
+        let cell = board.get_current_player();
+        for row in (board.get_height(col) + 1..HEIGHT).rev() {
+            self.draw(Some((col, row, cell)))?;
+            thread::sleep(DROP_DELAY);
+        }
+        self.game.make_move(col);
+        self.update_status();
+        Ok(())
+    }
+
+    fn undo(&mut self) {
+        match self.game.undo() {
+            Some(mut col) => {
+                // Take back the computer's reply along with the player's move.
+                if self.is_computer_turn() {
+                    col = self.game.undo().unwrap_or(col);
+                }
+                self.cursor = col;
+                self.update_status();
+            }
+            None => self.status = "Nothing to undo.".to_string(),
+        }
+    }
+
+    fn hint(&mut self) {
+        if self.game.is_over() {
+            return;
+        }
+        let hint = hint::hint_for(self.engine.as_mut(), self.game.get_board(), self.objective);
+        if let Some(col) = hint.best_move {
+            self.cursor = col;
+            self.status = format!("Hint ({}): play column {col}.", self.engine.name());
+        }
+        self.outcomes = Some(hint.outcomes);
+    }
+
+    fn update_status(&mut self) {
+        let board = self.game.get_board();
+        self.evaluation.clear();
+        self.outcomes = None;
+        self.status = match self.objective.interpret(self.game.get_result()) {
+            MoveResult::WinX => format!("{} wins!", player_name(Cell::X)),
+            MoveResult::WinO => format!("{} wins!", player_name(Cell::O)),
+            MoveResult::Draw => "It's a draw!".to_string(),
+            _ => {
+                self.evaluation = evaluate(board, self.objective);
+                format!("{} to move.", player_name(board.get_current_player()))
+            }
+        };
+    }
+
+    // Draws the whole screen, with an optional piece falling through an empty cell.
+    fn draw(&mut self, falling: Option<(usize, usize, Cell)>) -> io::Result<()> {
+        let board = self.game.get_board();
+        let title = match self.objective {
+            Objective::Standard => "Connect 4",
+            Objective::Misere => "Connect 4 (misère: four in a row loses)",
+        };
+        let out = &mut self.out;
+        queue!(
+            out,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0),
+            Print(title.bold()),
+            cursor::MoveTo(1 + 3 * self.cursor as u16, CURSOR_ROW),
+            PrintStyledContent(disc(board.get_current_player()))
+        )?;
+
+        for row in (0..HEIGHT).rev() {
+            queue!(
+                out,
+                cursor::MoveTo(0, BOARD_ROW + (HEIGHT - 1 - row) as u16)
+            )?;
+            for col in 0..WIDTH {
+                let cell = match falling {
+                    Some((c, r, cell)) if c == col && r == row => cell,
+                    _ => board.get(col, row),
+                };
+                queue!(out, Print("|"), PrintStyledContent(disc(cell)), Print(" "))?;
+            }
+            queue!(out, Print("|"))?;
+        }
+
+        let labels: String = (0..WIDTH).map(|col| format!("|{col} ")).collect();
+        queue!(
+            out,
+            cursor::MoveTo(0, BOARD_ROW + HEIGHT as u16),
+            Print(format!("{}+", "+--".repeat(WIDTH))),
+            cursor::MoveTo(0, BOARD_ROW + HEIGHT as u16 + 1),
+            Print(format!("{labels}|")),
+            cursor::MoveTo(0, OUTCOME_ROW)
+        )?;
+        if let Some(outcomes) = &self.outcomes {
+            for outcome in outcomes {
+                let label = match outcome {
+                    Some(Outcome::Win) => "W".green(),
+                    Some(Outcome::Draw) => "D".blue(),
+                    Some(Outcome::Loss) => "L".red(),
+                    None => "?".dim(),
+                };
+                queue!(out, Print(" "), PrintStyledContent(label), Print(" "))?;
+            }
+            queue!(out, Print(" W/D/L: winning/drawing/losing".dim()))?;
+        }
+        queue!(
+            out,
+            cursor::MoveTo(0, STATUS_ROW),
+            Print(self.status.as_str().reverse()),
+            Print("  "),
+            Print(&self.evaluation),
+            cursor::MoveTo(0, STATUS_ROW + 1),
+            Print(HELP.dim())
+        )?;
+        out.flush()
+    }
+}
+
+fn player_name(cell: Cell) -> &'static str {
+    match cell {
+        Cell::X => "Red",
+        Cell::O => "Yellow",
+        Cell::Empty => "Nobody",
+    }
+}
+
+fn disc(cell: Cell) -> StyledContent<char> {
+    match cell {
+        Cell::X => '●'.red(),
+        Cell::O => '●'.yellow(),
+        Cell::Empty => ' '.stylize(),
+    }
+}
+
+// Games played out to estimate the score of a position too early to solve.
+const EVALUATION_GAMES: usize = 200;
+
+// Describes the position for the player to move, solving it exactly when it is
+// close enough to the end of the game.
+fn evaluate(board: &Board, objective: Objective) -> String {
+    if WIDTH * HEIGHT - board.get_move_count() <= hint::SOLVE_LIMIT {
+        let score = Solver::with_objective(objective).solve(board);
+        let player = board.get_current_player();
+        match score {
+            0 => "Solver: draw".to_string(),
+            s if s > 0 => format!("Solver: {} wins ({s:+})", player_name(player)),
+            s => format!("Solver: {} wins ({s:+})", player_name(player.switch())),
+        }
+    } else {
+        let stats = play::playouts(
+            board,
+            board.get_current_player(),
+            EVALUATION_GAMES,
+            Policy::Uniform,
+            objective,
+            &mut thread_rng(),
+        );
+        let (low, high) = stats.confidence_interval(play::Z_95);
+        format!(
+            "Monte Carlo: {:.0}% for {} (95% between {:.0}% and {:.0}%)",
+            100.0 * stats.score(),
+            player_name(board.get_current_player()),
+            100.0 * low,
+            100.0 * high
+        )
+    }
+}
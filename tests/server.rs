@@ -0,0 +1,248 @@
+// Plays games through a server running on a local port
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use connect4::server::Server;
+use serde_json::{json, Value};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+type Spectator = WebSocket<MaybeTlsStream<TcpStream>>;
+
+fn start_server() -> SocketAddr {
+    let server = Arc::new(Server::bind("127.0.0.1:0").unwrap());
+    let addr = server.local_addr();
+    thread::spawn(move || server.run());
+    addr
+}
+
+// Sends a request and returns the status code and body of the response.
+fn request(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, String) {
+    let body = body.map_or(String::new(), |body| body.to_string());
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+fn request_json(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let (status, body) = request(addr, method, path, body);
+    (status, serde_json::from_str(&body).unwrap())
+}
+
+fn watch(addr: SocketAddr, id: u64) -> Spectator {
+    let (spectator, _) = tungstenite::connect(format!("ws://{addr}/games/{id}/stream")).unwrap();
+    spectator
+}
+
+fn next_message(spectator: &mut Spectator) -> Value {
+    loop {
+        if let Message::Text(text) = spectator.read().unwrap() {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+#[test]
+fn play_a_game() {
+    let addr = start_server();
+    let (status, game) = request_json(addr, "POST", "/games", Some(json!({"yellow": "solver"})));
+    assert_eq!(status, 200);
+    assert_eq!(game["id"], 1);
+    assert_eq!(game["red"], "human");
+    assert_eq!(game["to_move"], "red");
+    assert_eq!(game["valid_moves"], json!([0, 1, 2, 3, 4, 5, 6]));
+
+    for col in [0, 1, 0, 1, 0, 1] {
+        let (status, _) =
+            request_json(addr, "POST", "/games/1/moves", Some(json!({"column": col})));
+        assert_eq!(status, 200);
+    }
+    let (status, game) = request_json(addr, "GET", "/games/1", None);
+    assert_eq!(status, 200);
+    assert_eq!(game["moves"], "010101");
+    assert_eq!(game["rows"][2], ".......");
+    assert_eq!(game["rows"][5], "XO.....");
+
+    let (status, analysis) = request_json(addr, "GET", "/games/1/analysis", None);
+    assert_eq!(status, 200);
+    assert_eq!(analysis["engine"], "montecarlo");
+    assert_eq!(analysis["best_move"], 0);
+    assert_eq!(analysis["outcomes"][0], "winning");
+    assert_eq!(analysis["outcomes"][2], "losing");
+
+    let (status, game) = request_json(addr, "POST", "/games/1/moves", Some(json!({"column": 0})));
+    assert_eq!(status, 200);
+    assert_eq!(game["result"], "red");
+    assert_eq!(game["to_move"], Value::Null);
+    assert_eq!(game["valid_moves"], json!([]));
+
+    let (status, error) = request_json(addr, "POST", "/games/1/moves", Some(json!({"column": 2})));
+    assert_eq!(status, 409);
+    assert_eq!(error["error"], "the game is over");
+
+    let (status, record) = request(addr, "GET", "/games/1/record", None);
+    assert_eq!(status, 200);
+    assert_eq!(
+        record,
+        "[Red \"human\"]\n[Yellow \"solver\"]\n[Result \"1-0\"]\n0101010\n"
+    );
+}
+
+#[test]
+fn engine_moves_and_listing() {
+    let addr = start_server();
+    request_json(addr, "POST", "/games", None);
+    request_json(addr, "POST", "/games", Some(json!({"red": "alice"})));
+
+    let (status, game) = request_json(
+        addr,
+        "POST",
+        "/games/2/engine-move",
+        Some(json!({"engine": "casual"})),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(game["moves"].as_str().unwrap().len(), 1);
+    assert_eq!(game["to_move"], "yellow");
+    assert!(game["engine_move"].as_u64().unwrap() < 7);
+
+    let (status, games) = request_json(addr, "GET", "/games", None);
+    assert_eq!(status, 200);
+    assert_eq!(games.as_array().unwrap().len(), 2);
+    assert_eq!(games[0]["moves"], "");
+    assert_eq!(games[1]["red"], "alice");
+    assert_eq!(games[1]["moves"], game["moves"]);
+}
+
+#[test]
+fn errors() {
+    let addr = start_server();
+    assert_eq!(request(addr, "GET", "/games/1", None).0, 404);
+    assert_eq!(request(addr, "GET", "/nowhere", None).0, 404);
+    request_json(addr, "POST", "/games", None);
+
+    for _ in 0..6 {
+        request_json(addr, "POST", "/games/1/moves", Some(json!({"column": 3})));
+    }
+    let (status, error) = request_json(addr, "POST", "/games/1/moves", Some(json!({"column": 3})));
+    assert_eq!(status, 400);
+    assert_eq!(error["error"], "column 3 is not a valid move");
+
+    let (status, _) = request_json(addr, "POST", "/games/1/moves", Some(json!({"col": 3})));
+    assert_eq!(status, 400);
+    let (status, _) = request_json(addr, "GET", "/games/1/analysis?engine=oracle", None);
+    assert_eq!(status, 400);
+    let (status, error) = request(addr, "POST", "/games", Some(json!({"red": 5})));
+    assert_eq!(status, 400);
+    assert!(error.contains("red must be a string"));
+}
+
+#[test]
+fn spectators_see_moves() {
+    let addr = start_server();
+    request_json(addr, "POST", "/games", None);
+    let mut spectators = [watch(addr, 1), watch(addr, 1)];
+    for spectator in &mut spectators {
+        let message = next_message(spectator);
+        assert_eq!(message["type"], "state");
+        assert_eq!(message["moves"], "");
+    }
+
+    request_json(addr, "POST", "/games/1/moves", Some(json!({"column": 3})));
+    let (_, game) = request_json(addr, "POST", "/games/1/engine-move", None);
+    for spectator in &mut spectators {
+        let message = next_message(spectator);
+        assert_eq!(message["type"], "move");
+        assert_eq!(message["column"], 3);
+        assert_eq!(message["rows"][5], "...X...");
+
+        let info = next_message(spectator);
+        assert_eq!(info["type"], "info");
+        assert_eq!(info["engine"], "montecarlo");
+        assert_eq!(info["depth"], 1);
+        assert_eq!(info["pv"][0], game["engine_move"]);
+
+        let message = next_message(spectator);
+        assert_eq!(message["type"], "move");
+        assert_eq!(message["column"], game["engine_move"]);
+        assert_eq!(message["moves"], game["moves"]);
+    }
+
+    assert_eq!(request(addr, "GET", "/games/2/stream", None).0, 404);
+    assert_eq!(request(addr, "GET", "/games/1/stream", None).0, 400);
+}
+
+#[test]
+fn spectators_see_engine_progress() {
+    let addr = start_server();
+    request_json(addr, "POST", "/games", None);
+    let mut spectator = watch(addr, 1);
+    next_message(&mut spectator);
+
+    let body = json!({"engine": "heuristic"});
+    let (_, game) = request_json(addr, "POST", "/games/1/engine-move", Some(body));
+    // Each depth of the search is reported as it finishes.
+    for depth in 1..=4 {
+        let info = next_message(&mut spectator);
+        assert_eq!(info["type"], "info");
+        assert_eq!(info["engine"], "heuristic");
+        assert_eq!(info["depth"], depth);
+    }
+    let message = next_message(&mut spectator);
+    assert_eq!(message["type"], "move");
+    assert_eq!(message["column"], game["engine_move"]);
+}
+
+#[test]
+fn stream_self_play() {
+    let addr = start_server();
+    request_json(addr, "POST", "/games", None);
+    let mut spectator = watch(addr, 1);
+    assert_eq!(next_message(&mut spectator)["type"], "state");
+
+    let body = json!({"red": "montecarlo", "yellow": "beginner", "delay_ms": 20});
+    let (status, game) = request_json(addr, "POST", "/games/1/self-play", Some(body));
+    assert_eq!(status, 200);
+    assert_eq!(game["self_play"], true);
+    assert_eq!(game["yellow"], "beginner");
+    let (status, _) = request_json(addr, "POST", "/games/1/moves", Some(json!({"column": 0})));
+    assert_eq!(status, 409);
+
+    // Each move is preceded by the analysis of the engine that played it.
+    let mut moves = String::new();
+    loop {
+        let info = next_message(&mut spectator);
+        assert_eq!(info["type"], "info");
+        let engine = if moves.len().is_multiple_of(2) {
+            "montecarlo"
+        } else {
+            "beginner"
+        };
+        assert_eq!(info["engine"], engine);
+
+        let message = next_message(&mut spectator);
+        assert_eq!(message["type"], "move");
+        assert_eq!(message["column"], info["pv"][0]);
+        moves += &info["pv"][0].to_string();
+        assert_eq!(message["moves"], moves.as_str());
+        if !message["result"].is_null() {
+            assert_eq!(message["self_play"], false);
+            break;
+        }
+    }
+    let (_, game) = request_json(addr, "GET", "/games/1", None);
+    assert_eq!(game["moves"], moves.as_str());
+}